@@ -1,17 +1,52 @@
 use core::ffi::c_void;
 use core::fmt::Display;
+use core::marker::PhantomData;
 
 use alloc::string::String;
+use crate::io::DriverCapabilities;
 use crate::*;
 
 /// Input arguments for a boot driver.
 pub struct BootDriverArgs<'a> {
-  /// A byte vector containing the file to boot.
-  pub img: Vec<u8>,
+  /// A byte slice containing the file to boot.
+  pub img: &'a [u8],
   /// Command line options to use in booting.
   pub cmdline: &'a str,
 }
 
+impl<'a> BootDriverArgs<'a> {
+  /// Converts these arguments into their ABI-stable representation.
+  pub fn to_raw(&self) -> RawBootDriverArgs<'a> {
+    RawBootDriverArgs {
+      img_ptr: self.img.as_ptr(),
+      img_len: self.img.len(),
+      cmdline_ptr: self.cmdline.as_ptr(),
+      cmdline_len: self.cmdline.len(),
+      _marker: PhantomData
+    }
+  }
+
+  /// Recovers arguments from their ABI-stable representation.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(BootDriverArgs)` on success.
+  /// - `Err(Status::INVALID_PARAMETER)` if the command line is not valid
+  ///   UTF-8.
+  /// 
+  /// # Safety
+  /// The pointers in `raw` must be valid for the lifetime `'a`.
+  pub unsafe fn from_raw(raw: &RawBootDriverArgs<'a>) -> Result<BootDriverArgs<'a>, Status> {
+    let cmdline = core::slice::from_raw_parts(raw.cmdline_ptr, raw.cmdline_len);
+    Ok(
+      BootDriverArgs {
+        img: core::slice::from_raw_parts(raw.img_ptr, raw.img_len),
+        cmdline: core::str::from_utf8(cmdline).map_err(|_| Status::INVALID_PARAMETER)?
+      }
+    )
+  }
+}
+
 impl Display for BootDriverArgs<'_> {
   fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
     write!(
@@ -22,7 +57,52 @@ impl Display for BootDriverArgs<'_> {
   }
 }
 
+#[repr(C)]
+/// The ABI-stable representation of [`BootDriverArgs`], as passed through a
+/// [`DriverIO`].
+pub struct RawBootDriverArgs<'a> {
+  /// A pointer to the file to boot.
+  pub img_ptr: *const u8,
+  /// The length of the file to boot.
+  pub img_len: usize,
+  /// A pointer to the UTF-8 command line.
+  pub cmdline_ptr: *const u8,
+  /// The length of the command line in bytes.
+  pub cmdline_len: usize,
+  _marker: PhantomData<&'a [u8]>
+}
+
 impl BootDriver {
+  /// Creates a boot driver from an EFI image in memory, rather than on disk.
+  /// 
+  /// This allows drivers to be embedded in the loader (see
+  /// [`crate::include_driver`]) or fetched from elsewhere, e.g. read from
+  /// another volume through a file system driver.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `name` (`&str`) - The name of the driver (e.g. `linux`).
+  /// - `buffer` - The contents of the driver's EFI file.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(BootDriver)` on success.
+  /// - `Err(UdiveError::Load)` with `INVALID_PARAMETER` if `name` cannot be
+  ///   represented in UCS-2.
+  pub fn from_buffer(name: &str, buffer: impl Into<Cow<'static, [u8]>>) -> Result<BootDriver, UdiveError> {
+    Ok(BootDriver(Driver::from_buffer(name, DriverType::BOOT, buffer.into())?))
+  }
+
+  /// Attaches a detached signature to a boot driver created from a buffer,
+  /// checked against the [`crate::verify::signature_policy`] when it is
+  /// loaded.
+  /// 
+  /// Drivers on disk are signed by a `.sig` file alongside them instead.
+  pub fn with_signature(mut self, signature: impl Into<Cow<'static, [u8]>>) -> BootDriver {
+    self.0.signature = Some(signature.into());
+    self
+  }
+
   /// Prints the name of this boot driver.
   /// 
   /// Usually, this will just be the name of the OS to boot (e.g. Linux) or
@@ -31,16 +111,69 @@ impl BootDriver {
     self.0.name()
   }
 
+  /// Returns the manifest accompanying this boot driver, if it has one.
+  pub fn manifest(&self) -> Option<&DriverManifest> {
+    self.0.manifest.as_ref()
+  }
+
+  /// Returns the type of this driver, which is always [`DriverType::BOOT`].
+  pub fn driver_type(&self) -> DriverType {
+    DriverType::BOOT
+  }
+
+  /// Returns the path of this boot driver's EFI file, relative to the root of
+  /// the volume it resides on (e.g. `\EFI\wakatiwai\drivers\boot\name.efi`).
+  /// This is empty for drivers created from a buffer.
+  pub fn path(&self) -> CString16 {
+    self.0.path().unwrap_or_default()
+  }
+
+  /// Returns whether this boot driver is currently loaded.
+  pub fn is_loaded(&self) -> bool {
+    self.0.is_loaded()
+  }
+
   /// Loads this boot driver.
-  pub fn load(&mut self) -> Status {
+  pub fn load(&mut self) -> Result<(), UdiveError> {
     self.0.load()
   }
 
   /// Unloads this boot driver.
-  pub fn unload(&mut self) -> Status {
+  pub fn unload(&mut self) -> Result<(), UdiveError> {
     self.0.unload()
   }
 
+  /// Unloads this boot driver if it is loaded, and loads it again, so that it
+  /// may be invoked after it has exited.
+  pub fn reload(&mut self) -> Result<(), UdiveError> {
+    self.0.reload()
+  }
+
+  /// Returns the lifecycle state of this boot driver.
+  pub fn state(&self) -> DriverState {
+    self.0.state()
+  }
+
+  /// Returns the records logged by this boot driver during its last
+  /// invocation.
+  pub fn log(&self) -> &DriverLog {
+    &self.0.log
+  }
+
+  /// Sets the timeout applied when invoking this boot driver, in seconds,
+  /// overriding [`crate::watchdog::invocation_timeout`].
+  /// 
+  /// A timeout of `Some(0)` disables the timeout for this driver, and `None`
+  /// restores the default.
+  pub fn set_timeout(&mut self, seconds: Option<usize>) {
+    self.0.timeout = seconds;
+  }
+
+  /// Returns the timeout set for this boot driver, if any.
+  pub fn timeout(&self) -> Option<usize> {
+    self.0.timeout
+  }
+
   /// Invokes this boot driver.
   /// 
   /// # Arguments
@@ -50,23 +183,29 @@ impl BootDriver {
   /// 
   /// # Returns
   /// 
-  /// - `None` on a successful invokation and execution of the boot driver.
-  /// - `Some(Ok(Status))` on a successful invokation but failed execution of
-  ///   the boot driver.
-  /// - `Some(Err(Status))` on a failed invokation of the boot driver.
-  pub fn invoke(&mut self, args: &mut BootDriverArgs) -> Option<Result<Status, Status>> {
-    let mut dio = DriverIO {
-      inptr:  args as *mut BootDriverArgs as *mut c_void,
-      outptr: core::ptr::null_mut()
-    };
-
-    let invoke_status = self.0.invoke(&mut dio, BOOT_DRIVER_IO_MEMTYPE);
+  /// - `Ok(())` on a successful invokation and execution of the boot driver.
+  /// - `Err(UdiveError::Driver)` on a successful invokation but failed
+  ///   execution of the boot driver, carrying the status it returned.
+  /// - `Err(UdiveError::AbiMismatch)` if the driver was built against a
+  ///   different ABI.
+  /// - `Err(UdiveError::Load)` if the driver is not loaded, or has already
+  ///   been invoked since it was loaded ([`DRIVER_NOT_REENTRANT`]).
+  /// - `Err(UdiveError::IoAllocation)` if the arguments could not be passed
+  ///   to the driver.
+  /// - `Err(UdiveError::Firmware)` if the driver has a timeout, but the
+  ///   watchdog could not be armed (see [`crate::watchdog`]).
+  /// 
+  /// Records logged by the driver are available from [`BootDriver::log`]
+  /// afterwards.
+  pub fn invoke(&mut self, args: &mut BootDriverArgs) -> Result<(), UdiveError> {
+    let mut raw = args.to_raw();
+    let mut dio = DriverIO::new(
+      DriverCapabilities::BOOT_ARGS,
+      &mut raw as *mut RawBootDriverArgs as *mut c_void,
+      size_of::<RawBootDriverArgs>()
+    );
 
-    if invoke_status.is_ok_and(|t| t.is_success()) {
-      return None;
-    }
-    
-    return Some(invoke_status);
+    self.0.invoke(&mut dio, BOOT_DRIVER_IO_MEMTYPE)
   }
 }
 
@@ -76,16 +215,22 @@ impl BootDriver {
 /// An entry point `_entry` is defined and will recapture the
 /// [`BootDriverArgs`] that the driver was invoked with. It will then start a
 /// `main` method (the entry point of the driver, for the purposes of the
-/// programmer) with these arguments.
+/// programmer) with these arguments. If `wakatiwai` was built against a
+/// different driver ABI, the driver exits with
+/// [`uefi_raw::Status::INCOMPATIBLE_VERSION`] without calling `main`.
 /// 
 /// This driver may exit if booting fails, in which case the relevant status
 /// code will be returned to the caller, or a SUCCESS may be reported if
 /// control is returned to the boot driver.
+/// 
+/// Records logged through the `log` macros (re-exported as
+/// `wakatiwai_udive::log`) are passed to `wakatiwai`, see
+/// [`crate::logging::DriverLogger`].
 macro_rules! boot_prelude {
   () => {
     use uefi::Status;
 
-    use wakatiwai_udive::boot::BootDriverArgs;
+    use wakatiwai_udive::boot::{BootDriverArgs, RawBootDriverArgs};
 
     #[uefi::entry]
     #[allow(unsafe_op_in_unsafe_fn)]
@@ -98,10 +243,16 @@ macro_rules! boot_prelude {
         return find_io_mem_status;
       }
       let dio = wakatiwai_udive::io::DriverIO::allocated_driver_io().unwrap();
+      let _logger = wakatiwai_udive::logging::attach_driver_logger(dio);
+
+      let args = match dio.args::<RawBootDriverArgs>().and_then(|raw| BootDriverArgs::from_raw(raw)) {
+        Ok(ok) => ok,
+        Err(err) => {
+          return err;
+        }
+      };
 
-      let main_status = main(unsafe {
-        core::mem::transmute::<*mut core::ffi::c_void, *mut BootDriverArgs>(dio.inptr).as_ref().unwrap()
-      });
+      let main_status = main(&args);
 
       if main_status.is_none() {
         return Status::SUCCESS;
@@ -0,0 +1,336 @@
+use core::ffi::c_void;
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use uefi::boot::{AllocateType, EventType, MemoryType, OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol, Tpl, PAGE_SIZE};
+use uefi::proto::media::disk::{DiskIo2, DiskIo2Token};
+use uefi::proto::unsafe_protocol;
+use uefi::{Event, Handle, Status};
+use uefi_raw::protocol::block::BlockIoMedia;
+
+use crate::UdiveError;
+
+use super::{DiskReader, PageBuffer, DISK_OUT_OF_RANGE};
+
+/// The raw `EFI_BLOCK_IO2_PROTOCOL` function table.
+#[repr(C)]
+#[allow(dead_code)]
+struct BlockIo2Protocol {
+  media: *const BlockIoMedia,
+  reset: unsafe extern "efiapi" fn(this: *mut BlockIo2Protocol, extended_verification: bool) -> Status,
+  read_blocks_ex: unsafe extern "efiapi" fn(
+    this: *const BlockIo2Protocol,
+    media_id: u32,
+    lba: u64,
+    token: *mut DiskIo2Token,
+    buffer_size: usize,
+    buffer: *mut c_void
+  ) -> Status,
+  write_blocks_ex: unsafe extern "efiapi" fn(
+    this: *mut BlockIo2Protocol,
+    media_id: u32,
+    lba: u64,
+    token: *mut DiskIo2Token,
+    buffer_size: usize,
+    buffer: *const c_void
+  ) -> Status,
+  flush_blocks_ex: unsafe extern "efiapi" fn(this: *mut BlockIo2Protocol, token: *mut DiskIo2Token) -> Status
+}
+
+/// The block IO 2 protocol, which is not provided by the `uefi` crate.
+/// 
+/// Its token has the same layout as that of `DiskIo2`, so [`DiskIo2Token`]
+/// is used for both.
+#[repr(transparent)]
+#[unsafe_protocol("a77b2472-e282-4e9f-a245-c2c0e27bbcc1")]
+pub(crate) struct BlockIO2(BlockIo2Protocol);
+
+/// The protocol a [`DiskReader`] performs asynchronous reads through.
+pub(crate) enum AsyncBackend {
+  /// Byte-granular reads through `DiskIo2`.
+  DiskIo2(ScopedProtocol<DiskIo2>),
+  /// Block-granular reads through `BlockIO2`, reading whole blocks around
+  /// the requested range.
+  BlockIo2(ScopedProtocol<BlockIO2>)
+}
+
+impl AsyncBackend {
+  /// Opens an asynchronous protocol on a handle, preferring `DiskIo2`.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Some(AsyncBackend)` on success.
+  /// - `None` if neither `DiskIo2` nor `BlockIO2` is available.
+  pub(crate) fn open(handle: Handle) -> Option<AsyncBackend> {
+    let params = || OpenProtocolParams {
+      handle,
+      agent: uefi::boot::image_handle(),
+      controller: None
+    };
+
+    if let Ok(ok) = unsafe { uefi::boot::open_protocol::<DiskIo2>(params(), OpenProtocolAttributes::GetProtocol) } {
+      return Some(AsyncBackend::DiskIo2(ok));
+    }
+
+    match unsafe { uefi::boot::open_protocol::<BlockIO2>(params(), OpenProtocolAttributes::GetProtocol) } {
+      Ok(ok) => Some(AsyncBackend::BlockIo2(ok)),
+      Err(_) => None
+    }
+  }
+}
+
+/// A read from a [`DiskReader`] which may still be in progress.
+/// 
+/// Created by [`DiskReader::read_bytes_async`]. The read is driven by the
+/// firmware; [`PendingRead::poll`] checks whether it has finished without
+/// blocking, and [`PendingRead::wait`] or [`wait_all`] block until it has.
+/// 
+/// Dropping a [`PendingRead`] which is still in progress blocks until the
+/// firmware has finished writing to its buffer.
+pub struct PendingRead<'a> {
+  /// The token passed to the firmware, boxed so it does not move while the
+  /// read is in progress. `None` if the read completed synchronously.
+  token: Option<Box<DiskIo2Token>>,
+  /// The buffer being read into.
+  buffer: Option<PageBuffer>,
+  /// The offset of the requested bytes within the buffer.
+  skip: usize,
+  /// The number of bytes requested.
+  len: usize,
+  /// The result of the read, once it has completed.
+  status: Option<Status>,
+  /// Ties the read to the protocols of the disk reader it was issued on.
+  _marker: PhantomData<&'a DiskReader>
+}
+
+impl PendingRead<'_> {
+  /// Creates a read which has already completed.
+  fn completed(buffer: PageBuffer, status: Status) -> PendingRead<'static> {
+    let len = buffer.len();
+    PendingRead {
+      token: None,
+      buffer: Some(buffer),
+      skip: 0,
+      len,
+      status: Some(status),
+      _marker: PhantomData
+    }
+  }
+
+  /// Returns the event signalled when the read completes, if still in
+  /// progress.
+  fn event(&self) -> Option<Event> {
+    match (&self.token, self.status) {
+      (Some(token), None) => token.event.as_ref().map(|event| unsafe { event.unsafe_clone() }),
+      _ => None
+    }
+  }
+
+  /// Records the result of the read once its event has been signalled.
+  fn complete(&mut self) {
+    if let Some(token) = &mut self.token {
+      self.status = Some(token.transaction_status);
+      if let Some(event) = token.event.take() {
+        let _ = uefi::boot::close_event(event);
+      }
+    }
+  }
+
+  /// Returns whether the read has completed.
+  pub fn is_complete(&self) -> bool {
+    self.status.is_some()
+  }
+
+  /// Checks whether the read has completed, without blocking.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Some(Status)` if the read has completed, containing its result.
+  /// - `None` if the read is still in progress.
+  pub fn poll(&mut self) -> Option<Status> {
+    if let Some(event) = self.event() && let Ok(true) = uefi::boot::check_event(event) {
+      self.complete();
+    }
+
+    self.status
+  }
+
+  /// Blocks until the read has completed.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(PageBuffer)` on success, containing the bytes read.
+  /// - `Err(UdiveError::Disk)` if the read failed.
+  pub fn wait(mut self) -> Result<PageBuffer, UdiveError> {
+    if let Some(event) = self.event() {
+      match uefi::boot::wait_for_event(&mut [event]) {
+        Ok(_) => self.complete(),
+        Err(err) => {
+          return Err(UdiveError::disk(err.status()));
+        }
+      }
+    }
+
+    match self.status {
+      Some(status) if status.is_success() => {}
+      Some(status) => {
+        return Err(UdiveError::disk(status));
+      }
+      None => {
+        return Err(UdiveError::disk(Status::NOT_READY));
+      }
+    }
+
+    let mut buffer = self.buffer.take().ok_or(UdiveError::disk(Status::NOT_READY))?;
+    buffer.window(self.skip, self.len);
+    Ok(buffer)
+  }
+}
+
+impl Drop for PendingRead<'_> {
+  fn drop(&mut self) {
+    // The firmware may still be writing to the buffer
+    if let Some(event) = self.event() {
+      let _ = uefi::boot::wait_for_event(&mut [event]);
+      self.complete();
+    }
+  }
+}
+
+/// Blocks until all of the given reads have completed.
+/// 
+/// # Arguments
+/// 
+/// - `reads` (`&mut [PendingRead]`) - The reads to wait on.
+/// 
+/// # Returns
+/// 
+/// - `Ok(())` if every read succeeded.
+/// - `Err(UdiveError::Disk)` carrying the `Status` of the first failed read,
+///   once all reads have completed.
+/// - `Err(UdiveError::Disk)` carrying the `Status` of the firmware if
+///   waiting fails. This is returned straight away, and reads which are
+///   still in progress are left to be waited on individually, or when they
+///   are dropped.
+pub fn wait_all(reads: &mut [PendingRead]) -> Result<(), UdiveError> {
+  loop {
+    let (mut events, indices): (Vec<Event>, Vec<usize>) = reads.iter()
+      .enumerate()
+      .filter_map(|(i, read)| read.event().map(|event| (event, i)))
+      .unzip();
+    if events.is_empty() {
+      break;
+    }
+
+    match uefi::boot::wait_for_event(&mut events) {
+      Ok(signalled) => reads[indices[signalled]].complete(),
+      Err(err) => {
+        return Err(UdiveError::disk(err.status()));
+      }
+    }
+  }
+
+  match reads.iter().filter_map(|read| read.status).find(|status| status.is_error()) {
+    Some(status) => Err(UdiveError::disk(status)),
+    None => Ok(())
+  }
+}
+
+impl DiskReader {
+  /// Starts reading a number of bytes from the disk at a specified offset,
+  /// returning without waiting for the read to complete.
+  /// 
+  /// The read is performed through `DiskIo2` or `BlockIO2`, so that reads of
+  /// several extents, possibly on several disks, may be in progress at once.
+  /// If neither protocol is available, the read is performed synchronously
+  /// and the returned [`PendingRead`] has already completed. The block cache
+  /// is bypassed.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `offset` (`u64`) - The disk offset to read from.
+  /// - `count` (`usize`) - The number of bytes to read.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(PendingRead)` if the read was started.
+  /// - `Err(UdiveError::Disk)` carrying [`DISK_OUT_OF_RANGE`] if the read
+  ///   extends past the partition.
+  /// - `Err(UdiveError::Disk)` carrying another `Status` if the read could
+  ///   not be started.
+  pub fn read_bytes_async(&self, offset: u64, count: usize) -> Result<PendingRead<'_>, UdiveError> {
+    self.start_read(offset, count).map_err(UdiveError::disk)
+  }
+
+  /// Starts an asynchronous read, see [`DiskReader::read_bytes_async`].
+  fn start_read(&self, offset: u64, count: usize) -> Result<PendingRead<'_>, Status> {
+    self.check_range(offset, count)?;
+    let abs_offset = self.abs_offset.checked_add(offset).ok_or(DISK_OUT_OF_RANGE)?;
+
+    let block_size = self.block_size as u64;
+    let (skip, size) = match &self.async_backend {
+      Some(AsyncBackend::BlockIo2(_)) if count != 0 && self.backend.media().io_align() as usize <= PAGE_SIZE => {
+        let skip = (abs_offset % block_size) as usize;
+        (skip, (skip + count).div_ceil(block_size as usize) * block_size as usize)
+      }
+      Some(AsyncBackend::DiskIo2(_)) if count != 0 => (0, count),
+      _ => {
+        // Read synchronously if the read cannot be issued asynchronously
+        let mut buffer = PageBuffer::allocate(AllocateType::AnyPages, MemoryType::LOADER_DATA, count)?;
+        self.read_disk(offset, &mut buffer)?;
+        return Ok(PendingRead::completed(buffer, Status::SUCCESS));
+      }
+    };
+
+    let buffer = PageBuffer::allocate(AllocateType::AnyPages, MemoryType::LOADER_DATA, size)?;
+    let event = unsafe {
+      uefi::boot::create_event(EventType::empty(), Tpl::CALLBACK, None, None).map_err(|err| err.status())?
+    };
+    let mut token = Box::new(
+      DiskIo2Token {
+        event: Some(unsafe { event.unsafe_clone() }),
+        transaction_status: Status::SUCCESS
+      }
+    );
+
+    let status = unsafe {
+      match &self.async_backend {
+        Some(AsyncBackend::DiskIo2(protocol)) => {
+          match protocol.read_disk_raw(self.media_id, abs_offset, Some(NonNull::from(&mut *token)), size, buffer.as_ptr()) {
+            Ok(_) => Status::SUCCESS,
+            Err(err) => err.status()
+          }
+        }
+        Some(AsyncBackend::BlockIo2(protocol)) => {
+          (protocol.0.read_blocks_ex)(
+            &protocol.0,
+            self.media_id,
+            abs_offset / block_size,
+            &mut *token,
+            size,
+            buffer.as_ptr().cast()
+          )
+        }
+        None => Status::UNSUPPORTED
+      }
+    };
+
+    if status.is_error() {
+      let _ = uefi::boot::close_event(event);
+      return Err(status);
+    }
+
+    Ok(
+      PendingRead {
+        token: Some(token),
+        buffer: Some(buffer),
+        skip,
+        len: count,
+        status: None,
+        _marker: PhantomData
+      }
+    )
+  }
+}
@@ -0,0 +1,187 @@
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+use uefi::boot::{OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol};
+use uefi::proto::media::block::{BlockIO, BlockIOMedia};
+use uefi::proto::media::disk::DiskIo;
+use uefi::{Handle, Status};
+
+use super::DISK_OUT_OF_RANGE;
+
+/// The largest number of blocks transferred through a bounce buffer at once.
+const BOUNCE_BLOCKS: u64 = 64;
+
+/// The protocol a [`super::DiskReader`] performs its IO through.
+pub(crate) enum DiskBackend {
+  /// Byte-granular access through `DiskIo`, with the `BlockIO` protocol it
+  /// is layered upon kept for its media and to flush writes.
+  DiskIo(ScopedProtocol<DiskIo>, ScopedProtocol<BlockIO>),
+  /// Block-granular access through `BlockIO`, with unaligned accesses going
+  /// through a bounce buffer.
+  BlockIo(ScopedProtocol<BlockIO>)
+}
+
+impl DiskBackend {
+  /// Returns the media the backend reads.
+  pub(crate) fn media(&self) -> &BlockIOMedia {
+    match self {
+      DiskBackend::DiskIo(_, block_io_protocol) => block_io_protocol.media(),
+      DiskBackend::BlockIo(protocol) => protocol.media()
+    }
+  }
+
+  /// Reads bytes from an absolute disk offset into a buffer.
+  pub(crate) fn read(&self, media_id: u32, offset: u64, buffer: &mut [u8]) -> Result<(), Status> {
+    match self {
+      DiskBackend::DiskIo(protocol, _) => {
+        protocol.read_disk(media_id, offset, buffer).map_err(|err| err.status())
+      }
+      DiskBackend::BlockIo(protocol) => {
+        let block_size = media_block_size(protocol)?;
+        let align = protocol.media().io_align() as usize;
+
+        // Read straight into the buffer if the firmware will accept it
+        if is_direct(offset, buffer.len(), buffer.as_ptr(), block_size, align) {
+          return protocol.read_blocks(media_id, offset / block_size, buffer).map_err(|err| err.status());
+        }
+
+        let mut bounce = BounceBuffer::new(block_size, align)?;
+        let mut done = 0;
+        while done < buffer.len() {
+          let position = offset + done as u64;
+          let lba = position / block_size;
+          let skip = (position % block_size) as usize;
+          let count = (buffer.len() - done).min(bounce.len() - skip);
+          let blocks = (skip + count).div_ceil(block_size as usize) * block_size as usize;
+
+          protocol.read_blocks(media_id, lba, &mut bounce.as_mut_slice()[..blocks]).map_err(|err| err.status())?;
+          buffer[done..done + count].copy_from_slice(&bounce.as_mut_slice()[skip..skip + count]);
+          done += count;
+        }
+
+        Ok(())
+      }
+    }
+  }
+
+  /// Writes bytes from a buffer to an absolute disk offset.
+  pub(crate) fn write(&mut self, media_id: u32, offset: u64, buffer: &[u8]) -> Result<(), Status> {
+    match self {
+      DiskBackend::DiskIo(protocol, _) => {
+        protocol.write_disk(media_id, offset, buffer).map_err(|err| err.status())
+      }
+      DiskBackend::BlockIo(protocol) => {
+        let block_size = media_block_size(protocol)?;
+        let align = protocol.media().io_align() as usize;
+
+        if is_direct(offset, buffer.len(), buffer.as_ptr(), block_size, align) {
+          return protocol.write_blocks(media_id, offset / block_size, buffer).map_err(|err| err.status());
+        }
+
+        // Partial blocks must be read, patched, and written back
+        let mut bounce = BounceBuffer::new(block_size, align)?;
+        let mut done = 0;
+        while done < buffer.len() {
+          let position = offset + done as u64;
+          let lba = position / block_size;
+          let skip = (position % block_size) as usize;
+          let count = (buffer.len() - done).min(bounce.len() - skip);
+          let blocks = (skip + count).div_ceil(block_size as usize) * block_size as usize;
+
+          let chunk = &mut bounce.as_mut_slice()[..blocks];
+          if skip != 0 || count != blocks {
+            protocol.read_blocks(media_id, lba, chunk).map_err(|err| err.status())?;
+          }
+          chunk[skip..skip + count].copy_from_slice(&buffer[done..done + count]);
+          protocol.write_blocks(media_id, lba, chunk).map_err(|err| err.status())?;
+          done += count;
+        }
+
+        Ok(())
+      }
+    }
+  }
+
+  /// Flushes any cached writes to the device.
+  pub(crate) fn flush(&mut self) -> Result<(), Status> {
+    match self {
+      DiskBackend::DiskIo(_, protocol) | DiskBackend::BlockIo(protocol) => {
+        protocol.flush_blocks().map_err(|err| err.status())
+      }
+    }
+  }
+}
+
+/// Opens the `BlockIO` protocol on a handle.
+pub(crate) fn open_block_io(handle: Handle) -> Result<ScopedProtocol<BlockIO>, Status> {
+  unsafe {
+    uefi::boot::open_protocol::<BlockIO>(
+      OpenProtocolParams {
+        handle,
+        agent: uefi::boot::image_handle(),
+        controller: None
+      },
+      OpenProtocolAttributes::GetProtocol
+    ).map_err(|err| err.status())
+  }
+}
+
+/// Returns the block size of the media behind a `BlockIO` protocol, which
+/// may have changed since the [`super::DiskReader`] was created.
+fn media_block_size(protocol: &BlockIO) -> Result<u64, Status> {
+  match protocol.media().block_size() {
+    0 => Err(Status::INVALID_PARAMETER),
+    block_size => Ok(block_size as u64)
+  }
+}
+
+/// Returns whether an access can be passed directly to `BlockIO`.
+fn is_direct(offset: u64, len: usize, ptr: *const u8, block_size: u64, align: usize) -> bool {
+  offset.is_multiple_of(block_size)
+    && (len as u64).is_multiple_of(block_size)
+    && (align <= 1 || (ptr as usize).is_multiple_of(align))
+}
+
+/// A buffer meeting the alignment requirements of a `BlockIO` device.
+struct BounceBuffer {
+  /// The start of the buffer.
+  ptr: NonNull<u8>,
+  /// The layout the buffer was allocated with.
+  layout: Layout
+}
+
+impl BounceBuffer {
+  /// Allocates a bounce buffer of [`BOUNCE_BLOCKS`] blocks.
+  fn new(block_size: u64, align: usize) -> Result<BounceBuffer, Status> {
+    let size = block_size.checked_mul(BOUNCE_BLOCKS)
+      .and_then(|size| usize::try_from(size).ok())
+      .ok_or(DISK_OUT_OF_RANGE)?;
+    if size == 0 {
+      return Err(Status::INVALID_PARAMETER);
+    }
+    let layout = Layout::from_size_align(size, align.max(1)).map_err(|_| Status::INVALID_PARAMETER)?;
+
+    match NonNull::new(unsafe { alloc::alloc::alloc_zeroed(layout) }) {
+      Some(ptr) => Ok(BounceBuffer { ptr, layout }),
+      None => Err(Status::OUT_OF_RESOURCES)
+    }
+  }
+
+  /// Returns the size of the buffer in bytes.
+  fn len(&self) -> usize {
+    self.layout.size()
+  }
+
+  /// Returns the buffer as a slice.
+  fn as_mut_slice(&mut self) -> &mut [u8] {
+    unsafe { core::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.layout.size()) }
+  }
+}
+
+impl Drop for BounceBuffer {
+  fn drop(&mut self) {
+    unsafe {
+      alloc::alloc::dealloc(self.ptr.as_ptr(), self.layout);
+    }
+  }
+}
@@ -0,0 +1,222 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use uefi::Status;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+/// Statistics collected by the block cache of a [`super::DiskReader`].
+pub struct CacheStats {
+  /// The number of blocks served from the cache.
+  pub hits: u64,
+  /// The number of blocks that had to be read from the disk on demand.
+  pub misses: u64,
+  /// The number of blocks read from the disk ahead of being requested.
+  pub read_ahead: u64,
+  /// The number of blocks evicted to make room for others.
+  pub evictions: u64
+}
+
+/// A single cached block.
+struct CacheEntry {
+  /// The contents of the block.
+  data: Vec<u8>,
+  /// The tick at which this block was last used.
+  last_used: u64
+}
+
+/// A size-bounded LRU cache of disk blocks.
+pub(crate) struct BlockCache {
+  /// The maximum number of blocks held.
+  capacity: usize,
+  /// The number of blocks to read ahead on sequential access.
+  read_ahead: usize,
+  /// The cached blocks, keyed by LBA.
+  entries: BTreeMap<u64, CacheEntry>,
+  /// Incremented on every access, used to order entries by recency.
+  tick: u64,
+  /// The LBA following the last read, used to detect sequential access.
+  next_sequential: Option<u64>,
+  /// Statistics about the use of this cache.
+  stats: CacheStats
+}
+
+impl BlockCache {
+  /// Creates an empty cache.
+  pub(crate) fn new(capacity: usize, read_ahead: usize) -> BlockCache {
+    BlockCache {
+      capacity,
+      read_ahead,
+      entries: BTreeMap::new(),
+      tick: 0,
+      next_sequential: None,
+      stats: CacheStats::default()
+    }
+  }
+
+  /// Returns the maximum number of blocks held.
+  pub(crate) fn capacity(&self) -> usize {
+    self.capacity
+  }
+
+  /// Returns the statistics collected by this cache.
+  pub(crate) fn stats(&self) -> CacheStats {
+    self.stats
+  }
+
+  /// Returns the number of blocks to read after a miss at `lba`.
+  /// 
+  /// This is only non-zero when `lba` follows on from the previous read.
+  fn read_ahead_for(&self, lba: u64) -> usize {
+    if self.next_sequential == Some(lba) {
+      // Never read ahead more than could be kept
+      return self.read_ahead.min(self.capacity.saturating_sub(1));
+    }
+
+    0
+  }
+
+  /// Records that a read ended just before `lba`.
+  fn set_next_sequential(&mut self, lba: u64) {
+    self.next_sequential = Some(lba);
+  }
+
+  /// Looks up a block, marking it as recently used.
+  fn get(&mut self, lba: u64) -> Option<&[u8]> {
+    self.tick += 1;
+    match self.entries.get_mut(&lba) {
+      Some(entry) => {
+        entry.last_used = self.tick;
+        self.stats.hits += 1;
+        Some(&entry.data)
+      }
+      None => None
+    }
+  }
+
+  /// Returns whether a block is cached, without marking it as used.
+  fn contains(&self, lba: u64) -> bool {
+    self.entries.contains_key(&lba)
+  }
+
+  /// Inserts a block, evicting the least recently used block if full.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `lba` (`u64`) - The LBA of the block.
+  /// - `data` (`Vec<u8>`) - The contents of the block.
+  /// - `prefetched` (`bool`) - Whether the block was read ahead rather than
+  ///   on demand.
+  fn insert(&mut self, lba: u64, data: Vec<u8>, prefetched: bool) {
+    if self.capacity == 0 {
+      return;
+    }
+
+    if prefetched {
+      self.stats.read_ahead += 1;
+    } else {
+      self.stats.misses += 1;
+    }
+
+    if !self.entries.contains_key(&lba) && self.entries.len() >= self.capacity {
+      let lru = self.entries.iter().min_by_key(|(_, entry)| entry.last_used).map(|(lba, _)| *lba);
+      if let Some(lru) = lru {
+        self.entries.remove(&lru);
+        self.stats.evictions += 1;
+      }
+    }
+
+    self.tick += 1;
+    self.entries.insert(lba, CacheEntry { data, last_used: self.tick });
+  }
+
+  /// Fills a buffer with bytes read through the cache.
+  /// 
+  /// Blocks which are not cached are read with `read_blocks`, each run of
+  /// missing blocks at once. If the read follows on from the previous one,
+  /// the blocks after it are read ahead in the same request.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `offset` (`u64`) - The offset to read from.
+  /// - `buffer` (`&mut [u8]`) - The buffer to fill, spanning no more blocks
+  ///   than the capacity of the cache.
+  /// - `block_size` (`u32`) - The number of bytes that make up a block.
+  /// - `last_block` (`u64`) - The final LBA of the device, past which
+  ///   nothing is read ahead.
+  /// - `read_blocks` (`FnMut(u64, &mut [u8]) -> Result<(), Status>`) -
+  ///   Fills a buffer of whole blocks from the device at an offset.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(())` on success.
+  /// - `Err(Status)` if `read_blocks` fails.
+  pub(crate) fn read_into(
+    &mut self,
+    offset: u64,
+    buffer: &mut [u8],
+    block_size: u32,
+    last_block: u64,
+    mut read_blocks: impl FnMut(u64, &mut [u8]) -> Result<(), Status>
+  ) -> Result<(), Status> {
+    if buffer.is_empty() {
+      return Ok(());
+    }
+
+    let (first_lba, last_lba) = super::device::block_span(offset, buffer.len(), block_size)?;
+    let block_size = block_size as u64;
+    let last_byte = offset + (buffer.len() as u64 - 1);
+
+    // Copy the part of a block that was requested, with inclusive bounds so
+    // that a range ending at `u64::MAX` does not overflow
+    let copy_block = |buffer: &mut [u8], lba: u64, data: &[u8]| {
+      let block_start = lba * block_size;
+      let from = offset.max(block_start);
+      let to = last_byte.min(block_start.saturating_add(block_size - 1));
+      buffer[(from - offset) as usize..=(to - offset) as usize]
+        .copy_from_slice(&data[(from - block_start) as usize..=(to - block_start) as usize]);
+    };
+
+    let mut lba = first_lba;
+    while lba <= last_lba {
+      if let Some(data) = self.get(lba) {
+        copy_block(buffer, lba, data);
+        lba += 1;
+        continue;
+      }
+
+      // Read the whole run of missing blocks at once, plus any read-ahead
+      let mut run = 1;
+      while lba + run <= last_lba && !self.contains(lba + run) {
+        run += 1;
+      }
+      let mut read_ahead = if lba + run > last_lba { self.read_ahead_for(first_lba) as u64 } else { 0 };
+      read_ahead = read_ahead.min(last_block.saturating_sub(lba + run - 1));
+
+      let mut blocks = alloc::vec![0; ((run + read_ahead) * block_size) as usize];
+      read_blocks(lba * block_size, &mut blocks)?;
+
+      for (i, data) in blocks.chunks_exact(block_size as usize).enumerate() {
+        let block_lba = lba + i as u64;
+        if (i as u64) < run {
+          copy_block(buffer, block_lba, data);
+        }
+        self.insert(block_lba, data.to_vec(), i as u64 >= run);
+      }
+      lba += run;
+    }
+
+    self.set_next_sequential(last_lba + 1);
+    Ok(())
+  }
+
+  /// Drops any cached blocks in the given range.
+  pub(crate) fn invalidate(&mut self, first_lba: u64, last_lba: u64) {
+    self.entries.retain(|lba, _| *lba < first_lba || *lba > last_lba);
+  }
+
+  /// Drops all cached blocks.
+  pub(crate) fn clear(&mut self) {
+    self.entries.clear();
+    self.next_sequential = None;
+  }
+}
@@ -0,0 +1,248 @@
+use alloc::vec::Vec;
+
+use embedded_io::{ErrorKind, SeekFrom};
+use uefi::Status;
+
+use super::{BlockDevice, DISK_OUT_OF_RANGE};
+use crate::UdiveError;
+
+#[derive(Clone, Debug, PartialEq)]
+/// The error type of the `embedded-io` traits implemented by [`DiskCursor`].
+pub struct CursorError(pub UdiveError);
+
+impl embedded_io::Error for CursorError {
+  fn kind(&self) -> ErrorKind {
+    match self.0.status() {
+      DISK_OUT_OF_RANGE | Status::INVALID_PARAMETER => ErrorKind::InvalidInput,
+      Status::VOLUME_CORRUPTED => ErrorKind::InvalidData,
+      Status::UNSUPPORTED => ErrorKind::Unsupported,
+      Status::OUT_OF_RESOURCES => ErrorKind::OutOfMemory,
+      _ => ErrorKind::Other
+    }
+  }
+}
+
+/// A sequential reader over a [`BlockDevice`].
+/// 
+/// A [`DiskCursor`] keeps track of a position on the device, so that on-disk
+/// structures can be parsed field by field without managing offsets by hand.
+/// It implements the `embedded-io` `Read` and `Seek` traits and, with the
+/// `std` feature, `std::io::Read` and `std::io::Seek`.
+/// 
+/// As [`BlockDevice`] is implemented for references, a cursor may borrow its
+/// device, e.g. `DiskCursor::new(&args.diskreader)`.
+pub struct DiskCursor<D: BlockDevice> {
+  /// The device being read.
+  device: D,
+  /// The offset of the next byte to be read.
+  position: u64
+}
+
+impl<D: BlockDevice> DiskCursor<D> {
+  /// Creates a new cursor at the start of a device.
+  pub fn new(device: D) -> DiskCursor<D> {
+    DiskCursor {
+      device,
+      position: 0
+    }
+  }
+
+  /// Returns a reference to the underlying device.
+  pub fn get_ref(&self) -> &D {
+    &self.device
+  }
+
+  /// Returns the underlying device.
+  pub fn into_inner(self) -> D {
+    self.device
+  }
+
+  /// Returns the size of the device in bytes, including any trailing
+  /// partial block.
+  pub fn len(&self) -> u64 {
+    self.device.size()
+  }
+
+  /// Returns whether the device has no readable bytes.
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// Returns the offset of the next byte to be read.
+  pub fn position(&self) -> u64 {
+    self.position
+  }
+
+  /// Sets the offset of the next byte to be read.
+  /// 
+  /// The position may be set past the end of the device, in which case
+  /// subsequent reads will fail.
+  pub fn set_position(&mut self, position: u64) {
+    self.position = position;
+  }
+
+  /// Moves the cursor.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `pos` (`SeekFrom`) - The position to move to.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(u64)` on success, containing the new position.
+  /// - `Err(UdiveError::Disk)` with `DISK_OUT_OF_RANGE` if the new position
+  ///   would be negative or overflow.
+  pub fn seek(&mut self, pos: SeekFrom) -> Result<u64, UdiveError> {
+    let position = match pos {
+      SeekFrom::Start(offset) => Some(offset),
+      SeekFrom::End(offset) => self.len().checked_add_signed(offset),
+      SeekFrom::Current(offset) => self.position.checked_add_signed(offset)
+    };
+
+    match position {
+      Some(some) => {
+        self.position = some;
+        Ok(some)
+      }
+      None => Err(UdiveError::disk(DISK_OUT_OF_RANGE))
+    }
+  }
+
+  /// Advances the cursor by a number of bytes.
+  pub fn skip(&mut self, count: u64) -> Result<u64, UdiveError> {
+    self.seek(SeekFrom::Current(i64::try_from(count).map_err(|_| UdiveError::disk(DISK_OUT_OF_RANGE))?))
+  }
+
+  /// Reads as many bytes as possible into a buffer.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(usize)` on success, containing the number of bytes read. This is
+  ///   zero at the end of the device.
+  /// - `Err(UdiveError::Disk)` on failure.
+  pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize, UdiveError> {
+    let remaining = self.len().saturating_sub(self.position);
+    let count = (buffer.len() as u64).min(remaining) as usize;
+    if count == 0 {
+      return Ok(0);
+    }
+
+    self.device.read_at(self.position, &mut buffer[..count]).map_err(UdiveError::disk)?;
+    self.position += count as u64;
+    Ok(count)
+  }
+
+  /// Fills a buffer, failing if the end of the device is reached first.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(())` on success.
+  /// - `Err(UdiveError::Disk)` with `DISK_OUT_OF_RANGE` if the end of the
+  ///   device is reached. The position is left unchanged.
+  /// - `Err(UdiveError::Disk)` on failure.
+  pub fn read_exact(&mut self, buffer: &mut [u8]) -> Result<(), UdiveError> {
+    match self.position.checked_add(buffer.len() as u64) {
+      Some(end) if end <= self.len() => {}
+      _ => {
+        return Err(UdiveError::disk(DISK_OUT_OF_RANGE));
+      }
+    }
+
+    self.device.read_at(self.position, buffer).map_err(UdiveError::disk)?;
+    self.position += buffer.len() as u64;
+    Ok(())
+  }
+
+  /// Reads a number of bytes into a new vector.
+  pub fn read_bytes(&mut self, count: usize) -> Result<Vec<u8>, UdiveError> {
+    let mut buffer = alloc::vec![0; count];
+    self.read_exact(&mut buffer)?;
+    Ok(buffer)
+  }
+
+  /// Reads a fixed-size array of bytes.
+  pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N], UdiveError> {
+    let mut buffer = [0; N];
+    self.read_exact(&mut buffer)?;
+    Ok(buffer)
+  }
+
+  /// Reads a single byte.
+  pub fn read_u8(&mut self) -> Result<u8, UdiveError> {
+    Ok(self.read_array::<1>()?[0])
+  }
+
+  /// Reads a single signed byte.
+  pub fn read_i8(&mut self) -> Result<i8, UdiveError> {
+    Ok(self.read_u8()? as i8)
+  }
+}
+
+/// Defines little- and big-endian integer readers on [`DiskCursor`].
+macro_rules! cursor_int_readers {
+  ($($ty:ty => $le:ident, $be:ident;)*) => {
+    impl<D: BlockDevice> DiskCursor<D> {
+      $(
+        #[doc = concat!("Reads a little-endian `", stringify!($ty), "`.")]
+        pub fn $le(&mut self) -> Result<$ty, UdiveError> {
+          Ok(<$ty>::from_le_bytes(self.read_array()?))
+        }
+
+        #[doc = concat!("Reads a big-endian `", stringify!($ty), "`.")]
+        pub fn $be(&mut self) -> Result<$ty, UdiveError> {
+          Ok(<$ty>::from_be_bytes(self.read_array()?))
+        }
+      )*
+    }
+  };
+}
+
+cursor_int_readers! {
+  u16 => read_u16_le, read_u16_be;
+  u32 => read_u32_le, read_u32_be;
+  u64 => read_u64_le, read_u64_be;
+  i16 => read_i16_le, read_i16_be;
+  i32 => read_i32_le, read_i32_be;
+  i64 => read_i64_le, read_i64_be;
+}
+
+impl<D: BlockDevice> embedded_io::ErrorType for DiskCursor<D> {
+  type Error = CursorError;
+}
+
+impl<D: BlockDevice> embedded_io::Read for DiskCursor<D> {
+  fn read(&mut self, buf: &mut [u8]) -> Result<usize, CursorError> {
+    DiskCursor::read(self, buf).map_err(CursorError)
+  }
+}
+
+impl<D: BlockDevice> embedded_io::Seek for DiskCursor<D> {
+  fn seek(&mut self, pos: SeekFrom) -> Result<u64, CursorError> {
+    DiskCursor::seek(self, pos).map_err(CursorError)
+  }
+}
+
+#[cfg(feature = "std")]
+impl<D: BlockDevice> std::io::Read for DiskCursor<D> {
+  fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    DiskCursor::read(self, buf).map_err(|err| std::io::Error::other(CursorError(err)))
+  }
+}
+
+#[cfg(feature = "std")]
+impl<D: BlockDevice> std::io::Seek for DiskCursor<D> {
+  fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+    DiskCursor::seek(self, pos.into()).map_err(|err| {
+      std::io::Error::new(std::io::ErrorKind::InvalidInput, CursorError(err))
+    })
+  }
+}
+
+impl core::fmt::Display for CursorError {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "{}", self.0)
+  }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CursorError {}
@@ -0,0 +1,232 @@
+use alloc::vec::Vec;
+
+use uefi::Status;
+
+use super::{DiskReader, DISK_OUT_OF_RANGE};
+
+/// A device that can be read in units of bytes, sectors and blocks.
+/// 
+/// File system drivers should parse on-disk structures through this trait
+/// rather than a concrete [`DiskReader`], so that the same code may run
+/// against a [`MemoryDisk`] or, with the `std` feature, a disk image on the
+/// host.
+pub trait BlockDevice {
+  /// Returns the number of bytes that make up a physical sector.
+  fn sector_size(&self) -> u32;
+
+  /// Returns the number of bytes that make up a logical block.
+  fn block_size(&self) -> u32;
+
+  /// Returns the final LBA of the device.
+  /// 
+  /// A device holding less than a whole block has no final LBA, but still
+  /// returns 0, so [`BlockDevice::size`] should be used to find whether a
+  /// device is empty.
+  fn last_block(&self) -> u64;
+
+  /// Returns the number of bytes readable from the device.
+  /// 
+  /// By default, this is every byte up to the end of
+  /// [`BlockDevice::last_block`]. Devices which may be empty, or end with a
+  /// partial block, should override it.
+  fn size(&self) -> u64 {
+    self.last_block().saturating_add(1).saturating_mul(self.block_size() as u64)
+  }
+
+  /// Fills a buffer with bytes read from the device at a specified offset.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `offset` (`u64`) - The offset to read from.
+  /// - `buffer` (`&mut [u8]`) - The buffer to fill.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(())` on success.
+  /// - `Err(Status)` on failure.
+  fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<(), Status>;
+
+  /// Reads a number of bytes from the device at a specified offset.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `offset` (`u64`) - The offset to read from.
+  /// - `count` (`usize`) - The number of bytes to read.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(Vec<u8>)` on success, containing the bytes read.
+  /// - `Err(Status)` on failure.
+  fn read_bytes(&self, offset: u64, count: usize) -> Result<Vec<u8>, Status> {
+    let mut buffer = alloc::vec![0; count];
+    self.read_at(offset, &mut buffer)?;
+    Ok(buffer)
+  }
+
+  /// Reads the given sector from the device.
+  fn read_sector(&self, sector: u64) -> Result<Vec<u8>, Status> {
+    self.read_sectors(sector, 1)
+  }
+
+  /// Reads the given number of sectors from the device.
+  fn read_sectors(&self, sector: u64, count: usize) -> Result<Vec<u8>, Status> {
+    let (offset, length) = unit_range(sector, count, self.sector_size())?;
+    self.read_bytes(offset, length)
+  }
+
+  /// Reads the given block from the device.
+  fn read_block(&self, lba: u64) -> Result<Vec<u8>, Status> {
+    self.read_blocks(lba, 1)
+  }
+
+  /// Reads the given number of blocks from the device.
+  fn read_blocks(&self, lba: u64, count: usize) -> Result<Vec<u8>, Status> {
+    let (offset, length) = unit_range(lba, count, self.block_size())?;
+    self.read_bytes(offset, length)
+  }
+}
+
+/// Converts a number of units (e.g. sectors) into a byte offset and length.
+pub(super) fn unit_range(index: u64, count: usize, unit: u32) -> Result<(u64, usize), Status> {
+  match (index.checked_mul(unit as u64), count.checked_mul(unit as usize)) {
+    (Some(offset), Some(length)) => Ok((offset, length)),
+    _ => Err(DISK_OUT_OF_RANGE)
+  }
+}
+
+/// Returns the LBAs of the first and last blocks a non-empty range of bytes
+/// lies in.
+pub(super) fn block_span(offset: u64, count: usize, block_size: u32) -> Result<(u64, u64), Status> {
+  if block_size == 0 {
+    return Err(Status::INVALID_PARAMETER);
+  }
+
+  let last_byte = (count as u64).checked_sub(1)
+    .and_then(|length| offset.checked_add(length))
+    .ok_or(DISK_OUT_OF_RANGE)?;
+  Ok((offset / block_size as u64, last_byte / block_size as u64))
+}
+
+impl BlockDevice for DiskReader {
+  fn sector_size(&self) -> u32 {
+    self.sector_size
+  }
+
+  fn block_size(&self) -> u32 {
+    self.block_size
+  }
+
+  fn last_block(&self) -> u64 {
+    self.last_block
+  }
+
+  fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<(), Status> {
+    self.read_into(offset, buffer)
+  }
+
+  fn read_bytes(&self, offset: u64, count: usize) -> Result<Vec<u8>, Status> {
+    DiskReader::read_bytes(self, offset, count).map_err(Status::from)
+  }
+}
+
+impl<D: BlockDevice + ?Sized> BlockDevice for &D {
+  fn sector_size(&self) -> u32 {
+    (**self).sector_size()
+  }
+
+  fn block_size(&self) -> u32 {
+    (**self).block_size()
+  }
+
+  fn last_block(&self) -> u64 {
+    (**self).last_block()
+  }
+
+  fn size(&self) -> u64 {
+    (**self).size()
+  }
+
+  fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<(), Status> {
+    (**self).read_at(offset, buffer)
+  }
+}
+
+/// A block device backed by a buffer in memory.
+/// 
+/// This is mostly useful for testing file system parsing code against a disk
+/// image included in a test, or for reading a file system image that has
+/// already been loaded.
+pub struct MemoryDisk<B: AsRef<[u8]> = Vec<u8>> {
+  /// The contents of the device.
+  data: B,
+  /// The number of bytes that make up a sector and block.
+  block_size: u32
+}
+
+impl<B: AsRef<[u8]>> MemoryDisk<B> {
+  /// Creates a new in-memory block device.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `data` (`B`) - The contents of the device. Any trailing partial block
+  ///   is readable by [`BlockDevice::read_at`] and counted by
+  ///   [`BlockDevice::size`], but not by [`BlockDevice::last_block`].
+  /// - `block_size` (`u32`) - The size of a sector and block, in bytes.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(MemoryDisk)` on success.
+  /// - `Err(Status::INVALID_PARAMETER)` if `block_size` is zero.
+  pub fn new(data: B, block_size: u32) -> Result<MemoryDisk<B>, Status> {
+    if block_size == 0 {
+      return Err(Status::INVALID_PARAMETER);
+    }
+
+    Ok(
+      MemoryDisk {
+        data,
+        block_size
+      }
+    )
+  }
+
+  /// Returns the contents of the device.
+  pub fn data(&self) -> &[u8] {
+    self.data.as_ref()
+  }
+
+  /// Returns the underlying buffer.
+  pub fn into_inner(self) -> B {
+    self.data
+  }
+}
+
+impl<B: AsRef<[u8]>> BlockDevice for MemoryDisk<B> {
+  fn sector_size(&self) -> u32 {
+    self.block_size
+  }
+
+  fn block_size(&self) -> u32 {
+    self.block_size
+  }
+
+  fn last_block(&self) -> u64 {
+    (self.size() / self.block_size as u64).saturating_sub(1)
+  }
+
+  fn size(&self) -> u64 {
+    self.data.as_ref().len() as u64
+  }
+
+  fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<(), Status> {
+    let data = self.data.as_ref();
+    let start = usize::try_from(offset).map_err(|_| DISK_OUT_OF_RANGE)?;
+    match start.checked_add(buffer.len()) {
+      Some(end) if end <= data.len() => {
+        buffer.copy_from_slice(&data[start..end]);
+        Ok(())
+      }
+      _ => Err(DISK_OUT_OF_RANGE)
+    }
+  }
+}
@@ -0,0 +1,84 @@
+use core::cell::RefCell;
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use uefi::Status;
+
+use super::{BlockDevice, DISK_OUT_OF_RANGE};
+
+/// A block device backed by a disk image file on the host.
+/// 
+/// This is only available with the `std` feature, and allows file system
+/// drivers to be tested with `cargo test` against real disk images.
+pub struct ImageFile {
+  /// The open image file.
+  file: RefCell<File>,
+  /// The size of the image file in bytes.
+  len: u64,
+  /// The number of bytes that make up a sector and block.
+  block_size: u32
+}
+
+impl ImageFile {
+  /// Opens a disk image file.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `path` (`impl AsRef<Path>`) - The path of the image file.
+  /// - `block_size` (`u32`) - The size of a sector and block, in bytes.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(ImageFile)` on success.
+  /// - `Err(std::io::Error)` of kind `InvalidInput` if `block_size` is zero.
+  /// - `Err(std::io::Error)` if the file could not be opened.
+  pub fn open(path: impl AsRef<Path>, block_size: u32) -> std::io::Result<ImageFile> {
+    if block_size == 0 {
+      return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "block size must not be zero"));
+    }
+
+    let file = File::open(path)?;
+    let len = file.metadata()?.len();
+
+    Ok(
+      ImageFile {
+        file: RefCell::new(file),
+        len,
+        block_size
+      }
+    )
+  }
+}
+
+impl BlockDevice for ImageFile {
+  fn sector_size(&self) -> u32 {
+    self.block_size
+  }
+
+  fn block_size(&self) -> u32 {
+    self.block_size
+  }
+
+  fn last_block(&self) -> u64 {
+    (self.len / self.block_size as u64).saturating_sub(1)
+  }
+
+  fn size(&self) -> u64 {
+    self.len
+  }
+
+  fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<(), Status> {
+    match offset.checked_add(buffer.len() as u64) {
+      Some(end) if end <= self.len => {}
+      _ => {
+        return Err(DISK_OUT_OF_RANGE);
+      }
+    }
+
+    let mut file = self.file.borrow_mut();
+    file.seek(SeekFrom::Start(offset)).map_err(|_| Status::DEVICE_ERROR)?;
+    file.read_exact(buffer).map_err(|_| Status::DEVICE_ERROR)
+  }
+}
@@ -0,0 +1,636 @@
+mod aio;
+mod backend;
+mod cache;
+mod cursor;
+mod device;
+#[cfg(feature = "std")]
+mod image;
+mod pages;
+#[cfg(test)]
+mod tests;
+
+use core::cell::RefCell;
+
+use alloc::vec::Vec;
+use uefi::proto::media::block::BlockIO;
+use uefi::proto::media::disk::DiskIo;
+use uefi::boot::{AllocateType, MemoryType, OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol};
+use uefi::{Handle, Status};
+
+use crate::UdiveError;
+
+use aio::AsyncBackend;
+pub use aio::{wait_all, PendingRead};
+use backend::DiskBackend;
+use cache::BlockCache;
+pub use cache::CacheStats;
+pub use cursor::{CursorError, DiskCursor};
+pub use device::{BlockDevice, MemoryDisk};
+#[cfg(feature = "std")]
+pub use image::ImageFile;
+pub use pages::PageBuffer;
+
+/// Returned when a read or write falls outside the extent of a
+/// [`BlockDevice`], or its offset cannot be represented.
+pub const DISK_OUT_OF_RANGE: Status = crate::oem_error(0xD15C);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// The modes a [`DiskReader`] may be opened in.
+pub enum DiskMode {
+  /// Only reads are permitted.
+  ReadOnly,
+  /// Both reads and writes are permitted.
+  ReadWrite
+}
+
+/// Manages reading a disk.
+/// 
+/// Instances of [`DiskReader`] operate as an abstraction of a UEFI `DiskIo`
+/// protocol. It allows for raw low-level access to a disk, reading in
+/// intervals of bytes, sectors, and blocks. Where `DiskIo` is unavailable, the
+/// `BlockIO` protocol is used instead, with unaligned accesses going through a
+/// bounce buffer.
+/// 
+/// A [`DiskReader`] is read-only unless opened in [`DiskMode::ReadWrite`], in
+/// which case it may also write to the disk within the bounds of the
+/// partition.
+/// 
+/// All reads and writes are checked against the extent of the partition, from
+/// `abs_offset` up to the end of `last_block`, and fail with
+/// [`DISK_OUT_OF_RANGE`] otherwise. Whole-disk readers may bypass this with
+/// [`DiskReader::read_bytes_unchecked`].
+/// 
+/// An optional block cache may be enabled with [`DiskReader::enable_cache`],
+/// which keeps recently read blocks in memory and reads ahead when blocks are
+/// read sequentially.
+/// 
+/// Reads may also be issued asynchronously with
+/// [`DiskReader::read_bytes_async`], through `DiskIo2` or `BlockIO2` where
+/// available.
+pub struct DiskReader {
+  /// The handle on which the protocol is open.
+  handle: Handle,
+  /// The protocol over which to abstract.
+  backend: DiskBackend,
+  /// The protocol asynchronous reads are issued through, if available.
+  async_backend: Option<AsyncBackend>,
+  /// The offset within the disk to read from.
+  /// 
+  /// In reading a file system, this will usually be set to the offset of the
+  /// partition on which it resides.
+  pub abs_offset: u64,
+  /// The media ID of the partition.
+  pub media_id: u32,
+  /// The number of bytes that make up a physical sector on this disk.
+  pub sector_size: u32,
+  /// The number of bytes that make up a logical block on this disk.
+  pub block_size: u32,
+  /// The final LBA of this partition, relative to `abs_offset`.
+  pub last_block: u64,
+  /// Whether the media is write-protected.
+  pub read_only: bool,
+  /// The mode this disk reader is opened in.
+  mode: DiskMode,
+  /// The block cache, if enabled.
+  cache: RefCell<Option<BlockCache>>
+}
+
+impl DiskReader {
+  /// Creates a new diskreader.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `handle` (`&Handle`) - The EFI handle to the partition on which to
+  ///   create a disk reader.
+  /// - `protocol` (`ScopedProtocol<DiskIo>`) - An instance of the
+  ///   `DiskIo` protocol, currently open on the aforementioned handle.
+  /// - `abs_offset` (`u64`) - The offset on the disk to read from.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(DiskReader)` - An instance of a [`DiskReader`], extending from
+  ///   `abs_offset` to the end of the media. If the partition ends before
+  ///   then, `last_block` should be adjusted.
+  /// - `Err(UdiveError::Disk)` if the `BlockIO` protocol, which describes
+  ///   the media, could not be opened on the handle.
+  /// - `Err(UdiveError::Disk)` carrying
+  ///   [`uefi_raw::Status::INVALID_PARAMETER`] if the media reports a block
+  ///   size of zero.
+  pub fn new(handle: &Handle, protocol: ScopedProtocol<DiskIo>, abs_offset: u64) -> Result<DiskReader, UdiveError> {
+    let block_io_protocol = backend::open_block_io(*handle).map_err(UdiveError::disk)?;
+
+    DiskReader::with_backend(handle, DiskBackend::DiskIo(protocol, block_io_protocol), abs_offset)
+  }
+
+  /// Creates a new diskreader over the `BlockIO` protocol.
+  /// 
+  /// This is used for handles which do not expose `DiskIo`. Byte-granular
+  /// accesses are emulated with bounce buffers.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `handle` (`&Handle`) - The EFI handle to the partition on which to
+  ///   create a disk reader.
+  /// - `protocol` (`ScopedProtocol<BlockIO>`) - An instance of the
+  ///   `BlockIO` protocol, currently open on the aforementioned handle.
+  /// - `abs_offset` (`u64`) - The offset on the disk to read from.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(DiskReader)` - An instance of a [`DiskReader`], extending from
+  ///   `abs_offset` to the end of the media.
+  /// - `Err(UdiveError::Disk)` carrying
+  ///   [`uefi_raw::Status::INVALID_PARAMETER`] if the media reports a block
+  ///   size of zero.
+  pub fn from_block_io(handle: &Handle, protocol: ScopedProtocol<BlockIO>, abs_offset: u64) -> Result<DiskReader, UdiveError> {
+    DiskReader::with_backend(handle, DiskBackend::BlockIo(protocol), abs_offset)
+  }
+
+  /// Creates a new diskreader from a backend, reading its media.
+  fn with_backend(handle: &Handle, backend: DiskBackend, abs_offset: u64) -> Result<DiskReader, UdiveError> {
+    let media = backend.media();
+    let block_size = media.block_size();
+    if block_size == 0 {
+      return Err(UdiveError::disk(Status::INVALID_PARAMETER).with_message("media reports a block size of zero"));
+    }
+    let sector_size = if media.logical_blocks_per_physical_block() == 0 {
+      block_size
+    } else {
+      block_size / media.logical_blocks_per_physical_block()
+    };
+
+    let media_id = media.media_id();
+    let last_block = media.last_block().saturating_sub(abs_offset / block_size as u64);
+    let read_only = media.is_read_only();
+
+    Ok(
+      DiskReader {
+        handle: *handle,
+        backend,
+        async_backend: AsyncBackend::open(*handle),
+        abs_offset,
+        media_id,
+        sector_size,
+        block_size,
+        last_block,
+        read_only,
+        mode: DiskMode::ReadOnly,
+        cache: RefCell::new(None)
+      }
+    )
+  }
+
+  /// Opens a new diskreader on a handle.
+  /// 
+  /// Unlike [`DiskReader::new`], the protocol is opened by this function.
+  /// `DiskIo` is preferred, falling back to `BlockIO` if it is unavailable.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `handle` (`&Handle`) - The EFI handle to the partition on which to
+  ///   create a disk reader.
+  /// - `abs_offset` (`u64`) - The offset on the disk to read from.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(DiskReader)` on success.
+  /// - `Err(UdiveError::Disk)` carrying [`uefi_raw::Status::UNSUPPORTED`]
+  ///   if neither `DiskIo` nor `BlockIO` could be opened on the handle.
+  /// - `Err(UdiveError::Disk)` carrying
+  ///   [`uefi_raw::Status::INVALID_PARAMETER`] if the media reports a block
+  ///   size of zero.
+  pub fn open(handle: &Handle, abs_offset: u64) -> Result<DiskReader, UdiveError> {
+    let block_io_protocol = match backend::open_block_io(*handle) {
+      Ok(ok) => ok,
+      Err(_) => {
+        return Err(UdiveError::disk(Status::UNSUPPORTED).with_message("no block IO protocol on handle"));
+      }
+    };
+
+    let disk_io_protocol = unsafe {
+      uefi::boot::open_protocol::<DiskIo>(
+        OpenProtocolParams {
+          handle: *handle,
+          agent: uefi::boot::image_handle(),
+          controller: None
+        },
+        OpenProtocolAttributes::GetProtocol
+      )
+    };
+
+    match disk_io_protocol {
+      Ok(ok) => DiskReader::with_backend(handle, DiskBackend::DiskIo(ok, block_io_protocol), abs_offset),
+      Err(_) => DiskReader::from_block_io(handle, block_io_protocol, abs_offset)
+    }
+  }
+
+  /// Opens a new diskreader on a handle in the given mode.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `handle` (`&Handle`) - The EFI handle to the partition on which to
+  ///   create a disk reader.
+  /// - `abs_offset` (`u64`) - The offset on the disk to read from.
+  /// - `mode` (`DiskMode`) - The mode to open the disk reader in.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(DiskReader)` on success.
+  /// - `Err(UdiveError::Disk)` carrying
+  ///   [`uefi_raw::Status::WRITE_PROTECTED`] if opening in
+  ///   [`DiskMode::ReadWrite`] on read-only media.
+  /// - Otherwise, as with [`DiskReader::open`].
+  pub fn open_with_mode(handle: &Handle, abs_offset: u64, mode: DiskMode) -> Result<DiskReader, UdiveError> {
+    let mut diskreader = DiskReader::open(handle, abs_offset)?;
+    diskreader.set_mode(mode)?;
+
+    Ok(diskreader)
+  }
+
+  /// Returns the mode this disk reader is opened in.
+  pub fn mode(&self) -> DiskMode {
+    self.mode
+  }
+
+  /// Changes the mode of this disk reader.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `mode` (`DiskMode`) - The mode to switch to.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(())` on success.
+  /// - `Err(UdiveError::Disk)` carrying
+  ///   [`uefi_raw::Status::WRITE_PROTECTED`] if switching to
+  ///   [`DiskMode::ReadWrite`] on read-only media.
+  pub fn set_mode(&mut self, mode: DiskMode) -> Result<(), UdiveError> {
+    if mode == DiskMode::ReadWrite && self.read_only {
+      return Err(UdiveError::disk(Status::WRITE_PROTECTED).with_message("media is read-only"));
+    }
+
+    self.mode = mode;
+    Ok(())
+  }
+
+  /// Returns the handle this disk reader was created on.
+  pub fn handle(&self) -> Handle {
+    self.handle
+  }
+
+  /// Enables the block cache, discarding any previously cached blocks.
+  /// 
+  /// The cache may be enabled through a shared reference, so that file
+  /// system drivers can enable it on the disk reader they are passed.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `capacity` (`usize`) - The maximum number of blocks to cache. A
+  ///   capacity of zero disables the cache.
+  /// - `read_ahead` (`usize`) - The number of extra blocks to read when a
+  ///   read follows on directly from the previous one.
+  pub fn enable_cache(&self, capacity: usize, read_ahead: usize) {
+    if capacity == 0 {
+      self.disable_cache();
+      return;
+    }
+
+    *self.cache.borrow_mut() = Some(BlockCache::new(capacity, read_ahead));
+  }
+
+  /// Disables the block cache, discarding any cached blocks.
+  pub fn disable_cache(&self) {
+    *self.cache.borrow_mut() = None;
+  }
+
+  /// Discards all cached blocks, keeping the cache enabled.
+  pub fn clear_cache(&self) {
+    if let Some(cache) = self.cache.borrow_mut().as_mut() {
+      cache.clear();
+    }
+  }
+
+  /// Returns the statistics of the block cache.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Some(CacheStats)` if the cache is enabled.
+  /// - `None` otherwise.
+  pub fn cache_stats(&self) -> Option<CacheStats> {
+    self.cache.borrow().as_ref().map(|cache| cache.stats())
+  }
+
+  /// Reads a number of bytes from the disk at a specified offset.
+  /// 
+  /// If the block cache is enabled, the read is served from it where
+  /// possible.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `offset` (`u64`) - The disk offset to read from.
+  /// - `count` (`usize`) - The number of bytes to read.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(Vec<u8>)` on success, containing the bytes read.
+  /// - `Err(UdiveError::Disk)` carrying [`DISK_OUT_OF_RANGE`] if the read
+  ///   extends past the partition.
+  /// - `Err(UdiveError::Disk)` carrying another `Status` on failure.
+  pub fn read_bytes(&self, offset: u64, count: usize) -> Result<Vec<u8>, UdiveError> {
+    let mut buffer = alloc::vec![0; count];
+    self.read_bytes_into(offset, &mut buffer)?;
+    Ok(buffer)
+  }
+
+  /// Fills a buffer with bytes read from the disk at a specified offset.
+  /// 
+  /// Unlike [`DiskReader::read_bytes`], no memory is allocated unless the
+  /// read is served through the block cache.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `offset` (`u64`) - The disk offset to read from.
+  /// - `buffer` (`&mut [u8]`) - The buffer to fill.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(())` on success.
+  /// - `Err(UdiveError::Disk)` carrying [`DISK_OUT_OF_RANGE`] if the read
+  ///   extends past the partition.
+  /// - `Err(UdiveError::Disk)` carrying another `Status` on failure.
+  pub fn read_bytes_into(&self, offset: u64, buffer: &mut [u8]) -> Result<(), UdiveError> {
+    self.read_into(offset, buffer).map_err(UdiveError::disk)
+  }
+
+  /// Fills a buffer with bytes read from the disk, through the cache if
+  /// enabled.
+  fn read_into(&self, offset: u64, buffer: &mut [u8]) -> Result<(), Status> {
+    self.check_range(offset, buffer.len())?;
+    if buffer.is_empty() {
+      return Ok(());
+    }
+
+    // Reads too large to be cached go straight to the disk
+    let (first_lba, last_lba) = device::block_span(offset, buffer.len(), self.block_size)?;
+    if self.cache.borrow().as_ref().is_some_and(|cache| last_lba - first_lba < cache.capacity() as u64) {
+      return self.read_cached_into(offset, buffer);
+    }
+
+    self.read_disk(offset, buffer)
+  }
+
+  /// Fills a buffer with whole blocks read from the disk.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `lba` (`u64`) - The LBA of the first block to read.
+  /// - `buffer` (`&mut [u8]`) - The buffer to fill, a multiple of the block
+  ///   size in length.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(())` on success.
+  /// - `Err(UdiveError::Disk)` carrying
+  ///   [`uefi_raw::Status::BAD_BUFFER_SIZE`] if `buffer` is not a multiple of
+  ///   the block size in length.
+  /// - Otherwise, as with [`DiskReader::read_bytes_into`].
+  pub fn read_blocks_into(&self, lba: u64, buffer: &mut [u8]) -> Result<(), UdiveError> {
+    if !buffer.len().is_multiple_of(self.block_size as usize) {
+      return Err(UdiveError::disk(Status::BAD_BUFFER_SIZE));
+    }
+
+    let (offset, _) = device::unit_range(lba, 0, self.block_size).map_err(UdiveError::disk)?;
+    self.read_bytes_into(offset, buffer)
+  }
+
+  /// Reads a number of bytes from the disk into newly allocated pages.
+  /// 
+  /// This allows images to be read directly to where they will be executed,
+  /// without an intermediate copy.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `offset` (`u64`) - The disk offset to read from.
+  /// - `count` (`usize`) - The number of bytes to read.
+  /// - `alloc_type` (`AllocateType`) - Where to allocate the pages.
+  /// - `memtype` (`MemoryType`) - The memory type of the pages.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(PageBuffer)` on success, containing the bytes read.
+  /// - `Err(UdiveError::Disk)` carrying [`DISK_OUT_OF_RANGE`] if the read
+  ///   extends past the partition.
+  /// - `Err(UdiveError::Disk)` carrying another `Status` if the pages could
+  ///   not be allocated or the read fails.
+  pub fn read_bytes_into_pages(&self, offset: u64, count: usize, alloc_type: AllocateType, memtype: MemoryType) -> Result<PageBuffer, UdiveError> {
+    // Fail before allocating if the read cannot succeed
+    self.check_range(offset, count).map_err(UdiveError::disk)?;
+
+    let mut buffer = PageBuffer::allocate(alloc_type, memtype, count)?;
+    self.read_bytes_into(offset, &mut buffer)?;
+    Ok(buffer)
+  }
+
+  /// Reads a number of bytes from the disk without checking the extent of
+  /// the partition.
+  /// 
+  /// This is intended for whole-disk readers, where `last_block` may not be
+  /// known to cover the entire device. The block cache is bypassed.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `offset` (`u64`) - The disk offset to read from.
+  /// - `count` (`usize`) - The number of bytes to read.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(Vec<u8>)` on success, containing the bytes read.
+  /// - `Err(UdiveError::Disk)` carrying [`DISK_OUT_OF_RANGE`] if the
+  ///   absolute offset overflows.
+  /// - `Err(UdiveError::Disk)` carrying another `Status` on failure.
+  pub fn read_bytes_unchecked(&self, offset: u64, count: usize) -> Result<Vec<u8>, UdiveError> {
+    let mut buffer = alloc::vec![0; count];
+    self.read_disk(offset, &mut buffer).map_err(UdiveError::disk)?;
+    Ok(buffer)
+  }
+
+  /// Checks that a range of bytes lies within the extent of the partition.
+  fn check_range(&self, offset: u64, count: usize) -> Result<(), Status> {
+    let extent = (self.last_block as u128 + 1) * self.block_size as u128;
+    let end = offset as u128 + count as u128;
+    if end > extent {
+      return Err(DISK_OUT_OF_RANGE);
+    }
+
+    Ok(())
+  }
+
+  /// Reads from the disk into a buffer through the block cache.
+  fn read_cached_into(&self, offset: u64, buffer: &mut [u8]) -> Result<(), Status> {
+    let mut cache_ref = self.cache.borrow_mut();
+    let cache = cache_ref.as_mut().ok_or(Status::NOT_READY)?;
+
+    cache.read_into(
+      offset,
+      buffer,
+      self.block_size,
+      self.last_block,
+      |offset, blocks| self.read_disk(offset, blocks)
+    )
+  }
+
+  /// Reads bytes from the disk directly into a buffer, bypassing the cache.
+  fn read_disk(&self, offset: u64, buffer: &mut [u8]) -> Result<(), Status> {
+    self.backend.read(
+      self.media_id,
+      self.abs_offset.checked_add(offset).ok_or(DISK_OUT_OF_RANGE)?,
+      buffer
+    )
+  }
+
+  /// Reads the given sector from the disk.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `sector` (`u64`) - The number of the sector to read.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(Vec<u8>)` on success, containing the sector's data.
+  /// - `Err(UdiveError::Disk)` on failure.
+  pub fn read_sector(&self, sector: u64) -> Result<Vec<u8>, UdiveError> {
+    let (offset, length) = device::unit_range(sector, 1, self.sector_size).map_err(UdiveError::disk)?;
+    self.read_bytes(offset, length)
+  }
+
+  /// Reads the given number of sectors from the disk.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `sector` (`u64`) - The number of the first sector to read.
+  /// - `count` (`usize`) - The number of sectors to read.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(Vec<u8>)` on success, containing the data of those sectors.
+  /// - `Err(UdiveError::Disk)` on failure.
+  pub fn read_sectors(&self, sector: u64, count: usize) -> Result<Vec<u8>, UdiveError> {
+    let (offset, length) = device::unit_range(sector, count, self.sector_size).map_err(UdiveError::disk)?;
+    self.read_bytes(offset, length)
+  }
+
+  /// Reads the given block from the disk.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `lba` (`u64`) - The LBA of the block to read.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(Vec<u8>)` on success, containing the block's data.
+  /// - `Err(UdiveError::Disk)` on failure.
+  pub fn read_block(&self, lba: u64) -> Result<Vec<u8>, UdiveError> {
+    let (offset, length) = device::unit_range(lba, 1, self.block_size).map_err(UdiveError::disk)?;
+    self.read_bytes(offset, length)
+  }
+
+  /// Reads the given number of blocks from the disk.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `block` (`u64`) - The LBA of the first block to read.
+  /// - `count` (`usize`) - The number of blocks to read.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(Vec<u8>)` on success, containing the data of those blocks.
+  /// - `Err(UdiveError::Disk)` on failure.
+  pub fn read_blocks(&self, lba: u64, count: usize) -> Result<Vec<u8>, UdiveError> {
+    let (offset, length) = device::unit_range(lba, count, self.block_size).map_err(UdiveError::disk)?;
+    self.read_bytes(offset, length)
+  }
+
+  /// Writes a number of bytes to the disk at a specified offset.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `offset` (`u64`) - The disk offset to write to.
+  /// - `buffer` (`&[u8]`) - The bytes to write.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(())` on success.
+  /// - `Err(UdiveError::Disk)`, carrying:
+  ///   - [`uefi_raw::Status::ACCESS_DENIED`] if this disk reader is not
+  ///     opened in [`DiskMode::ReadWrite`].
+  ///   - [`uefi_raw::Status::WRITE_PROTECTED`] if the media is read-only.
+  ///   - [`DISK_OUT_OF_RANGE`] if the write would extend past the final
+  ///     block of the partition.
+  ///   - Another `Status` if the write fails.
+  pub fn write_bytes(&mut self, offset: u64, buffer: &[u8]) -> Result<(), UdiveError> {
+    if self.mode != DiskMode::ReadWrite {
+      return Err(UdiveError::disk(Status::ACCESS_DENIED).with_message("disk reader is read-only"));
+    }
+    if self.read_only {
+      return Err(UdiveError::disk(Status::WRITE_PROTECTED).with_message("media is read-only"));
+    }
+
+    // Ensure the write stays within the partition
+    let write_offset = self.check_range(offset, buffer.len())
+      .and_then(|_| self.abs_offset.checked_add(offset).ok_or(DISK_OUT_OF_RANGE))
+      .map_err(UdiveError::disk)?;
+
+    // Drop any stale cached blocks
+    if let Some(cache) = self.cache.get_mut() && !buffer.is_empty() {
+      let (first_lba, last_lba) = device::block_span(offset, buffer.len(), self.block_size).map_err(UdiveError::disk)?;
+      cache.invalidate(first_lba, last_lba);
+    }
+
+    self.backend.write(
+      self.media_id,
+      write_offset,
+      buffer
+    ).map_err(UdiveError::disk)
+  }
+
+  /// Writes whole blocks to the disk.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `lba` (`u64`) - The LBA of the first block to write.
+  /// - `buffer` (`&[u8]`) - The data to write, a multiple of the block size
+  ///   in length.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Err(UdiveError::Disk)` carrying
+  ///   [`uefi_raw::Status::BAD_BUFFER_SIZE`] if `buffer` is not a multiple of
+  ///   the block size in length.
+  /// - Otherwise, as with [`DiskReader::write_bytes`].
+  pub fn write_blocks(&mut self, lba: u64, buffer: &[u8]) -> Result<(), UdiveError> {
+    if !buffer.len().is_multiple_of(self.block_size as usize) {
+      return Err(UdiveError::disk(Status::BAD_BUFFER_SIZE));
+    }
+
+    match lba.checked_mul(self.block_size as u64) {
+      Some(offset) => self.write_bytes(offset, buffer),
+      None => Err(UdiveError::disk(DISK_OUT_OF_RANGE))
+    }
+  }
+
+  /// Flushes any cached writes to the underlying device.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(())` on success.
+  /// - `Err(UdiveError::Disk)` carrying [`uefi_raw::Status::ACCESS_DENIED`]
+  ///   if this disk reader is not opened in [`DiskMode::ReadWrite`].
+  /// - `Err(UdiveError::Disk)` carrying another `Status` if the flush fails.
+  pub fn flush(&mut self) -> Result<(), UdiveError> {
+    if self.mode != DiskMode::ReadWrite {
+      return Err(UdiveError::disk(Status::ACCESS_DENIED).with_message("disk reader is read-only"));
+    }
+
+    self.backend.flush().map_err(UdiveError::disk)
+  }
+}
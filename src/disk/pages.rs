@@ -0,0 +1,115 @@
+use core::ops::{Deref, DerefMut};
+use core::ptr::NonNull;
+
+use uefi::boot::{AllocateType, MemoryType, PAGE_SIZE};
+
+use crate::UdiveError;
+
+/// A page-aligned buffer allocated with `uefi::boot::allocate_pages`.
+/// 
+/// The pages are freed when the buffer is dropped, unless ownership is taken
+/// with [`PageBuffer::into_raw`] (e.g. to execute a kernel in place).
+pub struct PageBuffer {
+  /// The start of the allocated pages.
+  ptr: NonNull<u8>,
+  /// The number of allocated pages.
+  pages: usize,
+  /// The number of bytes in use.
+  len: usize
+}
+
+impl PageBuffer {
+  /// Allocates a page-aligned buffer.
+  /// 
+  /// The contents of the buffer are left as allocated, as it is expected to
+  /// be filled straight away (e.g. by a disk read), and clearing an image of
+  /// hundreds of megabytes would double the work of reading it. Only the
+  /// unused tail of the final page is zeroed, so that nothing stale follows
+  /// an image loaded in place.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `alloc_type` (`AllocateType`) - Where to allocate the pages, e.g.
+  ///   `AllocateType::Address` to place an image at its load address.
+  /// - `memtype` (`MemoryType`) - The memory type of the pages.
+  /// - `len` (`usize`) - The size of the buffer in bytes.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(PageBuffer)` on success.
+  /// - `Err(UdiveError::Disk)` if the pages could not be allocated.
+  pub fn allocate(alloc_type: AllocateType, memtype: MemoryType, len: usize) -> Result<PageBuffer, UdiveError> {
+    let pages = len.max(1).div_ceil(PAGE_SIZE);
+    match uefi::boot::allocate_pages(alloc_type, memtype, pages) {
+      Ok(ptr) => {
+        unsafe {
+          core::ptr::write_bytes(ptr.as_ptr().add(len), 0, pages * PAGE_SIZE - len);
+        }
+
+        Ok(
+          PageBuffer {
+            ptr,
+            pages,
+            len
+          }
+        )
+      }
+      Err(err) => Err(UdiveError::disk(err.status()))
+    }
+  }
+
+  /// Returns a pointer to the start of the buffer.
+  pub fn as_ptr(&self) -> *mut u8 {
+    self.ptr.as_ptr()
+  }
+
+  /// Returns the number of pages backing the buffer.
+  pub fn page_count(&self) -> usize {
+    self.pages
+  }
+
+  /// Moves a range of the buffer to its start, and shrinks the buffer to
+  /// that range.
+  pub(crate) fn window(&mut self, skip: usize, len: usize) {
+    if skip != 0 {
+      unsafe {
+        core::ptr::copy(self.ptr.as_ptr().add(skip), self.ptr.as_ptr(), len);
+      }
+    }
+    self.len = len;
+  }
+
+  /// Releases ownership of the pages without freeing them.
+  /// 
+  /// # Returns
+  /// 
+  /// - `(NonNull<u8>, usize)` - The start of the pages, and the number of
+  ///   pages, to later be passed to `uefi::boot::free_pages` if needed.
+  pub fn into_raw(self) -> (NonNull<u8>, usize) {
+    let raw = (self.ptr, self.pages);
+    core::mem::forget(self);
+    raw
+  }
+}
+
+impl Deref for PageBuffer {
+  type Target = [u8];
+
+  fn deref(&self) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+  }
+}
+
+impl DerefMut for PageBuffer {
+  fn deref_mut(&mut self) -> &mut [u8] {
+    unsafe { core::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+  }
+}
+
+impl Drop for PageBuffer {
+  fn drop(&mut self) {
+    unsafe {
+      let _ = uefi::boot::free_pages(self.ptr, self.pages);
+    }
+  }
+}
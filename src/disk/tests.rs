@@ -0,0 +1,317 @@
+use alloc::vec::Vec;
+
+use embedded_io::SeekFrom;
+
+use super::*;
+
+/// The block size of the test disks.
+const BLOCK_SIZE: u32 = 512;
+
+/// Builds a disk of `blocks` blocks, each filled with its own LBA.
+fn numbered_disk(blocks: u64) -> MemoryDisk {
+  let data = (0..blocks)
+    .flat_map(|lba| core::iter::repeat_n(lba as u8, BLOCK_SIZE as usize))
+    .collect();
+  MemoryDisk::new(data, BLOCK_SIZE).unwrap()
+}
+
+/// Reads through a cache from a disk, returning the bytes read and the
+/// requests made of the disk as `(lba, blocks)`.
+fn cached_read(cache: &mut BlockCache, disk: &MemoryDisk, offset: u64, count: usize) -> (Vec<u8>, Vec<(u64, usize)>) {
+  let mut buffer = alloc::vec![0; count];
+  let mut requests = Vec::new();
+  cache.read_into(offset, &mut buffer, BLOCK_SIZE, disk.last_block(), |offset, blocks| {
+    requests.push((offset / BLOCK_SIZE as u64, blocks.len() / BLOCK_SIZE as usize));
+    disk.read_at(offset, blocks)
+  }).unwrap();
+
+  (buffer, requests)
+}
+
+#[test]
+fn cache_serves_repeated_reads() {
+  let disk = numbered_disk(8);
+  let mut cache = BlockCache::new(4, 0);
+
+  let (data, requests) = cached_read(&mut cache, &disk, 512, 512);
+  assert!(data.iter().all(|byte| *byte == 1));
+  assert_eq!(requests, [(1, 1)]);
+
+  let (data, requests) = cached_read(&mut cache, &disk, 600, 8);
+  assert_eq!(data, [1; 8]);
+  assert!(requests.is_empty());
+
+  // Only the missing block of a read spanning two is requested
+  let (data, requests) = cached_read(&mut cache, &disk, 1020, 8);
+  assert_eq!(data, [1, 1, 1, 1, 2, 2, 2, 2]);
+  assert_eq!(requests, [(2, 1)]);
+
+  assert_eq!(cache.stats(), CacheStats { hits: 2, misses: 2, read_ahead: 0, evictions: 0 });
+}
+
+#[test]
+fn cache_evicts_least_recently_used() {
+  let disk = numbered_disk(8);
+  let mut cache = BlockCache::new(2, 0);
+
+  cached_read(&mut cache, &disk, 0, 512);
+  cached_read(&mut cache, &disk, 512, 512);
+  cached_read(&mut cache, &disk, 0, 512);
+  // Block 1 is now the least recently used
+  cached_read(&mut cache, &disk, 1024, 512);
+
+  assert!(cached_read(&mut cache, &disk, 0, 512).1.is_empty());
+  assert_eq!(cached_read(&mut cache, &disk, 512, 512).1, [(1, 1)]);
+  assert_eq!(cache.stats(), CacheStats { hits: 2, misses: 4, read_ahead: 0, evictions: 2 });
+}
+
+#[test]
+fn cache_reads_ahead_on_sequential_access() {
+  let disk = numbered_disk(16);
+  let mut cache = BlockCache::new(8, 3);
+
+  // Nothing is read ahead until reads are sequential
+  assert_eq!(cached_read(&mut cache, &disk, 0, 512).1, [(0, 1)]);
+  assert_eq!(cached_read(&mut cache, &disk, 512, 512).1, [(1, 4)]);
+  for lba in 2..5 {
+    let (data, requests) = cached_read(&mut cache, &disk, lba * 512, 512);
+    assert!(data.iter().all(|byte| *byte == lba as u8));
+    assert!(requests.is_empty());
+  }
+  assert_eq!(cache.stats(), CacheStats { hits: 3, misses: 2, read_ahead: 3, evictions: 0 });
+
+  // A read elsewhere is not sequential
+  assert_eq!(cached_read(&mut cache, &disk, 10 * 512, 512).1, [(10, 1)]);
+}
+
+#[test]
+fn cache_does_not_read_ahead_past_device() {
+  let disk = numbered_disk(4);
+  let mut cache = BlockCache::new(8, 8);
+
+  cached_read(&mut cache, &disk, 0, 512);
+  assert_eq!(cached_read(&mut cache, &disk, 512, 512).1, [(1, 3)]);
+  assert!(cached_read(&mut cache, &disk, 1536, 512).1.is_empty());
+
+  // Nor more than the cache can hold
+  let mut cache = BlockCache::new(2, 8);
+  cached_read(&mut cache, &disk, 0, 512);
+  assert_eq!(cached_read(&mut cache, &disk, 512, 512).1, [(1, 2)]);
+}
+
+#[test]
+fn cleared_cache_rereads() {
+  let disk = numbered_disk(4);
+  let mut cache = BlockCache::new(4, 0);
+
+  cached_read(&mut cache, &disk, 0, 1024);
+  cache.invalidate(1, 1);
+  assert_eq!(cached_read(&mut cache, &disk, 0, 1024).1, [(1, 1)]);
+
+  cache.clear();
+  assert_eq!(cached_read(&mut cache, &disk, 0, 1024).1, [(0, 2)]);
+}
+
+#[test]
+fn block_device_reads_units() {
+  let disk = numbered_disk(4);
+
+  assert_eq!(disk.read_block(2).unwrap(), [2; 512]);
+  assert_eq!(disk.read_sector(3).unwrap(), [3; 512]);
+  assert_eq!(disk.read_blocks(1, 2).unwrap()[511..513], [1, 2]);
+  assert_eq!(disk.read_sectors(0, 4).unwrap().len(), 2048);
+  assert_eq!(disk.read_bytes(1534, 4).unwrap(), [2, 2, 3, 3]);
+  assert!(disk.read_blocks(0, 0).unwrap().is_empty());
+
+  // Reads past the end of the device fail, rather than being truncated
+  assert_eq!(disk.read_block(4), Err(DISK_OUT_OF_RANGE));
+  assert_eq!(disk.read_blocks(3, 2), Err(DISK_OUT_OF_RANGE));
+  assert_eq!(disk.read_bytes(2047, 2), Err(DISK_OUT_OF_RANGE));
+  assert_eq!(disk.read_bytes(u64::MAX, 1), Err(DISK_OUT_OF_RANGE));
+}
+
+#[test]
+fn unit_ranges_do_not_overflow() {
+  assert_eq!(device::unit_range(3, 2, 512), Ok((1536, 1024)));
+  assert_eq!(device::unit_range(u64::MAX / 2, 1, 512), Err(DISK_OUT_OF_RANGE));
+  assert_eq!(device::unit_range(0, usize::MAX / 2, 512), Err(DISK_OUT_OF_RANGE));
+
+  let disk = numbered_disk(4);
+  assert_eq!(disk.read_block(u64::MAX / 2), Err(DISK_OUT_OF_RANGE));
+  assert_eq!(disk.read_sectors(0, usize::MAX / 2), Err(DISK_OUT_OF_RANGE));
+}
+
+#[test]
+fn block_spans_do_not_overflow() {
+  assert_eq!(device::block_span(511, 2, 512), Ok((0, 1)));
+  assert_eq!(device::block_span(u64::MAX, 1, 512), Ok((u64::MAX / 512, u64::MAX / 512)));
+  assert_eq!(device::block_span(u64::MAX, 2, 512), Err(DISK_OUT_OF_RANGE));
+  assert_eq!(device::block_span(0, 0, 512), Err(DISK_OUT_OF_RANGE));
+  assert_eq!(device::block_span(0, 1, 0), Err(Status::INVALID_PARAMETER));
+
+  // The cache is given offsets and extents straight from its caller
+  let mut cache = BlockCache::new(4, 2);
+  let mut buffer = [0; 4];
+  let mut read_blocks = |_: u64, blocks: &mut [u8]| {
+    blocks.fill(0xAB);
+    Ok(())
+  };
+  assert_eq!(cache.read_into(u64::MAX - 1, &mut buffer, BLOCK_SIZE, u64::MAX, &mut read_blocks), Err(DISK_OUT_OF_RANGE));
+  assert_eq!(cache.read_into(u64::MAX - 3, &mut buffer, BLOCK_SIZE, u64::MAX, &mut read_blocks), Ok(()));
+  assert_eq!(buffer, [0xAB; 4]);
+}
+
+#[test]
+fn memory_disk_extent() {
+  let disk = numbered_disk(4);
+  assert_eq!((disk.size(), disk.last_block()), (2048, 3));
+  assert_eq!(<&MemoryDisk as BlockDevice>::size(&&disk), 2048);
+
+  let empty = MemoryDisk::new(Vec::new(), BLOCK_SIZE).unwrap();
+  assert_eq!(empty.size(), 0);
+  assert_eq!(empty.read_block(0), Err(DISK_OUT_OF_RANGE));
+  assert!(empty.read_bytes(0, 0).unwrap().is_empty());
+
+  // A trailing partial block is readable, but is not a block
+  let partial = MemoryDisk::new([7u8; 700], BLOCK_SIZE).unwrap();
+  assert_eq!((partial.size(), partial.last_block()), (700, 0));
+  assert_eq!(partial.read_bytes(512, 188).unwrap(), [7; 188]);
+  assert_eq!(partial.read_block(1), Err(DISK_OUT_OF_RANGE));
+  assert_eq!(partial.into_inner().len(), 700);
+
+  // A device without a block size has no blocks to count
+  assert_eq!(MemoryDisk::new([0u8; 512], 0).err(), Some(Status::INVALID_PARAMETER));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn image_file_reads_host_files() {
+  let path = std::env::temp_dir().join(std::format!("wakatiwai-udive-image-{}.img", std::process::id()));
+  std::fs::write(&path, numbered_disk(3).data()).unwrap();
+  let image = ImageFile::open(&path, BLOCK_SIZE);
+  let unsized_image = ImageFile::open(&path, 0);
+  std::fs::remove_file(&path).unwrap();
+  assert_eq!(unsized_image.err().map(|err| err.kind()), Some(std::io::ErrorKind::InvalidInput));
+  let image = image.unwrap();
+
+  assert_eq!((image.size(), image.last_block()), (1536, 2));
+  assert_eq!(image.read_block(1).unwrap(), [1; 512]);
+  assert_eq!(image.read_bytes(1020, 8).unwrap(), [1, 1, 1, 1, 2, 2, 2, 2]);
+  assert_eq!(image.read_block(3), Err(DISK_OUT_OF_RANGE));
+  assert_eq!(image.read_bytes(u64::MAX, 2), Err(DISK_OUT_OF_RANGE));
+
+  assert!(ImageFile::open(&path, BLOCK_SIZE).is_err());
+}
+
+/// Builds a device holding the bytes `0..len`, wrapping at 256.
+fn counting_disk(len: usize) -> MemoryDisk {
+  MemoryDisk::new((0..len).map(|i| i as u8).collect(), BLOCK_SIZE).unwrap()
+}
+
+#[test]
+fn cursor_reads_partial_trailing_block() {
+  let mut cursor = DiskCursor::new(counting_disk(700));
+  assert_eq!(cursor.len(), 700);
+
+  cursor.set_position(690);
+  let mut buffer = [0; 16];
+  assert_eq!(cursor.read(&mut buffer), Ok(10));
+  assert_eq!(buffer[..10], [178, 179, 180, 181, 182, 183, 184, 185, 186, 187]);
+  assert_eq!(cursor.read(&mut buffer), Ok(0));
+  assert_eq!(cursor.position(), 700);
+}
+
+#[test]
+fn cursor_on_empty_device_is_at_eof() {
+  let mut cursor = DiskCursor::new(MemoryDisk::new(Vec::new(), BLOCK_SIZE).unwrap());
+  assert!(cursor.is_empty());
+  assert_eq!(cursor.read(&mut [0; 4]), Ok(0));
+  assert_eq!(cursor.read_u8(), Err(UdiveError::disk(DISK_OUT_OF_RANGE)));
+  assert_eq!(cursor.seek(SeekFrom::End(0)), Ok(0));
+}
+
+#[test]
+fn cursor_seeks() {
+  let disk = counting_disk(1024);
+  let mut cursor = DiskCursor::new(&disk);
+
+  assert_eq!(cursor.seek(SeekFrom::Start(100)), Ok(100));
+  assert_eq!(cursor.seek(SeekFrom::Current(-50)), Ok(50));
+  assert_eq!(cursor.skip(14), Ok(64));
+  assert_eq!(cursor.read_u8(), Ok(64));
+  assert_eq!(cursor.seek(SeekFrom::End(-1)), Ok(1023));
+  assert_eq!(cursor.read_u8(), Ok(255));
+
+  // Seeking past the end is allowed, but nothing can be read there
+  assert_eq!(cursor.seek(SeekFrom::End(10)), Ok(1034));
+  assert_eq!(cursor.read(&mut [0; 4]), Ok(0));
+
+  // Positions before the start or past `u64::MAX` are not
+  assert_eq!(cursor.seek(SeekFrom::Current(-2000)), Err(UdiveError::disk(DISK_OUT_OF_RANGE)));
+  assert_eq!(cursor.seek(SeekFrom::End(-1025)), Err(UdiveError::disk(DISK_OUT_OF_RANGE)));
+  cursor.set_position(u64::MAX);
+  assert_eq!(cursor.skip(1), Err(UdiveError::disk(DISK_OUT_OF_RANGE)));
+  assert_eq!(cursor.skip(u64::MAX), Err(UdiveError::disk(DISK_OUT_OF_RANGE)));
+  assert_eq!(cursor.position(), u64::MAX);
+}
+
+#[test]
+fn cursor_read_exact_stops_at_eof() {
+  let mut cursor = DiskCursor::new(counting_disk(600));
+
+  cursor.set_position(596);
+  assert_eq!(cursor.read_array::<4>(), Ok([84, 85, 86, 87]));
+  assert_eq!(cursor.position(), 600);
+
+  // The position is left unchanged by a failed read
+  cursor.set_position(598);
+  assert_eq!(cursor.read_u32_le(), Err(UdiveError::disk(DISK_OUT_OF_RANGE)));
+  assert_eq!(cursor.read_bytes(3), Err(UdiveError::disk(DISK_OUT_OF_RANGE)));
+  assert_eq!(cursor.position(), 598);
+  assert_eq!(cursor.read_bytes(2), Ok([86, 87].to_vec()));
+
+  cursor.set_position(u64::MAX);
+  assert_eq!(cursor.read_u8(), Err(UdiveError::disk(DISK_OUT_OF_RANGE)));
+}
+
+#[test]
+fn cursor_reads_integers() {
+  let mut data = alloc::vec![0u8; 64];
+  data[..30].copy_from_slice(&[
+    0xFE,
+    0x34, 0x12,
+    0x12, 0x34,
+    0x78, 0x56, 0x34, 0x12,
+    0x12, 0x34, 0x56, 0x78,
+    0xEF, 0xCD, 0xAB, 0x89, 0x67, 0x45, 0x23, 0x01,
+    0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFE,
+    0x80, 0x00, 0x00
+  ]);
+  let mut cursor = DiskCursor::new(MemoryDisk::new(data, BLOCK_SIZE).unwrap());
+
+  assert_eq!(cursor.read_i8(), Ok(-2));
+  assert_eq!(cursor.read_u16_le(), Ok(0x1234));
+  assert_eq!(cursor.read_u16_be(), Ok(0x1234));
+  assert_eq!(cursor.read_u32_le(), Ok(0x1234_5678));
+  assert_eq!(cursor.read_u32_be(), Ok(0x1234_5678));
+  assert_eq!(cursor.read_u64_le(), Ok(0x0123_4567_89AB_CDEF));
+  assert_eq!(cursor.read_i16_le(), Ok(-1));
+  assert_eq!(cursor.read_i32_be(), Ok(-2));
+  assert_eq!(cursor.position(), 27);
+  assert_eq!(cursor.read_i64_be(), Ok(i64::MIN));
+  assert_eq!(cursor.read_u64_be(), Ok(0));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn cursor_reads_to_end_through_std_io() {
+  use std::io::{Read, Seek};
+
+  let mut cursor = DiskCursor::new(counting_disk(700));
+  assert_eq!(Seek::seek(&mut cursor, std::io::SeekFrom::Start(200)).unwrap(), 200);
+
+  let mut data = Vec::new();
+  assert_eq!(Read::read_to_end(&mut cursor, &mut data).unwrap(), 500);
+  assert_eq!(data[0], 200);
+}
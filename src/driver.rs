@@ -2,11 +2,15 @@ use uefi::boot::MemoryType;
 use uefi::mem::memory_map::MemoryMap;
 use uefi::Status;
 
-use crate::io::DriverIO;
+use crate::io::{DriverCapabilities, DriverIO, DRIVER_IO_ABI_VERSION};
 use crate::*;
 
 /// Detects if a given memory type has been allocated.
 /// 
+/// Once found, the [`DriverIO`] header is validated against the ABI this
+/// driver was built with, and the driver's ABI version is written back to
+/// acknowledge it.
+/// 
 /// # Arguments
 /// 
 /// - `memtype` (`MemoryType`) - The memory type in question, either
@@ -15,19 +19,44 @@ use crate::*;
 /// # Returns
 /// 
 /// - [`uefi_raw::Status::NOT_FOUND`] if the memory type has not been allocated.
+/// - [`uefi_raw::Status::INCOMPATIBLE_VERSION`] if the memory type has been
+///   allocated, but was written with an incompatible ABI.
 /// - [`uefi_raw::Status::SUCCESS`] if the memory type has been allocated.
+/// - The error of `GetMemoryMap` if the memory map could not be read.
 /// 
 /// # Safety
 /// This function is unsafe because it must access a `static mut` variable.
 pub unsafe fn find_io_memory(memtype: MemoryType) -> Status {
+  let capabilities = match memtype {
+    BOOT_DRIVER_IO_MEMTYPE => DriverCapabilities::BOOT_ARGS,
+    FSYS_DRIVER_IO_MEMTYPE => DriverCapabilities::FS_ARGS | DriverCapabilities::FS_OPS,
+    _ => DriverCapabilities::NONE
+  };
+
+  let memory_map = match uefi::boot::memory_map(MemoryType::LOADER_DATA) {
+    Ok(ok) => ok,
+    Err(err) => {
+      return err.status();
+    }
+  };
+
   // Iterate over the memory map to detect the buffers
-  for mement in uefi::boot::memory_map(MemoryType::LOADER_DATA).unwrap().entries() {
+  for mement in memory_map.entries() {
     if mement.ty == memtype {
-      DRIVER_IO = Some(mement.phys_start as *mut DriverIO);
+      let dio = mement.phys_start as *mut DriverIO;
+
+      // Refuse to touch anything beyond the header if the ABI differs
+      let validate_status = (*dio).header.validate(capabilities);
+      if validate_status.is_error() {
+        return validate_status;
+      }
+      (*dio).header.driver_abi_version = DRIVER_IO_ABI_VERSION;
+
+      DRIVER_IO = Some(dio);
       return Status::SUCCESS;
     }
   }
 
   // The buffer wasn't found
   Status::NOT_FOUND
-}
\ No newline at end of file
+}
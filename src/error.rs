@@ -0,0 +1,164 @@
+use core::fmt::{Debug, Display, Formatter};
+
+use alloc::string::String;
+use uefi::Status;
+
+#[derive(Clone, Debug, PartialEq)]
+/// The details carried by every [`UdiveError`].
+pub struct ErrorInfo {
+  /// The underlying status.
+  pub status: Status,
+  /// The name of the driver involved, if any.
+  pub driver: Option<String>,
+  /// A description of what failed, if more is known than the status.
+  pub message: Option<String>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// An error raised by the loader-facing API of this crate.
+/// 
+/// Drivers themselves report a plain `Status`, as that is what crosses the
+/// driver ABI. When invoking a driver, that status is carried by
+/// [`UdiveError::Driver`].
+pub enum UdiveError {
+  /// Drivers could not be found, or a driver directory could not be read.
+  Discovery(ErrorInfo),
+  /// A driver's image could not be loaded, verified or unloaded, or it was
+  /// invoked in the wrong state.
+  Load(ErrorInfo),
+  /// Memory to pass arguments to or results from a driver could not be
+  /// allocated or freed.
+  IoAllocation(ErrorInfo),
+  /// A driver was built against a different driver ABI, or its output was
+  /// malformed.
+  AbiMismatch(ErrorInfo),
+  /// A driver ran, but reported failure.
+  Driver(ErrorInfo),
+  /// A disk could not be opened, read or written.
+  Disk(ErrorInfo),
+  /// A firmware service failed outside of any of the above.
+  Firmware(ErrorInfo)
+}
+
+/// Defines the constructor of a [`UdiveError`] variant.
+macro_rules! constructor {
+  ($name:ident, $variant:ident) => {
+    #[doc = concat!("Creates a [`UdiveError::", stringify!($variant), "`] from a status.")]
+    pub fn $name(status: Status) -> UdiveError {
+      UdiveError::$variant(
+        ErrorInfo {
+          status,
+          driver: None,
+          message: None
+        }
+      )
+    }
+  };
+}
+
+impl UdiveError {
+  constructor!(discovery, Discovery);
+  constructor!(load, Load);
+  constructor!(io_allocation, IoAllocation);
+  constructor!(abi_mismatch, AbiMismatch);
+  constructor!(driver, Driver);
+  constructor!(disk, Disk);
+  constructor!(firmware, Firmware);
+
+  /// Returns the details of the error.
+  pub fn info(&self) -> &ErrorInfo {
+    match self {
+      UdiveError::Discovery(info)
+      | UdiveError::Load(info)
+      | UdiveError::IoAllocation(info)
+      | UdiveError::AbiMismatch(info)
+      | UdiveError::Driver(info)
+      | UdiveError::Disk(info)
+      | UdiveError::Firmware(info) => info
+    }
+  }
+
+  /// Returns the details of the error, mutably.
+  fn info_mut(&mut self) -> &mut ErrorInfo {
+    match self {
+      UdiveError::Discovery(info)
+      | UdiveError::Load(info)
+      | UdiveError::IoAllocation(info)
+      | UdiveError::AbiMismatch(info)
+      | UdiveError::Driver(info)
+      | UdiveError::Disk(info)
+      | UdiveError::Firmware(info) => info
+    }
+  }
+
+  /// Returns the underlying status.
+  pub fn status(&self) -> Status {
+    self.info().status
+  }
+
+  /// Returns the name of the driver involved, if any.
+  pub fn driver_name(&self) -> Option<&str> {
+    self.info().driver.as_deref()
+  }
+
+  /// Returns the description of what failed, if any.
+  pub fn message(&self) -> Option<&str> {
+    self.info().message.as_deref()
+  }
+
+  /// Attaches the name of the driver involved.
+  pub fn with_driver(mut self, driver: impl Into<String>) -> UdiveError {
+    self.info_mut().driver = Some(driver.into());
+    self
+  }
+
+  /// Attaches a description of what failed.
+  pub fn with_message(mut self, message: impl Into<String>) -> UdiveError {
+    self.info_mut().message = Some(message.into());
+    self
+  }
+
+  /// Returns a short description of the kind of error.
+  fn kind(&self) -> &'static str {
+    match self {
+      UdiveError::Discovery(_) => "driver discovery failed",
+      UdiveError::Load(_) => "driver load failed",
+      UdiveError::IoAllocation(_) => "driver IO allocation failed",
+      UdiveError::AbiMismatch(_) => "driver ABI mismatch",
+      UdiveError::Driver(_) => "driver reported an error",
+      UdiveError::Disk(_) => "disk access failed",
+      UdiveError::Firmware(_) => "firmware call failed"
+    }
+  }
+}
+
+impl Display for UdiveError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+    write!(f, "{}", self.kind())?;
+    if let Some(driver) = self.driver_name() {
+      write!(f, " ({})", driver)?;
+    }
+    write!(f, ": {:?}", self.status())?;
+    if let Some(message) = self.message() {
+      write!(f, ": {}", message)?;
+    }
+
+    Ok(())
+  }
+}
+
+impl core::error::Error for UdiveError {}
+
+impl<T: Debug> From<uefi::Error<T>> for UdiveError {
+  fn from(err: uefi::Error<T>) -> UdiveError {
+    UdiveError::firmware(err.status())
+  }
+}
+
+impl From<UdiveError> for Status {
+  /// Allows a [`UdiveError`] to be reported from code returning a `Status`,
+  /// such as a driver.
+  fn from(err: UdiveError) -> Status {
+    err.status()
+  }
+}
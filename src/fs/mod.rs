@@ -1,16 +1,182 @@
+mod ops;
+mod session;
+mod simple_fs;
+#[cfg(test)]
+mod tests;
+
 use core::ffi::c_void;
+use core::marker::PhantomData;
+
+use uefi::Handle;
+
+use crate::io::DriverCapabilities;
+use crate::disk::{BlockDevice, DiskReader};
+use crate::*;
 
-use crate::{disk::DiskReader, *};
+pub use ops::{dispatch, DirEntry, FSDriverOps, FSOperation, FSOutput, FileKind, FileStat, ProbeResult};
+pub use session::{dispatch_session, install_session, FSSession, FSSessionOps, FSSessionProtocol, RawFSRequest};
 
 /// Input arguments for a file system driver.
-pub struct FSDriverArgs<'a> {
+/// 
+/// Drivers are always invoked with a [`DiskReader`], but file system parsing
+/// code may be written against any [`BlockDevice`] so that it can be tested
+/// on the host.
+pub struct FSDriverArgs<'a, D: BlockDevice = DiskReader> {
   /// The path containing the file to be read.
   pub path: &'a str,
-  /// An instance to a [`DiskReader`] to be used in reading the file.
-  pub diskreader: DiskReader
+  /// The operation to perform on `path`.
+  pub op: FSOperation,
+  /// An instance to a [`BlockDevice`] to be used in reading the file.
+  pub diskreader: D
+}
+
+impl<'a, D: BlockDevice> FSDriverArgs<'a, D> {
+  /// Creates arguments to read a file from any [`BlockDevice`].
+  /// 
+  /// # Arguments
+  /// 
+  /// - `path` (`&str`) - The path containing the file to be read.
+  /// - `diskreader` (`D`) - The device to read the file from.
+  pub fn new(path: &'a str, diskreader: D) -> FSDriverArgs<'a, D> {
+    FSDriverArgs::with_op(path, FSOperation::Read, diskreader)
+  }
+
+  /// Creates arguments to perform any operation on a path.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `path` (`&str`) - The path to operate on.
+  /// - `op` (`FSOperation`) - The operation to perform.
+  /// - `diskreader` (`D`) - The device containing the file system.
+  pub fn with_op(path: &'a str, op: FSOperation, diskreader: D) -> FSDriverArgs<'a, D> {
+    FSDriverArgs {
+      path,
+      op,
+      diskreader
+    }
+  }
+}
+
+impl<'a> FSDriverArgs<'a> {
+  /// Converts these arguments into their ABI-stable representation.
+  /// 
+  /// The [`DiskReader`] is not passed to the driver directly, rather the
+  /// driver reopens it on the same handle with the same extent.
+  pub fn to_raw(&self) -> RawFSDriverArgs<'a> {
+    RawFSDriverArgs::new(self.path, self.op, &self.diskreader)
+  }
+
+  /// Recovers arguments from their ABI-stable representation.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(FSDriverArgs)` on success.
+  /// - `Err(Status::INVALID_PARAMETER)` if the path is not valid UTF-8 or the
+  ///   handle is null.
+  /// - `Err(Status::UNSUPPORTED)` if the operation is unknown.
+  /// - `Err(Status)` if the [`DiskReader`] could not be reopened.
+  /// 
+  /// # Safety
+  /// The pointers in `raw` must be valid for the lifetime `'a`.
+  pub unsafe fn from_raw(raw: &RawFSDriverArgs<'a>) -> Result<FSDriverArgs<'a>, Status> {
+    let path = core::slice::from_raw_parts(raw.path_ptr, raw.path_len);
+    let op = FSOperation::from_raw(raw.op, raw.range_offset, raw.range_len)?;
+    let handle = match Handle::from_ptr(raw.handle) {
+      Some(some) => some,
+      None => {
+        return Err(Status::INVALID_PARAMETER);
+      }
+    };
+
+    let mut diskreader = DiskReader::open(&handle, raw.abs_offset)?;
+    diskreader.last_block = raw.last_block;
+
+    Ok(
+      FSDriverArgs {
+        path: core::str::from_utf8(path).map_err(|_| Status::INVALID_PARAMETER)?,
+        op,
+        diskreader
+      }
+    )
+  }
+}
+
+#[repr(C)]
+/// The ABI-stable representation of [`FSDriverArgs`], as passed through a
+/// [`DriverIO`].
+pub struct RawFSDriverArgs<'a> {
+  /// A pointer to the UTF-8 path of the file to be read.
+  pub path_ptr: *const u8,
+  /// The length of the path in bytes.
+  pub path_len: usize,
+  /// The handle on which the [`DiskReader`] was opened.
+  pub handle: *mut c_void,
+  /// The offset within the disk to read from.
+  pub abs_offset: u64,
+  /// The final LBA of the partition.
+  pub last_block: u64,
+  /// The code of the [`FSOperation`] to perform.
+  pub op: u32,
+  /// Reserved, must be zero.
+  pub reserved: u32,
+  /// The offset of a [`FSOperation::ReadRange`].
+  pub range_offset: u64,
+  /// The length of a [`FSOperation::ReadRange`].
+  pub range_len: u64,
+  _marker: PhantomData<&'a str>
+}
+
+impl<'a> RawFSDriverArgs<'a> {
+  /// Creates arguments to perform an operation on a path, with the
+  /// [`DiskReader`] to be reopened by the driver.
+  fn new(path: &'a str, op: FSOperation, diskreader: &DiskReader) -> RawFSDriverArgs<'a> {
+    let (op, range_offset, range_len) = op.to_raw();
+    RawFSDriverArgs {
+      path_ptr: path.as_ptr(),
+      path_len: path.len(),
+      handle: diskreader.handle().as_ptr(),
+      abs_offset: diskreader.abs_offset,
+      last_block: diskreader.last_block,
+      op,
+      reserved: 0,
+      range_offset,
+      range_len,
+      _marker: PhantomData
+    }
+  }
 }
 
 impl FSDriver {
+  /// Creates a file system driver from an EFI image in memory, rather than on disk.
+  /// 
+  /// This allows drivers to be embedded in the loader (see
+  /// [`crate::include_driver`]) or fetched from elsewhere, e.g. read from
+  /// another volume through a file system driver.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `name` (`&str`) - The name of the driver (e.g. `fat`).
+  /// - `buffer` - The contents of the driver's EFI file.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(FSDriver)` on success.
+  /// - `Err(UdiveError::Load)` with `INVALID_PARAMETER` if `name` cannot be
+  ///   represented in UCS-2.
+  pub fn from_buffer(name: &str, buffer: impl Into<Cow<'static, [u8]>>) -> Result<FSDriver, UdiveError> {
+    Ok(FSDriver(Driver::from_buffer(name, DriverType::FS, buffer.into())?))
+  }
+
+  /// Attaches a detached signature to a file system driver created from a buffer,
+  /// checked against the [`crate::verify::signature_policy`] when it is
+  /// loaded.
+  /// 
+  /// Drivers on disk are signed by a `.sig` file alongside them instead.
+  pub fn with_signature(mut self, signature: impl Into<Cow<'static, [u8]>>) -> FSDriver {
+    self.0.signature = Some(signature.into());
+    self
+  }
+
   /// Prints the name of this file system driver.
   /// 
   /// Usually, this will just be the name of the file system to read (e.g.
@@ -19,16 +185,69 @@ impl FSDriver {
     self.0.name()
   }
 
+  /// Returns the manifest accompanying this file system driver, if it has one.
+  pub fn manifest(&self) -> Option<&DriverManifest> {
+    self.0.manifest.as_ref()
+  }
+
+  /// Returns the type of this driver, which is always [`DriverType::FS`].
+  pub fn driver_type(&self) -> DriverType {
+    DriverType::FS
+  }
+
+  /// Returns the path of this file system driver's EFI file, relative to the root of
+  /// the volume it resides on (e.g. `\EFI\wakatiwai\drivers\fs\name.efi`).
+  /// This is empty for drivers created from a buffer.
+  pub fn path(&self) -> CString16 {
+    self.0.path().unwrap_or_default()
+  }
+
+  /// Returns whether this file system driver is currently loaded.
+  pub fn is_loaded(&self) -> bool {
+    self.0.is_loaded()
+  }
+
   /// Loads this file system driver.
-  pub fn load(&mut self) -> Status {
+  pub fn load(&mut self) -> Result<(), UdiveError> {
     self.0.load()
   }
 
   /// Unloads this file system driver.
-  pub fn unload(&mut self) -> Status {
+  pub fn unload(&mut self) -> Result<(), UdiveError> {
     self.0.unload()
   }
 
+  /// Unloads this file system driver if it is loaded, and loads it again, so that it
+  /// may be invoked after it has exited.
+  pub fn reload(&mut self) -> Result<(), UdiveError> {
+    self.0.reload()
+  }
+
+  /// Returns the lifecycle state of this file system driver.
+  pub fn state(&self) -> DriverState {
+    self.0.state()
+  }
+
+  /// Returns the records logged by this file system driver during its last
+  /// invocation.
+  pub fn log(&self) -> &DriverLog {
+    &self.0.log
+  }
+
+  /// Sets the timeout applied when invoking this file system driver, in seconds,
+  /// overriding [`crate::watchdog::invocation_timeout`].
+  /// 
+  /// A timeout of `Some(0)` disables the timeout for this driver, and `None`
+  /// restores the default.
+  pub fn set_timeout(&mut self, seconds: Option<usize>) {
+    self.0.timeout = seconds;
+  }
+
+  /// Returns the timeout set for this file system driver, if any.
+  pub fn timeout(&self) -> Option<usize> {
+    self.0.timeout
+  }
+
   /// Invokes this file system driver.
   /// 
   /// # Arguments
@@ -37,31 +256,132 @@ impl FSDriver {
   /// 
   /// # Returns
   /// 
-  /// - `Ok(&[u8])` on a successful invokation and execution of the file system
-  ///   driver, with the file's contents stored in the slice.
-  /// - `Err(Ok(Status))` on a successful invokation but failed execution of
-  ///   the file system driver.
-  /// - `Err(Err(Status))` on a failed invokation of the file system driver.
-  pub fn invoke(&mut self, args: &mut FSDriverArgs) -> Result<&[u8], Result<Status, Status>> {
-    let mut dio = DriverIO {
-      inptr:  args as *mut FSDriverArgs as *mut c_void,
-      outptr: core::ptr::null_mut()
-    };
-    
-    let invoke_status = self.0.invoke(&mut dio, FSYS_DRIVER_IO_MEMTYPE);
-
-    if invoke_status.is_ok_and(|t| t.is_success()) {
-      return Ok(
-        unsafe {
-          alloc::slice::from_raw_parts(
-            dio.outptr.add(size_of::<usize>()) as *mut u8,
-            *(dio.outptr as *mut usize)
-          )
-        }
+  /// - `Ok(FSOutput)` on a successful invokation and execution of the file
+  ///   system driver, containing the result of `args.op`. For reads, the
+  ///   file's contents are stored in the slice.
+  /// - `Err(UdiveError::Driver)` on a successful invokation but failed
+  ///   execution of the file system driver, carrying the status it returned.
+  /// - `Err(UdiveError::AbiMismatch)` if the driver was built against a
+  ///   different ABI, or its output is malformed.
+  /// - `Err(UdiveError::Load)` if the driver is not loaded, or has already
+  ///   been invoked since it was loaded ([`DRIVER_NOT_REENTRANT`]).
+  /// - `Err(UdiveError::IoAllocation)` if the arguments could not be passed
+  ///   to the driver.
+  /// - `Err(UdiveError::Firmware)` if the driver has a timeout, but the
+  ///   watchdog could not be armed (see [`crate::watchdog`]).
+  /// 
+  /// Records logged by the driver are available from [`FSDriver::log`]
+  /// afterwards.
+  pub fn invoke(&mut self, args: &mut FSDriverArgs) -> Result<FSOutput<'_>, UdiveError> {
+    self.invoke_raw(args.to_raw(), args.op)
+  }
+
+  /// Checks whether a partition holds a file system this driver can read.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `diskreader` (`&DiskReader`) - The partition to probe.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(ProbeResult)` on a successful probe.
+  /// - `Err(UdiveError::Driver)` on a successful invokation but failed
+  ///   probe. This carries [`uefi_raw::Status::UNSUPPORTED`] if the driver
+  ///   cannot be probed.
+  /// - Otherwise, as with [`FSDriver::invoke`].
+  pub fn probe(&mut self, diskreader: &DiskReader) -> Result<ProbeResult, UdiveError> {
+    match self.invoke_raw(RawFSDriverArgs::new("", FSOperation::Probe, diskreader), FSOperation::Probe)? {
+      FSOutput::Probe(probe) => Ok(probe),
+      _ => Err(self.malformed_output())
+    }
+  }
+
+  /// Invokes this file system driver with raw arguments.
+  fn invoke_raw(&mut self, mut raw: RawFSDriverArgs, op: FSOperation) -> Result<FSOutput<'_>, UdiveError> {
+    let mut dio = DriverIO::new(
+      DriverCapabilities::FS_ARGS | DriverCapabilities::FS_OPS,
+      &mut raw as *mut RawFSDriverArgs as *mut c_void,
+      size_of::<RawFSDriverArgs>()
+    );
+
+    self.0.invoke(&mut dio, FSYS_DRIVER_IO_MEMTYPE)?;
+
+    let output = unsafe {
+      alloc::slice::from_raw_parts(
+        dio.outptr as *const u8,
+        dio.outsize
       )
+    };
+
+    let malformed_output = self.malformed_output();
+    FSOutput::from_bytes(op, output).map_err(|_| malformed_output)
+  }
+
+  /// Returns the error raised when this driver's output is malformed.
+  fn malformed_output(&self) -> UdiveError {
+    UdiveError::abi_mismatch(Status::VOLUME_CORRUPTED)
+      .with_driver(self.name())
+      .with_message("malformed driver output")
+  }
+
+  /// Mounts a file system with this driver.
+  /// 
+  /// The driver is started once, and stays resident to serve requests
+  /// through the returned [`FSSession`] until it is unmounted. Only drivers
+  /// set up with `fs_prelude!(session T)` and built as boot service drivers
+  /// support this.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `diskreader` (`&DiskReader`) - The partition to mount.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(FSSession)` on a successful mount.
+  /// - `Err(UdiveError::Driver)` on a successful invokation but failed
+  ///   mount. This carries [`uefi_raw::Status::UNSUPPORTED`] if the driver
+  ///   cannot be mounted.
+  /// - Otherwise, as with [`FSDriver::invoke`].
+  pub fn mount(&mut self, diskreader: &DiskReader) -> Result<FSSession<'_>, UdiveError> {
+    self.mount_with(diskreader, DriverCapabilities::NONE)
+  }
+
+  /// Mounts a file system with this driver, and exposes it through the
+  /// `EFI_SIMPLE_FILE_SYSTEM_PROTOCOL`.
+  /// 
+  /// The protocol is installed on the handle of `diskreader`, so that other
+  /// EFI applications may read the file system with the standard file API
+  /// (e.g. `uefi::boot::get_image_file_system`). It is read-only, and is
+  /// uninstalled when the session is unmounted.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `diskreader` (`&DiskReader`) - The partition to mount.
+  /// 
+  /// # Returns
+  /// 
+  /// - As with [`FSDriver::mount`]. The driver fails with
+  ///   [`uefi_raw::Status::INVALID_PARAMETER`] if the handle already has a
+  ///   file system protocol installed.
+  pub fn mount_simple_fs(&mut self, diskreader: &DiskReader) -> Result<FSSession<'_>, UdiveError> {
+    self.mount_with(diskreader, DriverCapabilities::FS_SIMPLE_FS)
+  }
+
+  /// Mounts a file system, requesting any extra capabilities.
+  fn mount_with(&mut self, diskreader: &DiskReader, capabilities: DriverCapabilities) -> Result<FSSession<'_>, UdiveError> {
+    let mut raw = RawFSDriverArgs::new("", FSOperation::Read, diskreader);
+    let mut dio = DriverIO::new(
+      DriverCapabilities::FS_ARGS | DriverCapabilities::FS_OPS | DriverCapabilities::FS_SESSION | capabilities,
+      &mut raw as *mut RawFSDriverArgs as *mut c_void,
+      size_of::<RawFSDriverArgs>()
+    );
+
+    self.0.invoke(&mut dio, FSYS_DRIVER_IO_MEMTYPE)?;
+
+    match unsafe { Handle::from_ptr(dio.outptr) } {
+      Some(some) => FSSession::open(some).map_err(|err| err.with_driver(self.name())),
+      None => Err(self.malformed_output())
     }
-    
-    return Err(invoke_status);
   }
 }
 
@@ -70,21 +390,38 @@ impl FSDriver {
 /// driver.
 /// 
 /// An entry point `_entry` is defined and will recapture the
-/// [`FSDriverArgs`] that the driver was invoked with. It will then start a
-/// `main` method (the entry point of the driver, for the purposes of the
-/// programmer) with these arguments.
+/// [`FSDriverArgs`] that the driver was invoked with. The requested
+/// [`FSOperation`] is then dispatched to the matching handler of the given
+/// type, which must implement [`FSDriverOps`]. If `wakatiwai` was built
+/// against a different driver ABI, the driver exits with
+/// [`uefi_raw::Status::INCOMPATIBLE_VERSION`] without calling any handler.
+/// 
+/// If no type is given, a `main` method (the entry point of the driver, for
+/// the purposes of the programmer) is used as the [`FSDriverOps::read`]
+/// handler, with the other operations derived from it.
+/// 
+/// With `fs_prelude!(session T)`, `T` must instead implement
+/// [`FSSessionOps`]. The file system is mounted for every invocation, and
+/// when the driver is mounted with [`FSDriver::mount`], a
+/// [`FSSessionProtocol`] serving it is installed before the driver exits,
+/// along with an `EFI_SIMPLE_FILE_SYSTEM_PROTOCOL` on the partition for
+/// [`FSDriver::mount_simple_fs`].
 /// 
 /// Since this driver must necessarily exit, it will return either a SUCCESS
-/// with the file's contents stored in the driver's [`DriverIO`] or a failure
-/// otherwise.
+/// with the operation's output stored in the driver's [`DriverIO`] or a
+/// failure otherwise.
+/// 
+/// Records logged through the `log` macros (re-exported as
+/// `wakatiwai_udive::log`) are passed to `wakatiwai`, see
+/// [`crate::logging::DriverLogger`].
 macro_rules! fs_prelude {
-  () => {
+  (@entry |$dio:ident, $args:ident| $dispatch:block) => {
     extern crate alloc;
     use alloc::vec::Vec;
 
     use uefi::Status;
 
-    use wakatiwai_udive::fs::FSDriverArgs;
+    use wakatiwai_udive::fs::{FSDriverArgs, RawFSDriverArgs};
 
     #[uefi::entry]
     #[allow(unsafe_op_in_unsafe_fn)]
@@ -96,28 +433,83 @@ macro_rules! fs_prelude {
       if find_io_mem_status.is_error() {
         return find_io_mem_status;
       }
-      let dio = wakatiwai_udive::io::DriverIO::allocated_driver_io().unwrap();
-
-      let main_status = main(unsafe {
-        core::mem::transmute::<*mut core::ffi::c_void, *mut FSDriverArgs>(dio.inptr).as_ref().unwrap()
-      });
-
-      if main_status.is_ok() {
-        let filevec = main_status.unwrap();
-        // Allocate space for the size and content of filevec, return the allocated pointer
-        let vecptr = uefi::boot::allocate_pages(
-          uefi::boot::AllocateType::AnyPages,
-          uefi::boot::MemoryType::LOADER_DATA,
-          (filevec.len() + size_of::<usize>() + uefi::boot::PAGE_SIZE - 1) / uefi::boot::PAGE_SIZE
-        ).unwrap();
-        *(vecptr.as_ptr() as *mut usize) = filevec.len();
-        core::ptr::copy(filevec.as_ptr(), vecptr.as_ptr().add(size_of::<usize>()), filevec.len());
-        dio.outptr = vecptr.as_ptr() as *mut core::ffi::c_void;
-
-        return Status::SUCCESS;
+      let $dio = wakatiwai_udive::io::DriverIO::allocated_driver_io().unwrap();
+      let _logger = wakatiwai_udive::logging::attach_driver_logger($dio);
+
+      let $args = match $dio.args::<RawFSDriverArgs>().and_then(|raw| FSDriverArgs::from_raw(raw)) {
+        Ok(ok) => ok,
+        Err(err) => {
+          return err;
+        }
+      };
+
+      let op_status: Result<Vec<u8>, Status> = $dispatch;
+
+      match op_status {
+        Ok(ok) => $dio.set_output(&ok),
+        Err(err) => err
       }
+    }
+  };
+  () => {
+    /// Adapts `main` to the [`wakatiwai_udive::fs::FSDriverOps`] handlers.
+    struct MainFSDriver;
 
-      main_status.err().unwrap()
+    impl wakatiwai_udive::fs::FSDriverOps for MainFSDriver {
+      fn read(args: &wakatiwai_udive::fs::FSDriverArgs) -> Result<alloc::vec::Vec<u8>, uefi::Status> {
+        main(args)
+      }
     }
+
+    wakatiwai_udive::fs_prelude!(MainFSDriver);
+  };
+  (session $session:ty) => {
+    wakatiwai_udive::fs_prelude!(@entry |dio, args| {
+      let partition = args.diskreader.handle();
+      let FSDriverArgs { path, op, diskreader } = args;
+
+      // Probing must not mount the file system
+      if let wakatiwai_udive::fs::FSOperation::Probe = op {
+        return match <$session as wakatiwai_udive::fs::FSSessionOps>::probe(&diskreader) {
+          Ok(ok) => dio.set_output(&ok.to_bytes()),
+          Err(err) => err
+        };
+      }
+
+      let mut session = match <$session as wakatiwai_udive::fs::FSSessionOps>::mount(diskreader) {
+        Ok(ok) => ok,
+        Err(err) => {
+          return err;
+        }
+      };
+
+      // Stay mounted, serving requests through the installed protocol
+      if dio.header.capabilities.contains(wakatiwai_udive::io::DriverCapabilities::FS_SESSION) {
+        let simple_fs = dio.header.capabilities
+          .contains(wakatiwai_udive::io::DriverCapabilities::FS_SIMPLE_FS)
+          .then_some(partition);
+        return match wakatiwai_udive::fs::install_session(session, simple_fs) {
+          Ok(ok) => {
+            dio.outptr = ok.as_ptr();
+            Status::SUCCESS
+          }
+          Err(err) => err.status()
+        };
+      }
+
+      let op_status = wakatiwai_udive::fs::dispatch_session(&mut session, path, op);
+      let _ = wakatiwai_udive::fs::FSSessionOps::unmount(session);
+      op_status
+    });
+  };
+  ($ops:ty) => {
+    wakatiwai_udive::fs_prelude!(@entry |dio, args| {
+      // The driver would be unloaded as soon as it exits
+      if dio.header.capabilities.contains(wakatiwai_udive::io::DriverCapabilities::FS_SESSION) {
+        return Status::UNSUPPORTED;
+      }
+
+      wakatiwai_udive::fs::dispatch::<_, $ops>(&args)
+    });
   };
 }
\ No newline at end of file
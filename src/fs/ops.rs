@@ -0,0 +1,412 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use uefi::Status;
+
+use crate::disk::{BlockDevice, DiskReader};
+use super::FSDriverArgs;
+
+/// The size of an encoded [`FileStat`] in bytes.
+const STAT_SIZE: usize = 16;
+/// The size of the fixed part of an encoded [`DirEntry`] in bytes.
+const DIR_ENTRY_HEADER_SIZE: usize = 16;
+/// The size of the fixed part of an encoded [`ProbeResult`] in bytes.
+const PROBE_HEADER_SIZE: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// The operations a file system driver may be asked to perform on a path.
+pub enum FSOperation {
+  /// Read the whole file.
+  Read,
+  /// Read up to `len` bytes of the file, starting at `offset`.
+  ReadRange {
+    /// The offset within the file to start reading from.
+    offset: u64,
+    /// The maximum number of bytes to read.
+    len: u64
+  },
+  /// Describe the file or directory.
+  Stat,
+  /// List the entries of the directory.
+  ListDir,
+  /// Check whether the path exists.
+  Exists,
+  /// Check whether the device holds a file system the driver can read. The
+  /// path is ignored.
+  Probe
+}
+
+impl FSOperation {
+  /// Converts this operation into its ABI-stable representation.
+  /// 
+  /// # Returns
+  /// 
+  /// - `(u32, u64, u64)` - The operation code, followed by the offset and
+  ///   length of a ranged read (zero otherwise).
+  pub const fn to_raw(&self) -> (u32, u64, u64) {
+    match *self {
+      FSOperation::Read => (0, 0, 0),
+      FSOperation::ReadRange { offset, len } => (1, offset, len),
+      FSOperation::Stat => (2, 0, 0),
+      FSOperation::ListDir => (3, 0, 0),
+      FSOperation::Exists => (4, 0, 0),
+      FSOperation::Probe => (5, 0, 0)
+    }
+  }
+
+  /// Recovers an operation from its ABI-stable representation.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(FSOperation)` on success.
+  /// - `Err(Status::UNSUPPORTED)` if the operation code is unknown.
+  pub const fn from_raw(op: u32, offset: u64, len: u64) -> Result<FSOperation, Status> {
+    match op {
+      0 => Ok(FSOperation::Read),
+      1 => Ok(FSOperation::ReadRange { offset, len }),
+      2 => Ok(FSOperation::Stat),
+      3 => Ok(FSOperation::ListDir),
+      4 => Ok(FSOperation::Exists),
+      5 => Ok(FSOperation::Probe),
+      _ => Err(Status::UNSUPPORTED)
+    }
+  }
+}
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// The kinds of object a path may refer to.
+pub enum FileKind {
+  /// Anything not covered by the other kinds, e.g. a device node.
+  Other = 0,
+  /// A regular file.
+  File = 1,
+  /// A directory.
+  Directory = 2,
+  /// A symbolic link.
+  Symlink = 3
+}
+
+impl FileKind {
+  /// Converts a raw kind, treating unknown values as [`FileKind::Other`].
+  pub const fn from_raw(kind: u32) -> FileKind {
+    match kind {
+      1 => FileKind::File,
+      2 => FileKind::Directory,
+      3 => FileKind::Symlink,
+      _ => FileKind::Other
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// The result of [`FSOperation::Stat`].
+pub struct FileStat {
+  /// The size of the file in bytes.
+  pub size: u64,
+  /// The kind of object the path refers to.
+  pub kind: FileKind
+}
+
+impl FileStat {
+  /// Encodes this stat as it is returned through a [`crate::io::DriverIO`].
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(STAT_SIZE);
+    bytes.extend_from_slice(&self.size.to_le_bytes());
+    bytes.extend_from_slice(&(self.kind as u32).to_le_bytes());
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    bytes
+  }
+
+  /// Decodes a stat returned through a [`crate::io::DriverIO`].
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(FileStat)` on success.
+  /// - `Err(Status::VOLUME_CORRUPTED)` if `bytes` is malformed.
+  pub fn from_bytes(bytes: &[u8]) -> Result<FileStat, Status> {
+    if bytes.len() != STAT_SIZE {
+      return Err(Status::VOLUME_CORRUPTED);
+    }
+
+    Ok(
+      FileStat {
+        size: u64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+        kind: FileKind::from_raw(u32::from_le_bytes(bytes[8..12].try_into().unwrap()))
+      }
+    )
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// An entry in the result of [`FSOperation::ListDir`].
+pub struct DirEntry {
+  /// The name of the entry, without the path of its directory.
+  pub name: String,
+  /// The kind of object the entry refers to.
+  pub kind: FileKind,
+  /// The size of the entry in bytes, or zero if unknown.
+  pub size: u64
+}
+
+impl DirEntry {
+  /// Encodes a list of entries as it is returned through a
+  /// [`crate::io::DriverIO`].
+  /// 
+  /// Each entry is encoded as its size (`u64`), kind (`u32`) and name length
+  /// (`u32`), followed by its UTF-8 name padded to a multiple of 8 bytes.
+  pub fn encode_all(entries: &[DirEntry]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for entry in entries {
+      bytes.extend_from_slice(&entry.size.to_le_bytes());
+      bytes.extend_from_slice(&(entry.kind as u32).to_le_bytes());
+      bytes.extend_from_slice(&(entry.name.len() as u32).to_le_bytes());
+      bytes.extend_from_slice(entry.name.as_bytes());
+      bytes.resize(bytes.len().next_multiple_of(8), 0);
+    }
+
+    bytes
+  }
+
+  /// Decodes a list of entries returned through a [`crate::io::DriverIO`].
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(Vec<DirEntry>)` on success.
+  /// - `Err(Status::VOLUME_CORRUPTED)` if `bytes` is malformed.
+  pub fn decode_all(mut bytes: &[u8]) -> Result<Vec<DirEntry>, Status> {
+    let mut entries = Vec::new();
+    while !bytes.is_empty() {
+      if bytes.len() < DIR_ENTRY_HEADER_SIZE {
+        return Err(Status::VOLUME_CORRUPTED);
+      }
+
+      let size = u64::from_le_bytes(bytes[0..8].try_into().unwrap());
+      let kind = FileKind::from_raw(u32::from_le_bytes(bytes[8..12].try_into().unwrap()));
+      let name_len = u32::from_le_bytes(bytes[12..16].try_into().unwrap()) as usize;
+      let end = DIR_ENTRY_HEADER_SIZE + name_len;
+      if bytes.len() < end {
+        return Err(Status::VOLUME_CORRUPTED);
+      }
+
+      let name = core::str::from_utf8(&bytes[DIR_ENTRY_HEADER_SIZE..end]).map_err(|_| Status::VOLUME_CORRUPTED)?;
+      entries.push(
+        DirEntry {
+          name: String::from(name),
+          kind,
+          size
+        }
+      );
+      bytes = &bytes[end.next_multiple_of(8).min(bytes.len())..];
+    }
+
+    Ok(entries)
+  }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+/// The result of [`FSOperation::Probe`].
+pub struct ProbeResult {
+  /// How confident the driver is that it can read the file system, from 0
+  /// (not at all) to 100 (certain, e.g. a checksummed superblock matched).
+  pub confidence: u8,
+  /// The label of the file system, or empty if it has none.
+  pub label: String,
+  /// The UUID or serial number of the file system, formatted as the file
+  /// system's own tools would (e.g. `1234-ABCD` for FAT), or empty if it has
+  /// none.
+  pub uuid: String
+}
+
+impl ProbeResult {
+  /// The greatest confidence a driver may report.
+  pub const CERTAIN: u8 = 100;
+
+  /// Encodes this result as it is returned through a
+  /// [`crate::io::DriverIO`].
+  /// 
+  /// The confidence (`u32`), label length (`u32`) and UUID length (`u32`)
+  /// are followed by a reserved `u32`, and then the UTF-8 label and UUID.
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(PROBE_HEADER_SIZE + self.label.len() + self.uuid.len());
+    bytes.extend_from_slice(&(self.confidence.min(ProbeResult::CERTAIN) as u32).to_le_bytes());
+    bytes.extend_from_slice(&(self.label.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&(self.uuid.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    bytes.extend_from_slice(self.label.as_bytes());
+    bytes.extend_from_slice(self.uuid.as_bytes());
+    bytes
+  }
+
+  /// Decodes a result returned through a [`crate::io::DriverIO`].
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(ProbeResult)` on success.
+  /// - `Err(Status::VOLUME_CORRUPTED)` if `bytes` is malformed.
+  pub fn from_bytes(bytes: &[u8]) -> Result<ProbeResult, Status> {
+    if bytes.len() < PROBE_HEADER_SIZE {
+      return Err(Status::VOLUME_CORRUPTED);
+    }
+
+    let confidence = u32::from_le_bytes(bytes[0..4].try_into().unwrap());
+    let label_len = u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+    let uuid_len = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+    // The lengths come from the driver, and may overflow a 32-bit `usize`
+    let len = PROBE_HEADER_SIZE.checked_add(label_len)
+      .and_then(|len| len.checked_add(uuid_len))
+      .ok_or(Status::VOLUME_CORRUPTED)?;
+    if bytes.len() != len {
+      return Err(Status::VOLUME_CORRUPTED);
+    }
+
+    let strings = &bytes[PROBE_HEADER_SIZE..];
+    let label = core::str::from_utf8(&strings[..label_len]).map_err(|_| Status::VOLUME_CORRUPTED)?;
+    let uuid = core::str::from_utf8(&strings[label_len..]).map_err(|_| Status::VOLUME_CORRUPTED)?;
+
+    Ok(
+      ProbeResult {
+        confidence: confidence.min(ProbeResult::CERTAIN as u32) as u8,
+        label: String::from(label),
+        uuid: String::from(uuid)
+      }
+    )
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// The result of invoking a file system driver, matching the
+/// [`FSOperation`] it was invoked with.
+pub enum FSOutput<'a> {
+  /// The result of [`FSOperation::Read`] or [`FSOperation::ReadRange`].
+  Data(&'a [u8]),
+  /// The result of [`FSOperation::Stat`].
+  Stat(FileStat),
+  /// The result of [`FSOperation::ListDir`].
+  Entries(Vec<DirEntry>),
+  /// The result of [`FSOperation::Exists`].
+  Exists(bool),
+  /// The result of [`FSOperation::Probe`].
+  Probe(ProbeResult)
+}
+
+impl<'a> FSOutput<'a> {
+  /// Decodes the output of a file system driver.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `op` (`FSOperation`) - The operation the driver was invoked with.
+  /// - `bytes` (`&[u8]`) - The output of the driver.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(FSOutput)` on success.
+  /// - `Err(Status::VOLUME_CORRUPTED)` if `bytes` is malformed.
+  pub fn from_bytes(op: FSOperation, bytes: &'a [u8]) -> Result<FSOutput<'a>, Status> {
+    match op {
+      FSOperation::Read | FSOperation::ReadRange { .. } => Ok(FSOutput::Data(bytes)),
+      FSOperation::Stat => Ok(FSOutput::Stat(FileStat::from_bytes(bytes)?)),
+      FSOperation::ListDir => Ok(FSOutput::Entries(DirEntry::decode_all(bytes)?)),
+      FSOperation::Exists => match bytes {
+        [exists] => Ok(FSOutput::Exists(*exists != 0)),
+        _ => Err(Status::VOLUME_CORRUPTED)
+      },
+      FSOperation::Probe => Ok(FSOutput::Probe(ProbeResult::from_bytes(bytes)?))
+    }
+  }
+}
+
+/// The operations implemented by a file system driver.
+/// 
+/// Only [`FSDriverOps::read`] is required. The other operations default to
+/// being derived from it where possible, and drivers should override them
+/// when the file system allows a cheaper implementation. Implementing this
+/// trait for any [`BlockDevice`], rather than just [`DiskReader`], allows a
+/// driver to be tested on the host.
+/// 
+/// The implementing type is passed to [`crate::fs_prelude`], which dispatches
+/// each invocation to the matching handler.
+pub trait FSDriverOps<D: BlockDevice = DiskReader> {
+  /// Reads the whole file at `args.path`.
+  fn read(args: &FSDriverArgs<D>) -> Result<Vec<u8>, Status>;
+
+  /// Reads up to `len` bytes of the file at `args.path`, starting at
+  /// `offset`.
+  /// 
+  /// Reading past the end of the file returns fewer bytes, or none.
+  fn read_range(args: &FSDriverArgs<D>, offset: u64, len: u64) -> Result<Vec<u8>, Status> {
+    Ok(default_read_range(Self::read(args)?, offset, len))
+  }
+
+  /// Describes the file or directory at `args.path`.
+  /// 
+  /// By default, the file is read to find its size, and directories are not
+  /// supported.
+  fn stat(args: &FSDriverArgs<D>) -> Result<FileStat, Status> {
+    Ok(
+      FileStat {
+        size: Self::read(args)?.len() as u64,
+        kind: FileKind::File
+      }
+    )
+  }
+
+  /// Lists the entries of the directory at `args.path`.
+  /// 
+  /// This is unsupported by default.
+  fn list_dir(_args: &FSDriverArgs<D>) -> Result<Vec<DirEntry>, Status> {
+    Err(Status::UNSUPPORTED)
+  }
+
+  /// Checks whether `args.path` exists.
+  /// 
+  /// By default, this is derived from [`FSDriverOps::stat`], treating
+  /// [`uefi_raw::Status::NOT_FOUND`] as not existing.
+  fn exists(args: &FSDriverArgs<D>) -> Result<bool, Status> {
+    match Self::stat(args) {
+      Ok(_) => Ok(true),
+      Err(Status::NOT_FOUND) => Ok(false),
+      Err(err) => Err(err)
+    }
+  }
+
+  /// Checks whether `args.diskreader` holds a file system this driver can
+  /// read.
+  /// 
+  /// This should be cheap, e.g. checking the magic number of a superblock,
+  /// as every driver is probed when searching for one to read a partition.
+  /// This is unsupported by default, so that the driver is never chosen.
+  fn probe(_args: &FSDriverArgs<D>) -> Result<ProbeResult, Status> {
+    Err(Status::UNSUPPORTED)
+  }
+}
+
+/// Cuts a range out of a whole file, as the default for ranged reads.
+pub(super) fn default_read_range(mut data: Vec<u8>, offset: u64, len: u64) -> Vec<u8> {
+  let start = usize::try_from(offset).unwrap_or(usize::MAX).min(data.len());
+  let end = start.saturating_add(usize::try_from(len).unwrap_or(usize::MAX)).min(data.len());
+  data.truncate(end);
+  data.drain(..start);
+  data
+}
+
+/// Performs the operation in `args` with a driver's handlers.
+/// 
+/// This is called by [`crate::fs_prelude`], and may be used to exercise a
+/// driver on the host.
+/// 
+/// # Returns
+/// 
+/// - `Ok(Vec<u8>)` on success, containing the output encoded as it is
+///   returned through a [`crate::io::DriverIO`].
+/// - `Err(Status)` if the handler fails.
+pub fn dispatch<D: BlockDevice, T: FSDriverOps<D> + ?Sized>(args: &FSDriverArgs<D>) -> Result<Vec<u8>, Status> {
+  match args.op {
+    FSOperation::Read => T::read(args),
+    FSOperation::ReadRange { offset, len } => T::read_range(args, offset, len),
+    FSOperation::Stat => T::stat(args).map(|stat| stat.to_bytes()),
+    FSOperation::ListDir => T::list_dir(args).map(|entries| DirEntry::encode_all(&entries)),
+    FSOperation::Exists => T::exists(args).map(|exists| alloc::vec![exists as u8]),
+    FSOperation::Probe => T::probe(args).map(|probe| probe.to_bytes())
+  }
+}
@@ -0,0 +1,444 @@
+use core::ffi::c_void;
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use uefi::boot::{MemoryType, OpenProtocolAttributes, OpenProtocolParams};
+use uefi::proto::loaded_image::LoadedImage;
+use uefi::proto::unsafe_protocol;
+use uefi::{Handle, Identify, Status};
+
+use crate::disk::{BlockDevice, DiskReader};
+use crate::io::{copy_to_pages, output_page_count, DRIVER_IO_ABI_VERSION};
+use crate::{FSDriver, UdiveError};
+use super::ops::{default_read_range, FileKind};
+use super::simple_fs::SimpleFs;
+use super::{DirEntry, FSOperation, FSOutput, FileStat, ProbeResult};
+
+/// The operations implemented by a mounted file system.
+/// 
+/// Unlike [`super::FSDriverOps`], which starts from scratch for every
+/// operation, an implementor is created once by [`FSSessionOps::mount`] and
+/// then serves many operations, so that on-disk structures such as the
+/// superblock need only be read once.
+/// 
+/// The implementing type is passed to [`crate::fs_prelude`] as
+/// `fs_prelude!(session T)`. Such drivers must be built as boot service
+/// drivers, rather than applications, to stay resident while mounted.
+pub trait FSSessionOps<D: BlockDevice = DiskReader>: Sized {
+  /// Mounts the file system on a device.
+  fn mount(diskreader: D) -> Result<Self, Status>;
+
+  /// Reads the whole file at `path`.
+  fn read(&mut self, path: &str) -> Result<Vec<u8>, Status>;
+
+  /// Reads up to `len` bytes of the file at `path`, starting at `offset`.
+  /// 
+  /// Reading past the end of the file returns fewer bytes, or none.
+  fn read_range(&mut self, path: &str, offset: u64, len: u64) -> Result<Vec<u8>, Status> {
+    Ok(default_read_range(self.read(path)?, offset, len))
+  }
+
+  /// Describes the file or directory at `path`.
+  /// 
+  /// By default, the file is read to find its size, and directories are not
+  /// supported.
+  fn stat(&mut self, path: &str) -> Result<FileStat, Status> {
+    Ok(
+      FileStat {
+        size: self.read(path)?.len() as u64,
+        kind: FileKind::File
+      }
+    )
+  }
+
+  /// Lists the entries of the directory at `path`.
+  /// 
+  /// This is unsupported by default.
+  fn list_dir(&mut self, _path: &str) -> Result<Vec<DirEntry>, Status> {
+    Err(Status::UNSUPPORTED)
+  }
+
+  /// Checks whether `path` exists.
+  /// 
+  /// By default, this is derived from [`FSSessionOps::stat`], treating
+  /// [`uefi_raw::Status::NOT_FOUND`] as not existing.
+  fn exists(&mut self, path: &str) -> Result<bool, Status> {
+    match self.stat(path) {
+      Ok(_) => Ok(true),
+      Err(Status::NOT_FOUND) => Ok(false),
+      Err(err) => Err(err)
+    }
+  }
+
+  /// Unmounts the file system.
+  fn unmount(self) -> Status {
+    Status::SUCCESS
+  }
+
+  /// Checks whether a device holds a file system this driver can read,
+  /// without mounting it.
+  /// 
+  /// As with [`super::FSDriverOps::probe`], this should be cheap, and is
+  /// unsupported by default.
+  fn probe(_diskreader: &D) -> Result<ProbeResult, Status> {
+    Err(Status::UNSUPPORTED)
+  }
+}
+
+/// Performs an operation with a mounted file system's handlers.
+/// 
+/// [`FSOperation::Probe`] is unsupported, as the file system has already
+/// been mounted.
+/// 
+/// # Returns
+/// 
+/// - `Ok(Vec<u8>)` on success, containing the output encoded as it is
+///   returned through a [`crate::io::DriverIO`].
+/// - `Err(Status)` if the handler fails.
+pub fn dispatch_session<D: BlockDevice, S: FSSessionOps<D>>(session: &mut S, path: &str, op: FSOperation) -> Result<Vec<u8>, Status> {
+  match op {
+    FSOperation::Read => session.read(path),
+    FSOperation::ReadRange { offset, len } => session.read_range(path, offset, len),
+    FSOperation::Stat => session.stat(path).map(|stat| stat.to_bytes()),
+    FSOperation::ListDir => session.list_dir(path).map(|entries| DirEntry::encode_all(&entries)),
+    FSOperation::Exists => session.exists(path).map(|exists| alloc::vec![exists as u8]),
+    FSOperation::Probe => Err(Status::UNSUPPORTED)
+  }
+}
+
+#[repr(C)]
+/// A single request made of a mounted file system.
+pub struct RawFSRequest<'a> {
+  /// A pointer to the UTF-8 path to operate on.
+  pub path_ptr: *const u8,
+  /// The length of the path in bytes.
+  pub path_len: usize,
+  /// The code of the [`FSOperation`] to perform.
+  pub op: u32,
+  /// Reserved, must be zero.
+  pub reserved: u32,
+  /// The offset of a [`FSOperation::ReadRange`].
+  pub range_offset: u64,
+  /// The length of a [`FSOperation::ReadRange`].
+  pub range_len: u64,
+  _marker: PhantomData<&'a str>
+}
+
+impl<'a> RawFSRequest<'a> {
+  /// Creates a request to perform an operation on a path.
+  pub fn new(path: &'a str, op: FSOperation) -> RawFSRequest<'a> {
+    let (op, range_offset, range_len) = op.to_raw();
+    RawFSRequest {
+      path_ptr: path.as_ptr(),
+      path_len: path.len(),
+      op,
+      reserved: 0,
+      range_offset,
+      range_len,
+      _marker: PhantomData
+    }
+  }
+}
+
+#[repr(C)]
+#[unsafe_protocol("d4e196af-3646-4da6-8ca8-18618b6665f0")]
+/// The protocol installed by a file system driver for each mounted
+/// [`FSSession`].
+pub struct FSSessionProtocol {
+  /// The ABI version the driver was built with.
+  pub abi_version: u32,
+  /// Reserved, must be zero.
+  pub reserved: u32,
+  /// Performs a request, storing its output in newly allocated pages which
+  /// the caller must free.
+  pub request: unsafe extern "efiapi" fn(
+    this: *mut FSSessionProtocol,
+    request: *const RawFSRequest,
+    outptr: *mut *mut c_void,
+    outsize: *mut usize
+  ) -> Status,
+  /// Unmounts the file system and uninstalls this protocol.
+  pub unmount: unsafe extern "efiapi" fn(this: *mut FSSessionProtocol) -> Status
+}
+
+#[repr(C)]
+/// A [`FSSessionProtocol`] followed by the state of the mounted file system.
+struct SessionInterface<S> {
+  protocol: FSSessionProtocol,
+  handle: Option<Handle>,
+  simple_fs: Option<NonNull<SimpleFs>>,
+  session: S
+}
+
+/// Installs a [`FSSessionProtocol`] serving a mounted file system on a new
+/// handle.
+/// 
+/// This is called by [`crate::fs_prelude`] when a driver is mounted.
+/// 
+/// # Arguments
+/// 
+/// - `session` (`S`) - The mounted file system.
+/// - `simple_fs` (`Option<Handle>`) - A handle on which to also install an
+///   `EFI_SIMPLE_FILE_SYSTEM_PROTOCOL` serving the file system, usually the
+///   partition it was mounted from.
+/// 
+/// # Returns
+/// 
+/// - `Ok(Handle)` on success, containing the new handle.
+/// - `Err(UdiveError::Load)` with `UNSUPPORTED` if the driver is not a boot
+///   service driver, and so would be unloaded as soon as it exits.
+/// - `Err(UdiveError::Firmware)` if either protocol could not be installed.
+pub fn install_session<D: BlockDevice, S: FSSessionOps<D>>(session: S, simple_fs: Option<Handle>) -> Result<Handle, UdiveError> {
+  let loaded_image = uefi::boot::open_protocol_exclusive::<LoadedImage>(uefi::boot::image_handle())?;
+  if loaded_image.code_type() != MemoryType::BOOT_SERVICES_CODE {
+    return Err(UdiveError::load(Status::UNSUPPORTED).with_message("sessions are only served by boot service drivers"));
+  }
+
+  let interface = Box::into_raw(
+    Box::new(
+      SessionInterface {
+        protocol: FSSessionProtocol {
+          abi_version: DRIVER_IO_ABI_VERSION,
+          reserved: 0,
+          request: session_request::<D, S>,
+          unmount: session_unmount::<D, S>
+        },
+        handle: None,
+        simple_fs: None,
+        session
+      }
+    )
+  );
+
+  unsafe {
+    match uefi::boot::install_protocol_interface(None, &FSSessionProtocol::GUID, interface as *const c_void) {
+      Ok(ok) => {
+        (*interface).handle = Some(ok);
+      }
+      Err(err) => {
+        drop(Box::from_raw(interface));
+        return Err(err.into());
+      }
+    }
+
+    if let Some(simple_fs_handle) = simple_fs {
+      match super::simple_fs::install(simple_fs_handle, NonNull::new_unchecked(interface as *mut FSSessionProtocol)) {
+        Ok(ok) => {
+          (*interface).simple_fs = Some(ok);
+        }
+        Err(err) => {
+          let _ = session_unmount::<D, S>(interface as *mut FSSessionProtocol);
+          return Err(UdiveError::firmware(err));
+        }
+      }
+    }
+
+    Ok((*interface).handle.unwrap())
+  }
+}
+
+/// Implements [`FSSessionProtocol::request`].
+unsafe extern "efiapi" fn session_request<D: BlockDevice, S: FSSessionOps<D>>(
+  this: *mut FSSessionProtocol,
+  request: *const RawFSRequest,
+  outptr: *mut *mut c_void,
+  outsize: *mut usize
+) -> Status {
+  if this.is_null() || request.is_null() || outptr.is_null() || outsize.is_null() {
+    return Status::INVALID_PARAMETER;
+  }
+
+  let interface = &mut *(this as *mut SessionInterface<S>);
+  let request = &*request;
+  let path = match core::str::from_utf8(core::slice::from_raw_parts(request.path_ptr, request.path_len)) {
+    Ok(ok) => ok,
+    Err(_) => {
+      return Status::INVALID_PARAMETER;
+    }
+  };
+  let op = match FSOperation::from_raw(request.op, request.range_offset, request.range_len) {
+    Ok(ok) => ok,
+    Err(err) => {
+      return err;
+    }
+  };
+
+  let output = match dispatch_session(&mut interface.session, path, op) {
+    Ok(ok) => ok,
+    Err(err) => {
+      return err;
+    }
+  };
+
+  match copy_to_pages(&output) {
+    Ok(ok) => {
+      *outptr = ok.as_ptr() as *mut c_void;
+      *outsize = output.len();
+      Status::SUCCESS
+    }
+    Err(err) => err
+  }
+}
+
+/// Implements [`FSSessionProtocol::unmount`].
+unsafe extern "efiapi" fn session_unmount<D: BlockDevice, S: FSSessionOps<D>>(this: *mut FSSessionProtocol) -> Status {
+  if this.is_null() {
+    return Status::INVALID_PARAMETER;
+  }
+
+  // Files opened through the file system protocol still refer to the session
+  let interface = this as *mut SessionInterface<S>;
+  if let Some(simple_fs) = (*interface).simple_fs {
+    let uninstall_status = super::simple_fs::uninstall(simple_fs);
+    if uninstall_status.is_error() {
+      return uninstall_status;
+    }
+    (*interface).simple_fs = None;
+  }
+  if let Some(handle) = (*interface).handle
+    && let Err(err) = uefi::boot::uninstall_protocol_interface(handle, &FSSessionProtocol::GUID, interface as *const c_void) {
+    return err.status();
+  }
+
+  Box::from_raw(interface).session.unmount()
+}
+
+/// A file system mounted by a [`FSDriver`].
+/// 
+/// Created by [`FSDriver::mount`]. Any number of operations may be performed
+/// on the file system through [`FSSession::request`] before it is unmounted
+/// with [`FSSession::unmount`], or when the session is dropped.
+pub struct FSSession<'a> {
+  /// The protocol installed by the driver.
+  protocol: NonNull<FSSessionProtocol>,
+  /// The pages holding the output of the last request, and their length.
+  output: Option<(NonNull<u8>, usize)>,
+  /// Whether the file system has been unmounted.
+  unmounted: bool,
+  /// Ties the session to the driver serving it.
+  _driver: PhantomData<&'a mut FSDriver>
+}
+
+impl FSSession<'_> {
+  /// Opens the session installed on a handle by a driver.
+  pub(crate) fn open(handle: Handle) -> Result<FSSession<'static>, UdiveError> {
+    // The protocol is only closed, not used, through the scoped protocol,
+    // as the driver uninstalls it on unmount
+    let mut scoped = unsafe {
+      uefi::boot::open_protocol::<FSSessionProtocol>(
+        OpenProtocolParams {
+          handle,
+          agent: uefi::boot::image_handle(),
+          controller: None
+        },
+        OpenProtocolAttributes::GetProtocol
+      ).map_err(|err| UdiveError::abi_mismatch(err.status()).with_message("driver did not install a session"))?
+    };
+    let protocol = NonNull::from(&mut *scoped);
+    drop(scoped);
+
+    // The function table cannot be trusted, even to unmount, if the ABI differs
+    if unsafe { protocol.as_ref().abi_version } != DRIVER_IO_ABI_VERSION {
+      return Err(UdiveError::abi_mismatch(Status::INCOMPATIBLE_VERSION));
+    }
+
+    Ok(
+      FSSession {
+        protocol,
+        output: None,
+        unmounted: false,
+        _driver: PhantomData
+      }
+    )
+  }
+
+  /// Performs an operation on the mounted file system.
+  /// 
+  /// The output of the previous request is freed.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `path` (`&str`) - The path to operate on.
+  /// - `op` (`FSOperation`) - The operation to perform.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(FSOutput)` on success, containing the result of `op`.
+  /// - `Err(UdiveError::Load)` carrying [`uefi_raw::Status::NOT_STARTED`]
+  ///   if the session has been unmounted.
+  /// - `Err(UdiveError::AbiMismatch)` if the driver's output is malformed.
+  /// - `Err(UdiveError::Driver)` if the driver fails.
+  pub fn request(&mut self, path: &str, op: FSOperation) -> Result<FSOutput<'_>, UdiveError> {
+    if self.unmounted {
+      return Err(UdiveError::load(Status::NOT_STARTED).with_message("session is unmounted"));
+    }
+    self.free_output();
+
+    let request = RawFSRequest::new(path, op);
+    let mut outptr = core::ptr::null_mut();
+    let mut outsize = 0;
+    let status = unsafe {
+      (self.protocol.as_ref().request)(self.protocol.as_ptr(), &request, &mut outptr, &mut outsize)
+    };
+    if status.is_error() {
+      return Err(UdiveError::driver(status));
+    }
+
+    let malformed_output = || UdiveError::abi_mismatch(Status::VOLUME_CORRUPTED).with_message("malformed driver output");
+    let outptr = NonNull::new(outptr as *mut u8).ok_or_else(malformed_output)?;
+    self.output = Some((outptr, outsize));
+    FSOutput::from_bytes(op, unsafe { core::slice::from_raw_parts(outptr.as_ptr(), outsize) })
+      .map_err(|_| malformed_output())
+  }
+
+  /// Unmounts the file system.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(())` on success, or if the file system is already unmounted.
+  /// - `Err(UdiveError::Driver)` if the driver failed to unmount. This
+  ///   carries [`uefi_raw::Status::ACCESS_DENIED`] if the session was mounted
+  ///   with [`FSDriver::mount_simple_fs`] and files are still open through it.
+  ///   The file system stays mounted, and unmounting may be tried again,
+  ///   e.g. once those files are closed.
+  pub fn unmount(&mut self) -> Result<(), UdiveError> {
+    let unmount_status = self.unmount_inner();
+    if unmount_status.is_error() {
+      return Err(UdiveError::driver(unmount_status));
+    }
+
+    Ok(())
+  }
+
+  /// Unmounts the file system if still mounted.
+  fn unmount_inner(&mut self) -> Status {
+    self.free_output();
+    if self.unmounted {
+      return Status::SUCCESS;
+    }
+
+    let unmount_status = unsafe { (self.protocol.as_ref().unmount)(self.protocol.as_ptr()) };
+    if unmount_status.is_success() {
+      self.unmounted = true;
+    }
+
+    unmount_status
+  }
+
+  /// Frees the output of the last request.
+  fn free_output(&mut self) {
+    if let Some((ptr, len)) = self.output.take() {
+      unsafe {
+        let _ = uefi::boot::free_pages(ptr, output_page_count(len));
+      }
+    }
+  }
+}
+
+impl Drop for FSSession<'_> {
+  fn drop(&mut self) {
+    let _ = self.unmount_inner();
+  }
+}
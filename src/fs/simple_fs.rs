@@ -0,0 +1,473 @@
+use core::ffi::c_void;
+use core::ptr::NonNull;
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use uefi::{Guid, Handle, Status};
+use uefi_raw::protocol::file_system::{
+  FileAttribute, FileInfo, FileMode, FileProtocolRevision, FileProtocolV1, FileSystemInfo, FileSystemVolumeLabel,
+  SimpleFileSystemProtocol
+};
+use uefi_raw::Char16;
+
+use crate::io::output_page_count;
+use super::session::RawFSRequest;
+use super::{DirEntry, FSOperation, FSOutput, FSSessionProtocol, FileKind, FileStat};
+
+/// The offset of the name within an `EFI_FILE_INFO`.
+const FILE_INFO_NAME_OFFSET: usize = 80;
+/// The offset of the label within an `EFI_FILE_SYSTEM_INFO`.
+const FS_INFO_LABEL_OFFSET: usize = 36;
+
+#[repr(C)]
+/// An `EFI_SIMPLE_FILE_SYSTEM_PROTOCOL` serving a mounted file system.
+pub(crate) struct SimpleFs {
+  /// The protocol, first so that it may be cast back to this struct.
+  protocol: SimpleFileSystemProtocol,
+  /// The handle the protocol is installed on.
+  handle: Handle,
+  /// The session requests are forwarded to.
+  session: NonNull<FSSessionProtocol>,
+  /// The number of files currently open.
+  open_files: usize
+}
+
+#[repr(C)]
+/// An `EFI_FILE_PROTOCOL` for a file or directory opened through a
+/// [`SimpleFs`].
+struct SimpleFile {
+  /// The protocol, first so that it may be cast back to this struct.
+  protocol: FileProtocolV1,
+  /// The file system the file was opened on.
+  fs: NonNull<SimpleFs>,
+  /// The absolute, `/`-separated path of the file.
+  path: String,
+  /// The size and kind of the file.
+  stat: FileStat,
+  /// The byte offset of a file, or the index of the next entry of a
+  /// directory.
+  position: u64,
+  /// The entries of a directory, listed on the first read.
+  entries: Option<Vec<DirEntry>>
+}
+
+/// The output of a request, freed when dropped.
+struct Output(NonNull<u8>, usize);
+
+impl Output {
+  /// Returns the output as a slice.
+  fn as_slice(&self) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(self.0.as_ptr(), self.1) }
+  }
+}
+
+impl Drop for Output {
+  fn drop(&mut self) {
+    unsafe {
+      let _ = uefi::boot::free_pages(self.0, output_page_count(self.1));
+    }
+  }
+}
+
+/// Installs an `EFI_SIMPLE_FILE_SYSTEM_PROTOCOL` on a handle, forwarding
+/// all accesses to a mounted session.
+/// 
+/// # Returns
+/// 
+/// - `Ok(NonNull<SimpleFs>)` on success, to be passed to [`uninstall`].
+/// - `Err(Status)` if the protocol could not be installed, e.g. as the
+///   handle already has a file system.
+pub(crate) fn install(handle: Handle, session: NonNull<FSSessionProtocol>) -> Result<NonNull<SimpleFs>, Status> {
+  let simple_fs = Box::into_raw(
+    Box::new(
+      SimpleFs {
+        protocol: SimpleFileSystemProtocol {
+          revision: 0x0001_0000,
+          open_volume
+        },
+        handle,
+        session,
+        open_files: 0
+      }
+    )
+  );
+
+  unsafe {
+    match uefi::boot::install_protocol_interface(Some(handle), &SimpleFileSystemProtocol::GUID, simple_fs as *const c_void) {
+      Ok(_) => Ok(NonNull::new_unchecked(simple_fs)),
+      Err(err) => {
+        drop(Box::from_raw(simple_fs));
+        Err(err.status())
+      }
+    }
+  }
+}
+
+/// Uninstalls a protocol installed with [`install`].
+/// 
+/// # Returns
+/// 
+/// - [`uefi_raw::Status::SUCCESS`] on success.
+/// - [`uefi_raw::Status::ACCESS_DENIED`] if any files are still open.
+/// - Another `Status` if the protocol could not be uninstalled.
+pub(crate) unsafe fn uninstall(simple_fs: NonNull<SimpleFs>) -> Status {
+  let fs = simple_fs.as_ptr();
+  if (*fs).open_files != 0 {
+    return Status::ACCESS_DENIED;
+  }
+
+  match uefi::boot::uninstall_protocol_interface((*fs).handle, &SimpleFileSystemProtocol::GUID, fs as *const c_void) {
+    Ok(_) => {
+      drop(Box::from_raw(fs));
+      Status::SUCCESS
+    }
+    Err(err) => err.status()
+  }
+}
+
+impl SimpleFs {
+  /// Forwards a request to the session.
+  unsafe fn request(&self, path: &str, op: FSOperation) -> Result<Output, Status> {
+    let request = RawFSRequest::new(path, op);
+    let mut outptr = core::ptr::null_mut();
+    let mut outsize = 0;
+
+    let status = (self.session.as_ref().request)(self.session.as_ptr(), &request, &mut outptr, &mut outsize);
+    if status.is_error() {
+      return Err(status);
+    }
+
+    match NonNull::new(outptr as *mut u8) {
+      Some(some) => Ok(Output(some, outsize)),
+      None => Err(Status::VOLUME_CORRUPTED)
+    }
+  }
+
+  /// Describes a path, treating the root as a directory.
+  unsafe fn stat(&self, path: &str) -> Result<FileStat, Status> {
+    if path == "/" {
+      return Ok(FileStat { size: 0, kind: FileKind::Directory });
+    }
+
+    let output = self.request(path, FSOperation::Stat)?;
+    FileStat::from_bytes(output.as_slice())
+  }
+
+  /// Opens a file, counting it against the file system.
+  unsafe fn open_file(&mut self, path: String, stat: FileStat) -> *mut FileProtocolV1 {
+    self.open_files += 1;
+    let file = Box::new(
+      SimpleFile {
+        protocol: FileProtocolV1 {
+          revision: FileProtocolRevision::REVISION_1,
+          open: file_open,
+          close: file_close,
+          delete: file_delete,
+          read: file_read,
+          write: file_write,
+          get_position: file_get_position,
+          set_position: file_set_position,
+          get_info: file_get_info,
+          set_info: file_set_info,
+          flush: file_flush
+        },
+        fs: NonNull::from(&mut *self),
+        path,
+        stat,
+        position: 0,
+        entries: None
+      }
+    );
+
+    Box::into_raw(file) as *mut FileProtocolV1
+  }
+}
+
+/// Resolves a UEFI file name relative to a directory.
+fn resolve(base: &str, name: &str) -> String {
+  let mut parts: Vec<&str> = Vec::new();
+  if !name.starts_with(['\\', '/']) {
+    parts.extend(base.split('/').filter(|part| !part.is_empty()));
+  }
+
+  for part in name.split(['\\', '/']) {
+    match part {
+      "" | "." => {}
+      ".." => {
+        parts.pop();
+      }
+      _ => parts.push(part)
+    }
+  }
+
+  let mut path = String::from("/");
+  path.push_str(&parts.join("/"));
+  path
+}
+
+/// Appends a string as a null-terminated UCS-2 string.
+fn push_ucs2(bytes: &mut Vec<u8>, string: &str) {
+  for c in string.chars() {
+    let c = if (c as u32) < 0x10000 { c as u16 } else { '?' as u16 };
+    bytes.extend_from_slice(&c.to_le_bytes());
+  }
+  bytes.extend_from_slice(&0u16.to_le_bytes());
+}
+
+/// Encodes an `EFI_FILE_INFO`.
+fn file_info(name: &str, stat: &FileStat) -> Vec<u8> {
+  let mut attribute = FileAttribute::READ_ONLY;
+  if stat.kind == FileKind::Directory {
+    attribute |= FileAttribute::DIRECTORY;
+  }
+
+  // Timestamps are not known, so are left zeroed
+  let mut bytes = alloc::vec![0; FILE_INFO_NAME_OFFSET];
+  push_ucs2(&mut bytes, name);
+  let size = bytes.len() as u64;
+  bytes[0..8].copy_from_slice(&size.to_le_bytes());
+  bytes[8..16].copy_from_slice(&stat.size.to_le_bytes());
+  bytes[16..24].copy_from_slice(&stat.size.to_le_bytes());
+  bytes[72..80].copy_from_slice(&attribute.bits().to_le_bytes());
+  bytes
+}
+
+/// Encodes an `EFI_FILE_SYSTEM_INFO`, whose sizes are not known.
+fn file_system_info() -> Vec<u8> {
+  let mut bytes = alloc::vec![0; FS_INFO_LABEL_OFFSET];
+  push_ucs2(&mut bytes, "");
+  let size = bytes.len() as u64;
+  bytes[0..8].copy_from_slice(&size.to_le_bytes());
+  bytes[8] = 1;
+  bytes
+}
+
+/// Copies information into a caller's buffer.
+unsafe fn copy_info(info: &[u8], buffer_size: *mut usize, buffer: *mut c_void) -> Status {
+  let available = *buffer_size;
+  *buffer_size = info.len();
+  if available < info.len() {
+    return Status::BUFFER_TOO_SMALL;
+  }
+  if buffer.is_null() {
+    return Status::INVALID_PARAMETER;
+  }
+
+  core::ptr::copy_nonoverlapping(info.as_ptr(), buffer as *mut u8, info.len());
+  Status::SUCCESS
+}
+
+/// Implements `EFI_SIMPLE_FILE_SYSTEM_PROTOCOL.OpenVolume()`.
+unsafe extern "efiapi" fn open_volume(this: *mut SimpleFileSystemProtocol, root: *mut *mut FileProtocolV1) -> Status {
+  if this.is_null() || root.is_null() {
+    return Status::INVALID_PARAMETER;
+  }
+
+  let fs = &mut *(this as *mut SimpleFs);
+  *root = fs.open_file(String::from("/"), FileStat { size: 0, kind: FileKind::Directory });
+  Status::SUCCESS
+}
+
+/// Implements `EFI_FILE_PROTOCOL.Open()`.
+unsafe extern "efiapi" fn file_open(
+  this: *mut FileProtocolV1,
+  new_handle: *mut *mut FileProtocolV1,
+  file_name: *const Char16,
+  open_mode: FileMode,
+  _attributes: FileAttribute
+) -> Status {
+  if this.is_null() || new_handle.is_null() || file_name.is_null() {
+    return Status::INVALID_PARAMETER;
+  }
+  if open_mode != FileMode::READ {
+    return Status::WRITE_PROTECTED;
+  }
+
+  let file = &*(this as *mut SimpleFile);
+  let mut len = 0;
+  while *file_name.add(len) != 0 {
+    len += 1;
+  }
+  let name = match String::from_utf16(core::slice::from_raw_parts(file_name, len)) {
+    Ok(ok) => ok,
+    Err(_) => {
+      return Status::INVALID_PARAMETER;
+    }
+  };
+
+  let fs = &mut *file.fs.as_ptr();
+  let path = resolve(&file.path, &name);
+  match fs.stat(&path) {
+    Ok(stat) => {
+      *new_handle = fs.open_file(path, stat);
+      Status::SUCCESS
+    }
+    Err(err) => err
+  }
+}
+
+/// Implements `EFI_FILE_PROTOCOL.Close()`.
+unsafe extern "efiapi" fn file_close(this: *mut FileProtocolV1) -> Status {
+  if this.is_null() {
+    return Status::INVALID_PARAMETER;
+  }
+
+  let file = Box::from_raw(this as *mut SimpleFile);
+  (*file.fs.as_ptr()).open_files -= 1;
+  Status::SUCCESS
+}
+
+/// Implements `EFI_FILE_PROTOCOL.Delete()`, which always fails.
+unsafe extern "efiapi" fn file_delete(this: *mut FileProtocolV1) -> Status {
+  let _ = file_close(this);
+  Status::WARN_DELETE_FAILURE
+}
+
+/// Implements `EFI_FILE_PROTOCOL.Read()`.
+unsafe extern "efiapi" fn file_read(this: *mut FileProtocolV1, buffer_size: *mut usize, buffer: *mut c_void) -> Status {
+  if this.is_null() || buffer_size.is_null() {
+    return Status::INVALID_PARAMETER;
+  }
+  // A null buffer is only valid to query the size of a directory entry
+  if buffer.is_null() && *buffer_size != 0 {
+    return Status::INVALID_PARAMETER;
+  }
+
+  let file = &mut *(this as *mut SimpleFile);
+  let fs = &*file.fs.as_ptr();
+
+  if file.stat.kind != FileKind::Directory {
+    let op = FSOperation::ReadRange { offset: file.position, len: *buffer_size as u64 };
+    let output = match fs.request(&file.path, op) {
+      Ok(ok) => ok,
+      Err(err) => {
+        return err;
+      }
+    };
+
+    let data = output.as_slice();
+    let count = data.len().min(*buffer_size);
+    if count != 0 {
+      core::ptr::copy_nonoverlapping(data.as_ptr(), buffer as *mut u8, count);
+    }
+    *buffer_size = count;
+    file.position += count as u64;
+    return Status::SUCCESS;
+  }
+
+  // Directories return one entry per read, until none remain
+  if file.entries.is_none() {
+    let entries = match fs.request(&file.path, FSOperation::ListDir)
+      .and_then(|output| match FSOutput::from_bytes(FSOperation::ListDir, output.as_slice())? {
+        FSOutput::Entries(entries) => Ok(entries),
+        _ => Err(Status::VOLUME_CORRUPTED)
+      }) {
+      Ok(ok) => ok,
+      Err(err) => {
+        return err;
+      }
+    };
+    file.entries = Some(entries);
+  }
+
+  let entry = match file.entries.as_ref().and_then(|entries| entries.get(file.position as usize)) {
+    Some(some) => some,
+    None => {
+      *buffer_size = 0;
+      return Status::SUCCESS;
+    }
+  };
+
+  let info = file_info(&entry.name, &FileStat { size: entry.size, kind: entry.kind });
+  let status = copy_info(&info, buffer_size, buffer);
+  if status.is_success() {
+    file.position += 1;
+  }
+  status
+}
+
+/// Implements `EFI_FILE_PROTOCOL.Write()`, which always fails.
+unsafe extern "efiapi" fn file_write(_this: *mut FileProtocolV1, _buffer_size: *mut usize, _buffer: *const c_void) -> Status {
+  Status::WRITE_PROTECTED
+}
+
+/// Implements `EFI_FILE_PROTOCOL.GetPosition()`.
+unsafe extern "efiapi" fn file_get_position(this: *const FileProtocolV1, position: *mut u64) -> Status {
+  if this.is_null() || position.is_null() {
+    return Status::INVALID_PARAMETER;
+  }
+
+  let file = &*(this as *const SimpleFile);
+  if file.stat.kind == FileKind::Directory {
+    return Status::UNSUPPORTED;
+  }
+
+  *position = file.position;
+  Status::SUCCESS
+}
+
+/// Implements `EFI_FILE_PROTOCOL.SetPosition()`.
+unsafe extern "efiapi" fn file_set_position(this: *mut FileProtocolV1, position: u64) -> Status {
+  if this.is_null() {
+    return Status::INVALID_PARAMETER;
+  }
+
+  let file = &mut *(this as *mut SimpleFile);
+  if file.stat.kind == FileKind::Directory {
+    // Directories may only be rewound
+    if position != 0 {
+      return Status::UNSUPPORTED;
+    }
+    file.entries = None;
+  }
+
+  file.position = if position == u64::MAX { file.stat.size } else { position };
+  Status::SUCCESS
+}
+
+/// Implements `EFI_FILE_PROTOCOL.GetInfo()`.
+unsafe extern "efiapi" fn file_get_info(
+  this: *mut FileProtocolV1,
+  information_type: *const Guid,
+  buffer_size: *mut usize,
+  buffer: *mut c_void
+) -> Status {
+  if this.is_null() || information_type.is_null() || buffer_size.is_null() {
+    return Status::INVALID_PARAMETER;
+  }
+
+  let file = &*(this as *mut SimpleFile);
+  let info = match *information_type {
+    FileInfo::ID => {
+      let name = file.path.rsplit('/').next().unwrap_or("");
+      file_info(name, &file.stat)
+    }
+    FileSystemInfo::ID => file_system_info(),
+    FileSystemVolumeLabel::ID => {
+      let mut label = Vec::new();
+      push_ucs2(&mut label, "");
+      label
+    }
+    _ => {
+      return Status::UNSUPPORTED;
+    }
+  };
+
+  copy_info(&info, buffer_size, buffer)
+}
+
+/// Implements `EFI_FILE_PROTOCOL.SetInfo()`, which always fails.
+unsafe extern "efiapi" fn file_set_info(
+  _this: *mut FileProtocolV1,
+  _information_type: *const Guid,
+  _buffer_size: usize,
+  _buffer: *const c_void
+) -> Status {
+  Status::WRITE_PROTECTED
+}
+
+/// Implements `EFI_FILE_PROTOCOL.Flush()`, which has nothing to flush.
+unsafe extern "efiapi" fn file_flush(_this: *mut FileProtocolV1) -> Status {
+  Status::SUCCESS
+}
@@ -0,0 +1,143 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use uefi::Status;
+
+use super::*;
+use crate::disk::MemoryDisk;
+
+/// The magic number at the start of a [`ToyFS`] volume.
+const TOY_MAGIC: &[u8; 8] = b"TOYFS\0\0\0";
+
+/// A minimal file system, to exercise drivers on the host.
+/// 
+/// Block 0 holds the magic number followed by 16-byte file entries, each a
+/// NUL-padded name (8 bytes), first LBA (`u32`) and length (`u32`).
+struct ToyFS;
+
+impl ToyFS {
+  /// Finds the extent of a file.
+  fn find(disk: &MemoryDisk, path: &str) -> Result<(u64, usize), Status> {
+    let table = disk.read_block(0)?;
+    if &table[..8] != TOY_MAGIC {
+      return Err(Status::VOLUME_CORRUPTED);
+    }
+
+    for entry in table[16..].chunks_exact(16) {
+      let name = &entry[..8];
+      if name[0] == 0 {
+        break;
+      }
+      if name.split(|byte| *byte == 0).next() == Some(path.as_bytes()) {
+        let lba = u32::from_le_bytes(entry[8..12].try_into().unwrap()) as u64;
+        let len = u32::from_le_bytes(entry[12..16].try_into().unwrap()) as usize;
+        return Ok((lba, len));
+      }
+    }
+
+    Err(Status::NOT_FOUND)
+  }
+}
+
+impl FSDriverOps<MemoryDisk> for ToyFS {
+  fn read(args: &FSDriverArgs<MemoryDisk>) -> Result<Vec<u8>, Status> {
+    let (lba, len) = ToyFS::find(&args.diskreader, args.path)?;
+    args.diskreader.read_bytes(lba * args.diskreader.block_size() as u64, len)
+  }
+
+  fn probe(args: &FSDriverArgs<MemoryDisk>) -> Result<ProbeResult, Status> {
+    let confidence = match args.diskreader.read_block(0) {
+      Ok(block) if block.starts_with(TOY_MAGIC) => ProbeResult::CERTAIN,
+      _ => 0
+    };
+
+    Ok(
+      ProbeResult {
+        confidence,
+        label: String::from("toy"),
+        uuid: String::new()
+      }
+    )
+  }
+}
+
+/// Builds a volume holding `hello` and `empty`.
+fn toy_disk() -> MemoryDisk {
+  let mut data = alloc::vec![0u8; 4 * 512];
+  data[..8].copy_from_slice(TOY_MAGIC);
+  for (i, (name, lba, len)) in [("hello", 1u32, 13u32), ("empty", 2, 0)].into_iter().enumerate() {
+    let entry = &mut data[16 + i * 16..][..16];
+    entry[..name.len()].copy_from_slice(name.as_bytes());
+    entry[8..12].copy_from_slice(&lba.to_le_bytes());
+    entry[12..16].copy_from_slice(&len.to_le_bytes());
+  }
+  data[512..525].copy_from_slice(b"Hello, world!");
+
+  MemoryDisk::new(data, 512).unwrap()
+}
+
+/// Invokes [`ToyFS`] as `fs_prelude!` would, returning its encoded output.
+fn invoke(disk: MemoryDisk, path: &str, op: FSOperation) -> Result<Vec<u8>, Status> {
+  dispatch::<_, ToyFS>(&FSDriverArgs::with_op(path, op, disk))
+}
+
+#[test]
+fn reads_files_from_memory_disk() {
+  let args = FSDriverArgs::new("hello", toy_disk());
+  assert_eq!(args.op, FSOperation::Read);
+  assert_eq!(ToyFS::read(&args).as_deref(), Ok(&b"Hello, world!"[..]));
+
+  let output = invoke(toy_disk(), "hello", FSOperation::Read).unwrap();
+  assert_eq!(FSOutput::from_bytes(FSOperation::Read, &output), Ok(FSOutput::Data(b"Hello, world!")));
+  assert_eq!(invoke(toy_disk(), "empty", FSOperation::Read), Ok(Vec::new()));
+  assert_eq!(invoke(toy_disk(), "missing", FSOperation::Read), Err(Status::NOT_FOUND));
+}
+
+#[test]
+fn derives_default_operations() {
+  let range = |offset, len| invoke(toy_disk(), "hello", FSOperation::ReadRange { offset, len });
+  assert_eq!(range(7, 100).as_deref(), Ok(&b"world!"[..]));
+  assert_eq!(range(0, 5).as_deref(), Ok(&b"Hello"[..]));
+  assert_eq!(range(u64::MAX, u64::MAX), Ok(Vec::new()));
+
+  let stat = invoke(toy_disk(), "hello", FSOperation::Stat).unwrap();
+  assert_eq!(FileStat::from_bytes(&stat), Ok(FileStat { size: 13, kind: FileKind::File }));
+
+  let exists = invoke(toy_disk(), "empty", FSOperation::Exists).unwrap();
+  assert_eq!(FSOutput::from_bytes(FSOperation::Exists, &exists), Ok(FSOutput::Exists(true)));
+  let exists = invoke(toy_disk(), "missing", FSOperation::Exists).unwrap();
+  assert_eq!(FSOutput::from_bytes(FSOperation::Exists, &exists), Ok(FSOutput::Exists(false)));
+
+  assert_eq!(invoke(toy_disk(), "", FSOperation::ListDir), Err(Status::UNSUPPORTED));
+}
+
+#[test]
+fn probes_memory_disk() {
+  let probe = ProbeResult::from_bytes(&invoke(toy_disk(), "", FSOperation::Probe).unwrap()).unwrap();
+  assert_eq!(probe.confidence, ProbeResult::CERTAIN);
+  assert_eq!(probe.label, "toy");
+
+  let blank = MemoryDisk::new(alloc::vec![0; 512], 512).unwrap();
+  let probe = ProbeResult::from_bytes(&invoke(blank, "", FSOperation::Probe).unwrap()).unwrap();
+  assert_eq!(probe.confidence, 0);
+
+  // Errors reading the volume reach the caller
+  let empty = MemoryDisk::new(Vec::new(), 512).unwrap();
+  assert_eq!(invoke(empty, "hello", FSOperation::Read), Err(crate::disk::DISK_OUT_OF_RANGE));
+}
+
+#[test]
+fn rejects_malformed_probe_results() {
+  let probe = ProbeResult { confidence: 200, label: String::from("toy"), uuid: String::from("1234") };
+  let bytes = probe.to_bytes();
+  assert_eq!(ProbeResult::from_bytes(&bytes).map(|probe| probe.confidence), Ok(ProbeResult::CERTAIN));
+
+  assert_eq!(ProbeResult::from_bytes(&bytes[..bytes.len() - 1]), Err(Status::VOLUME_CORRUPTED));
+  assert_eq!(ProbeResult::from_bytes(&bytes[..4]), Err(Status::VOLUME_CORRUPTED));
+
+  // Lengths which would wrap a 32-bit `usize` when summed
+  let mut overflowing = bytes.clone();
+  overflowing[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+  overflowing[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+  assert_eq!(ProbeResult::from_bytes(&overflowing), Err(Status::VOLUME_CORRUPTED));
+}
@@ -1,18 +1,156 @@
 use core::{ffi::c_void, ptr::null_mut};
+use core::ops::BitOr;
+use core::ptr::NonNull;
 
-use uefi::boot::PAGE_SIZE;
+use uefi::boot::{AllocateType, MemoryType, PAGE_SIZE};
+use uefi::Status;
 
+use crate::logging::LogChannel;
 use crate::DRIVER_IO;
 
+/// The magic number identifying a [`DriverIO`] block ("WKTWUDIW" in memory).
+pub const DRIVER_IO_MAGIC: u64 = 0x5749_4455_5754_4B57;
+/// The version of the ABI used to communicate between `wakatiwai` and
+/// drivers.
+/// 
+/// This must be incremented whenever the layout of [`DriverIO`] or any of the
+/// raw argument structs changes.
+pub const DRIVER_IO_ABI_VERSION: u32 = 5;
+
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Capability bits advertised in a [`DriverIOHeader`].
+pub struct DriverCapabilities(pub u64);
+
+impl DriverCapabilities {
+  /// No capabilities.
+  pub const NONE: DriverCapabilities      = DriverCapabilities(0);
+  /// `inptr` points to a [`crate::boot::RawBootDriverArgs`].
+  pub const BOOT_ARGS: DriverCapabilities = DriverCapabilities(1 << 0);
+  /// `inptr` points to a [`crate::fs::RawFSDriverArgs`].
+  pub const FS_ARGS: DriverCapabilities   = DriverCapabilities(1 << 1);
+  /// The [`crate::fs::RawFSDriverArgs`] carry a [`crate::fs::FSOperation`],
+  /// and the output is encoded as described by [`crate::fs::FSOutput`].
+  pub const FS_OPS: DriverCapabilities    = DriverCapabilities(1 << 2);
+  /// The driver is asked to mount a [`crate::fs::FSSession`] rather than
+  /// perform a single operation. On success, `outptr` is the handle the
+  /// [`crate::fs::FSSessionProtocol`] is installed on.
+  pub const FS_SESSION: DriverCapabilities = DriverCapabilities(1 << 3);
+  /// Alongside [`DriverCapabilities::FS_SESSION`], the driver is asked to
+  /// also install the `EFI_SIMPLE_FILE_SYSTEM_PROTOCOL` on the partition.
+  pub const FS_SIMPLE_FS: DriverCapabilities = DriverCapabilities(1 << 4);
+  /// The [`DriverIO`] carries a [`LogChannel`] the driver may log to.
+  pub const LOG: DriverCapabilities = DriverCapabilities(1 << 5);
+
+  /// Returns whether all the bits in `other` are set in `self`.
+  pub const fn contains(&self, other: DriverCapabilities) -> bool {
+    self.0 & other.0 == other.0
+  }
+}
+
+impl BitOr for DriverCapabilities {
+  type Output = DriverCapabilities;
+
+  fn bitor(self, rhs: DriverCapabilities) -> DriverCapabilities {
+    DriverCapabilities(self.0 | rhs.0)
+  }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+/// Identifies a [`DriverIO`] block and the ABI it was written with.
+pub struct DriverIOHeader {
+  /// Always [`DRIVER_IO_MAGIC`].
+  pub magic: u64,
+  /// The ABI version `wakatiwai` was built with.
+  pub abi_version: u32,
+  /// The size of the [`DriverIO`] struct `wakatiwai` was built with.
+  pub size: u32,
+  /// The capabilities `wakatiwai` offers for this invocation.
+  pub capabilities: DriverCapabilities,
+  /// The ABI version the driver was built with.
+  /// 
+  /// This is zero until the driver accepts the [`DriverIO`] block in
+  /// [`crate::driver::find_io_memory`].
+  pub driver_abi_version: u32,
+  /// Reserved, must be zero.
+  pub reserved: u32
+}
+
+impl DriverIOHeader {
+  /// Creates a header for the running ABI version.
+  pub const fn new(capabilities: DriverCapabilities) -> DriverIOHeader {
+    DriverIOHeader {
+      magic: DRIVER_IO_MAGIC,
+      abi_version: DRIVER_IO_ABI_VERSION,
+      size: size_of::<DriverIO>() as u32,
+      capabilities,
+      driver_abi_version: 0,
+      reserved: 0
+    }
+  }
+
+  /// Validates a header against the running ABI version.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `capabilities` (`DriverCapabilities`) - The capabilities the header
+  ///   must advertise.
+  /// 
+  /// # Returns
+  /// 
+  /// - [`uefi_raw::Status::INCOMPATIBLE_VERSION`] if the magic number, ABI
+  ///   version or struct size do not match, or a capability is missing.
+  /// - [`uefi_raw::Status::SUCCESS`] otherwise.
+  pub fn validate(&self, capabilities: DriverCapabilities) -> Status {
+    if self.magic != DRIVER_IO_MAGIC
+      || self.abi_version != DRIVER_IO_ABI_VERSION
+      || self.size as usize != size_of::<DriverIO>()
+      || !self.capabilities.contains(capabilities) {
+      return Status::INCOMPATIBLE_VERSION;
+    }
+
+    Status::SUCCESS
+  }
+}
+
+#[repr(C)]
 /// Used to communicate between `wakatiwai` and drivers.
 pub struct DriverIO {
+  /// Identifies the ABI of this block.
+  pub header: DriverIOHeader,
   /// A pointer to the arguments for a driver.
   pub inptr:  *mut c_void,
+  /// The size of the arguments pointed to by `inptr`.
+  pub insize: usize,
   /// A pointer to the output of a driver.
-  pub outptr: *mut c_void
+  pub outptr: *mut c_void,
+  /// The size of the output pointed to by `outptr`.
+  pub outsize: usize,
+  /// The channel the driver may log to, if [`DriverCapabilities::LOG`] is
+  /// advertised.
+  pub log: LogChannel
 }
 
 impl DriverIO {
+  /// Creates a new [`DriverIO`] pointing to the given arguments.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `capabilities` (`DriverCapabilities`) - The capabilities to advertise.
+  /// - `inptr` (`*mut c_void`) - A pointer to the arguments for a driver.
+  /// - `insize` (`usize`) - The size of the arguments.
+  pub const fn new(capabilities: DriverCapabilities, inptr: *mut c_void, insize: usize) -> DriverIO {
+    DriverIO {
+      header: DriverIOHeader::new(capabilities),
+      inptr,
+      insize,
+      outptr: null_mut(),
+      outsize: 0,
+      log: LogChannel::empty()
+    }
+  }
+
   /// Returns the currently allocated [`DriverIO`], or `None` if unset.
   /// 
   /// # Returns
@@ -21,26 +159,76 @@ impl DriverIO {
   /// - `None` otherwise.
   pub fn allocated_driver_io() -> Option<&'static mut DriverIO> {
     unsafe {
-      if DRIVER_IO.is_none() {
-        return None;
-      }
-
-      return Some(
-        DRIVER_IO.unwrap().as_mut().unwrap()
-      )
+      DRIVER_IO.and_then(|dio| dio.as_mut())
     }
   }
 
   /// Returns the number of pages used by a [`DriverIO`] struct.
   pub const fn page_count() -> usize {
-    (size_of::<DriverIO>() + PAGE_SIZE - 1) / PAGE_SIZE
+    size_of::<DriverIO>().div_ceil(PAGE_SIZE)
   }
 
   /// Resets a DriverIO instance.
   /// 
-  /// The `inptr` and outptr` fields are set to zero.
+  /// The header is rewritten for the running ABI version with no
+  /// capabilities, and all other fields are set to zero.
   pub fn zero(&mut self) {
-    self.inptr  = null_mut();
-    self.outptr = null_mut();
+    *self = DriverIO::new(DriverCapabilities::NONE, null_mut(), 0);
+  }
+
+  /// Copies the output of a driver into newly allocated pages, and points
+  /// `outptr` and `outsize` to them.
+  /// 
+  /// # Returns
+  /// 
+  /// - [`uefi_raw::Status::SUCCESS`] on success.
+  /// - Another `Status` if the pages could not be allocated.
+  pub fn set_output(&mut self, data: &[u8]) -> Status {
+    match copy_to_pages(data) {
+      Ok(ok) => {
+        self.outptr = ok.as_ptr() as *mut c_void;
+        self.outsize = data.len();
+        Status::SUCCESS
+      }
+      Err(err) => err
+    }
   }
-}
\ No newline at end of file
+
+  /// Returns the arguments of a driver as a `T`.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(&T)` if `inptr` is set and `insize` matches the size of `T`.
+  /// - `Err(Status::INCOMPATIBLE_VERSION)` otherwise.
+  /// 
+  /// # Safety
+  /// The caller must ensure that `inptr` actually points to a `T`, usually by
+  /// validating the header's capabilities.
+  pub unsafe fn args<T>(&self) -> Result<&T, Status> {
+    if self.inptr.is_null() || self.insize != size_of::<T>() {
+      return Err(Status::INCOMPATIBLE_VERSION);
+    }
+
+    Ok(&*(self.inptr as *const T))
+  }
+}
+
+/// Returns the number of pages holding `len` bytes of driver output.
+pub const fn output_page_count(len: usize) -> usize {
+  if len == 0 { 1 } else { len.div_ceil(PAGE_SIZE) }
+}
+
+/// Copies driver output into newly allocated pages, which the caller must
+/// free with [`output_page_count`] pages.
+pub(crate) fn copy_to_pages(data: &[u8]) -> Result<NonNull<u8>, Status> {
+  let ptr = uefi::boot::allocate_pages(
+    AllocateType::AnyPages,
+    MemoryType::LOADER_DATA,
+    output_page_count(data.len())
+  ).map_err(|err| err.status())?;
+
+  unsafe {
+    core::ptr::copy(data.as_ptr(), ptr.as_ptr(), data.len());
+  }
+  Ok(ptr)
+}
@@ -5,24 +5,41 @@
 )]
 
 extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
 
 pub mod boot;
 pub mod fs;
 pub mod wakatiwai;
 pub mod driver;
 pub mod disk;
+pub mod partition;
 pub mod io;
+pub mod manifest;
+pub mod verify;
+pub mod error;
+pub mod logging;
+pub mod watchdog;
+mod loaded;
 
-use crate::io::DriverIO;
+pub use error::UdiveError;
+pub use log;
+pub use loaded::{DriverLifecycle, LoadedDriver};
 
+use crate::io::{DriverCapabilities, DriverIO, DRIVER_IO_ABI_VERSION};
+use crate::logging::{DriverLog, LogChannel};
+use crate::manifest::DriverManifest;
+
+use alloc::borrow::Cow;
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use core::ptr::NonNull;
 
 use uefi::boot::{open_protocol, AllocateType, LoadImageSource, MemoryType, OpenProtocolAttributes, OpenProtocolParams};
 use uefi::proto::device_path::build::media::FilePath;
+use uefi::proto::loaded_image::LoadedImage;
 use uefi::proto::device_path::build::DevicePathBuilder;
-use uefi::proto::device_path::{DeviceSubType, LoadedImageDevicePath};
+use uefi::proto::device_path::{DevicePath, DeviceSubType, LoadedImageDevicePath};
 use uefi::{cstr16, CStr16, CString16, Handle, Status};
 
 /// A reference to the [`DriverIO`] currently in use.
@@ -32,7 +49,13 @@ pub const BOOT_DRIVER_IO_MEMTYPE: MemoryType  = MemoryType::custom(0xCA11_B007);
 /// The memory type used to store arguments and return values for file system drivers.
 pub const FSYS_DRIVER_IO_MEMTYPE: MemoryType  = MemoryType::custom(0xCA11_F575);
 
-const DRIVER_DIRECTORY: &CStr16           = cstr16!("\\EFI\\wakatiwai\\drivers");
+/// Creates an OEM-defined error status, unique to this crate.
+pub(crate) const fn oem_error(code: usize) -> Status {
+  Status(Status::ERROR_BIT | (1 << (usize::BITS - 2)) | code)
+}
+
+/// The directory drivers are searched for in by default.
+pub const DRIVER_DIRECTORY: &CStr16       = cstr16!("\\EFI\\wakatiwai\\drivers");
 const BOOT_DRIVER_DIRECTORY: &CStr16      = cstr16!("boot");
 const FSYS_DRIVER_DIRECTORY: &CStr16      = cstr16!("fs");
 
@@ -45,11 +68,72 @@ pub enum DriverType {
   FS
 }
 
+/// Returned when a driver which has already run is invoked again without
+/// being reloaded.
+pub const DRIVER_NOT_REENTRANT: Status = oem_error(0x2E17);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+/// The stages of a driver's lifecycle.
+pub enum DriverState {
+  /// The driver has been found, but its image is not loaded.
+  #[default]
+  Discovered,
+  /// The driver's image is loaded and may be invoked.
+  Loaded,
+  /// The driver is being invoked.
+  Running,
+  /// The driver has been invoked and has returned. UEFI does not allow an
+  /// image to be started twice, so the driver must be reloaded before it is
+  /// invoked again.
+  Exited,
+  /// The driver's image has been unloaded, and may be loaded again.
+  Unloaded
+}
+
+#[macro_export]
+/// Embeds a driver's EFI file in the loader at build time.
+/// 
+/// Expands to a call to [`BootDriver::from_buffer`] or
+/// [`FSDriver::from_buffer`] with the contents of the file, which is located
+/// as with [`include_bytes`]. Embedded drivers can be registered with a
+/// [`wakatiwai::DriverRegistry`] so that they are available even when no
+/// drivers are installed on disk. A detached signature may also be embedded,
+/// to be checked against the [`verify::signature_policy`].
+/// 
+/// ```ignore
+/// let linux = include_driver!(boot "linux", "../drivers/linux.efi")?;
+/// let fat = include_driver!(fs "fat", "../drivers/fat.efi", "../drivers/fat.sig")?;
+/// ```
+macro_rules! include_driver {
+  (boot $name:expr, $path:literal) => {
+    $crate::BootDriver::from_buffer($name, include_bytes!($path) as &'static [u8])
+  };
+  (boot $name:expr, $path:literal, $signature:literal) => {
+    $crate::include_driver!(boot $name, $path)
+      .map(|driver| driver.with_signature(include_bytes!($signature) as &'static [u8]))
+  };
+  (fs $name:expr, $path:literal) => {
+    $crate::FSDriver::from_buffer($name, include_bytes!($path) as &'static [u8])
+  };
+  (fs $name:expr, $path:literal, $signature:literal) => {
+    $crate::include_driver!(fs $name, $path)
+      .map(|driver| driver.with_signature(include_bytes!($signature) as &'static [u8]))
+  };
+}
+
 #[derive(Clone, Debug)]
 struct Driver {
   name: CString16,
   driver_type: Option<DriverType>,
-  exec_handle: Option<Handle>
+  exec_handle: Option<Handle>,
+  state: DriverState,
+  manifest: Option<DriverManifest>,
+  volume: Option<Handle>,
+  root: CString16,
+  buffer: Option<Cow<'static, [u8]>>,
+  signature: Option<Cow<'static, [u8]>>,
+  log: DriverLog,
+  timeout: Option<usize>
 }
 
 #[derive(Clone, Debug)]
@@ -60,15 +144,48 @@ pub struct BootDriver(Driver);
 pub struct FSDriver(Driver);
 
 impl Driver {
+  fn from_buffer(name: &str, driver_type: DriverType, buffer: Cow<'static, [u8]>) -> Result<Driver, UdiveError> {
+    Ok(
+      Driver {
+        name: CString16::try_from(name).map_err(|_| {
+          UdiveError::load(Status::INVALID_PARAMETER).with_message("driver name is not valid UCS-2")
+        })?,
+        driver_type: Some(driver_type),
+        exec_handle: None,
+        state: DriverState::Discovered,
+        manifest: None,
+        volume: None,
+        root: CString16::new(),
+        buffer: Some(buffer),
+        signature: None,
+        log: DriverLog::default(),
+        timeout: None
+      }
+    )
+  }
+
   pub fn name(&self) -> String {
     self.name.to_string().replace(".efi", "")
   }
 
+  pub fn is_loaded(&self) -> bool {
+    self.exec_handle.is_some()
+  }
+
+  pub fn state(&self) -> DriverState {
+    self.state
+  }
+
   pub fn path(&self) -> Option<CString16> {
+    // Drivers loaded from memory have no file
+    if self.buffer.is_some() {
+      return None;
+    }
+
     match self.driver_type {
       Some(some) => {
         let mut ret = CString16::new();
-        ret.push_str(DRIVER_DIRECTORY);
+        ret.push_str(&self.root);
         ret.push_str(cstr16!("\\"));
         ret.push_str(
           match some {
@@ -87,114 +204,348 @@ impl Driver {
     }
   }
 
-  pub fn load(&mut self) -> Status {
-    // Disallow reloading drivers
-    if self.exec_handle.is_some() {
-      return Status::INVALID_PARAMETER;
+  pub fn load(&mut self) -> Result<(), UdiveError> {
+    // Disallow loading drivers twice
+    if !matches!(self.state, DriverState::Discovered | DriverState::Unloaded) {
+      return Err(self.error(UdiveError::load(Status::INVALID_PARAMETER)).with_message("driver is already loaded"));
+    }
+
+    // Load from memory if the driver was not found on disk
+    if let Some(buffer) = &self.buffer {
+      let verify_status = verify::check_driver(&self.name(), buffer, self.signature.as_deref());
+      if verify_status.is_error() {
+        return Err(self.error(UdiveError::load(verify_status)).with_message("signature verification failed"));
+      }
+
+      match uefi::boot::load_image(
+        uefi::boot::image_handle(),
+        LoadImageSource::FromBuffer {
+          buffer,
+          file_path: None
+        }
+      ) {
+        Ok(ok) => {
+          self.exec_handle = Some(ok);
+          self.state = DriverState::Loaded;
+          return Ok(());
+        }
+        Err(err) => {
+          return Err(self.error(UdiveError::load(err.status())));
+        }
+      }
     }
 
     // Build the fully-qualified device path to load
+    let driver_path = match self.path() {
+      Some(some) => some,
+      None => {
+        return Err(self.error(UdiveError::load(Status::NOT_FOUND)).with_message("driver has no path"));
+      }
+    };
     let mut driver_devpath_build_vec = Vec::new();
-    let mut driver_devpath_builder = DevicePathBuilder::with_vec(&mut driver_devpath_build_vec);
-  
-    // Push the partition device path (which wakatiwai resides on)
-    let ldimg = unsafe {
-      open_protocol::<LoadedImageDevicePath>(
-        OpenProtocolParams {
-          handle: uefi::boot::image_handle(),
-          agent: uefi::boot::image_handle(),
-          controller: None
-        },
-        OpenProtocolAttributes::GetProtocol
-      ).unwrap()
+    let driver_devpath = match self.build_device_path(&mut driver_devpath_build_vec, &driver_path) {
+      Ok(ok) => ok,
+      Err(err) => {
+        return Err(self.error(UdiveError::load(err)).with_message("driver device path could not be built"));
+      }
     };
-    
-    for partition_devpath_node in ldimg.node_iter() {
-      // Ignore the file path part, as that points to wakatiwai
-      if partition_devpath_node.sub_type() == DeviceSubType(4) {
-        break;
+
+    // Load the driver, verifying the image read if required
+    let load_status = match verify::signature_policy() {
+      verify::SignaturePolicy::Off => {
+        uefi::boot::load_image(
+          uefi::boot::image_handle(),
+          LoadImageSource::FromDevicePath {
+            device_path: driver_devpath,
+            boot_policy: uefi::proto::BootPolicy::ExactMatch
+          }
+        )
       }
-      
-      driver_devpath_builder = driver_devpath_builder.push(&partition_devpath_node).unwrap();
-    }
-    // Push the actual path of the file
-    driver_devpath_builder = driver_devpath_builder.push(&FilePath { path_name: &self.path().unwrap() }).unwrap();
-    
-    // Load the driver
-    match uefi::boot::load_image(
-      uefi::boot::image_handle(),
-      LoadImageSource::FromDevicePath {
-        device_path: driver_devpath_builder.finalize().unwrap(),
-        boot_policy: uefi::proto::BootPolicy::ExactMatch
+      _ => {
+        let (image, signature) = match self.read_signed_image(&driver_path) {
+          Ok(ok) => ok,
+          Err(err) => {
+            return Err(self.error(UdiveError::load(err)).with_message("driver image could not be read"));
+          }
+        };
+
+        let verify_status = verify::check_driver(&self.name(), &image, signature.as_deref());
+        if verify_status.is_error() {
+          return Err(self.error(UdiveError::load(verify_status)).with_message("signature verification failed"));
+        }
+
+        uefi::boot::load_image(
+          uefi::boot::image_handle(),
+          LoadImageSource::FromBuffer {
+            buffer: &image,
+            file_path: Some(driver_devpath)
+          }
+        )
       }
-    ) {
+    };
+
+    match load_status {
       Ok(ok) => {
         self.exec_handle = Some(ok);
+        self.state = DriverState::Loaded;
       }
       Err(err) => {
-        return err.status();
+        return Err(self.error(UdiveError::load(err.status())));
       }
     }
   
-    Status::SUCCESS
+    Ok(())
   }
 
-  pub fn unload(&mut self) -> Status {
-    // Cannot unload an unloaded driver
-    if self.exec_handle.is_none() {
-      return Status::INVALID_PARAMETER;
+  /// Builds the fully-qualified device path of the driver's EFI file.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(&DevicePath)` on success, stored in `buffer`.
+  /// - `Err(Status)` if the device path of the volume could not be opened, or
+  ///   the device path could not be built.
+  fn build_device_path<'a>(&self, buffer: &'a mut Vec<u8>, driver_path: &CStr16) -> Result<&'a DevicePath, Status> {
+    let build_error = |_| Status::INVALID_PARAMETER;
+    let mut driver_devpath_builder = DevicePathBuilder::with_vec(buffer);
+
+    match self.volume {
+      // Push the device path of the volume the driver was found on
+      Some(volume) => {
+        let volume_devpath = unsafe {
+          open_protocol::<DevicePath>(
+            OpenProtocolParams {
+              handle: volume,
+              agent: uefi::boot::image_handle(),
+              controller: None
+            },
+            OpenProtocolAttributes::GetProtocol
+          ).map_err(|err| err.status())?
+        };
+
+        for volume_devpath_node in volume_devpath.node_iter() {
+          driver_devpath_builder = driver_devpath_builder.push(&volume_devpath_node).map_err(build_error)?;
+        }
+      }
+      // Push the partition device path (which wakatiwai resides on)
+      None => {
+        let ldimg = unsafe {
+          open_protocol::<LoadedImageDevicePath>(
+            OpenProtocolParams {
+              handle: uefi::boot::image_handle(),
+              agent: uefi::boot::image_handle(),
+              controller: None
+            },
+            OpenProtocolAttributes::GetProtocol
+          ).map_err(|err| err.status())?
+        };
+
+        for partition_devpath_node in ldimg.node_iter() {
+          // Ignore the file path part, as that points to wakatiwai
+          if partition_devpath_node.sub_type() == DeviceSubType(4) {
+            break;
+          }
+
+          driver_devpath_builder = driver_devpath_builder.push(&partition_devpath_node).map_err(build_error)?;
+        }
+      }
     }
+    // Push the actual path of the file
+    driver_devpath_builder = driver_devpath_builder.push(&FilePath { path_name: driver_path }).map_err(build_error)?;
 
-    match uefi::boot::unload_image(self.exec_handle.unwrap()) {
-      Ok(_) => {
-        return Status::SUCCESS;
+    driver_devpath_builder.finalize().map_err(build_error)
+  }
+
+  /// Reads the driver's EFI file and its detached signature, if any, from the
+  /// volume the driver resides on.
+  fn read_signed_image(&self, driver_path: &CStr16) -> Result<(Vec<u8>, Option<Vec<u8>>), Status> {
+    let volume = match self.volume {
+      Some(some) => some,
+      None => wakatiwai::DriverVolume::Image.locate()?
+    };
+
+    let image = wakatiwai::read_file(volume, driver_path)?.ok_or(Status::NOT_FOUND)?;
+
+    let signature_path = wakatiwai::sibling_path(driver_path, "sig")?;
+    let signature = wakatiwai::read_file(volume, &signature_path)?;
+
+    Ok((image, signature))
+  }
+
+  pub fn unload(&mut self) -> Result<(), UdiveError> {
+    match (self.state, self.exec_handle) {
+      // Cannot unload a running driver
+      (DriverState::Running, _) => {
+        Err(self.error(UdiveError::load(Status::ACCESS_DENIED)).with_message("driver is running"))
       }
-      Err(err) => {
-        return err.status();
+      (_, Some(exec_handle)) => {
+        match uefi::boot::unload_image(exec_handle) {
+          Ok(_) => {
+            self.exec_handle = None;
+            self.state = DriverState::Unloaded;
+            Ok(())
+          }
+          Err(err) => Err(self.error(UdiveError::load(err.status())))
+        }
+      }
+      // Applications are unloaded by the firmware as soon as they exit
+      (DriverState::Exited, None) => {
+        self.state = DriverState::Unloaded;
+        Ok(())
+      }
+      // Cannot unload an unloaded driver
+      (_, None) => {
+        Err(self.error(UdiveError::load(Status::INVALID_PARAMETER)).with_message("driver is not loaded"))
       }
     }
   }
 
-  fn invoke(&mut self, invoke_io: &mut DriverIO, memtype: MemoryType) -> Result<Status, Status> {
-    // Cannot invoke an unloaded image
-    if self.exec_handle.is_none() {
-      return Err(Status::NOT_READY);
+  pub fn reload(&mut self) -> Result<(), UdiveError> {
+    if matches!(self.state, DriverState::Loaded | DriverState::Exited) {
+      self.unload()?;
+    }
+
+    self.load()
+  }
+
+  /// Attaches the name of this driver to an error.
+  fn error(&self, err: UdiveError) -> UdiveError {
+    err.with_driver(self.name())
+  }
+
+  fn invoke(&mut self, invoke_io: &mut DriverIO, memtype: MemoryType) -> Result<(), UdiveError> {
+    // Cannot invoke an image which has already run, or is not loaded
+    match self.state {
+      DriverState::Loaded => {}
+      DriverState::Running | DriverState::Exited => {
+        return Err(self.error(UdiveError::load(DRIVER_NOT_REENTRANT)).with_message("driver has already been invoked"));
+      }
+      DriverState::Discovered | DriverState::Unloaded => {
+        return Err(self.error(UdiveError::load(Status::NOT_READY)).with_message("driver is not loaded"));
+      }
+    }
+    let exec_handle = match self.exec_handle {
+      Some(some) => some,
+      None => {
+        return Err(self.error(UdiveError::load(Status::NOT_READY)).with_message("driver is not loaded"));
+      }
+    };
+
+    // Refuse to hand out a malformed IO block
+    let validate_status = invoke_io.header.validate(DriverCapabilities::NONE);
+    if validate_status.is_error() {
+      return Err(self.error(UdiveError::abi_mismatch(validate_status)).with_message("malformed driver IO block"));
     }
 
+    // Arm the watchdog, which is disarmed when dropped. File system drivers
+    // are invoked for every file read, so their record is kept until cleared
+    let watchdog = match self.timeout.or(watchdog::invocation_timeout()) {
+      Some(seconds) if seconds > 0 => {
+        let clear_on_return = self.driver_type == Some(DriverType::BOOT);
+        Some(watchdog::Watchdog::arm(&self.name(), seconds, clear_on_return).map_err(|err| self.error(err))?)
+      }
+      _ => None
+    };
+
     // Allocate IO memory
     unsafe {
       let alloc_status = self.allocate_io_memory(memtype);
       if alloc_status.is_error() {
-        return Err(alloc_status);
+        return Err(self.error(UdiveError::io_allocation(alloc_status)));
       }
     }
 
-    // Bind to input
-    unsafe {
-      DRIVER_IO.unwrap().as_mut().unwrap().inptr = invoke_io.inptr;
-    }
+    // Bind to input, offering a log channel if one can be allocated
+    let mut log_channel = unsafe {
+      let Some(dio) = DRIVER_IO.and_then(|dio| dio.as_mut()) else {
+        return Err(self.error(UdiveError::io_allocation(Status::ABORTED)));
+      };
+      dio.header.capabilities = invoke_io.header.capabilities;
+      dio.inptr = invoke_io.inptr;
+      dio.insize = invoke_io.insize;
+
+      // Only allocated once bound, as nothing frees the channel before then
+      let log_channel = LogChannel::allocate();
+      if let Some(channel) = log_channel {
+        dio.header.capabilities = dio.header.capabilities | DriverCapabilities::LOG;
+        dio.log = channel;
+      }
+      log_channel
+    };
+
+    // Applications are unloaded by the firmware when they exit, unlike
+    // drivers, which stay resident
+    let is_application = unsafe {
+      open_protocol::<LoadedImage>(
+        OpenProtocolParams {
+          handle: exec_handle,
+          agent: uefi::boot::image_handle(),
+          controller: None
+        },
+        OpenProtocolAttributes::GetProtocol
+      ).is_ok_and(|loaded_image| loaded_image.code_type() == MemoryType::LOADER_CODE)
+    };
 
     // Start the image
-    uefi::println!("Starting driver...");
-    let driver_status = match uefi::boot::start_image(self.exec_handle.unwrap()) {
+    log::debug!("Starting driver {}", self.name());
+    self.state = DriverState::Running;
+    let driver_status = match uefi::boot::start_image(exec_handle) {
       Ok(_) => Status::SUCCESS,
       Err(err) => err.status()
     };
+    self.state = DriverState::Exited;
+    drop(watchdog);
+    if is_application {
+      self.exec_handle = None;
+    }
 
-    // Bind to output
-    unsafe {
-      invoke_io.outptr = DRIVER_IO.unwrap().as_mut().unwrap().outptr;
+    // Collect the records logged by the driver, trusting only the length it
+    // reports
+    if let Some(channel) = &mut log_channel {
+      unsafe {
+        if let Some(dio) = DRIVER_IO.and_then(|dio| dio.as_mut()) {
+          channel.len = dio.log.len;
+          channel.dropped = dio.log.dropped;
+        }
+        self.log = channel.read();
+        channel.free();
+      }
+    } else {
+      self.log = DriverLog::default();
     }
+    self.log.forward(&self.name());
+
+    // Bind to output, provided the driver acknowledged our ABI
+    let abi_status = unsafe {
+      match DRIVER_IO.and_then(|dio| dio.as_mut()) {
+        Some(dio) if dio.header.validate(invoke_io.header.capabilities).is_success()
+          && dio.header.driver_abi_version == DRIVER_IO_ABI_VERSION => {
+          invoke_io.outptr = dio.outptr;
+          invoke_io.outsize = dio.outsize;
+          invoke_io.header.driver_abi_version = dio.header.driver_abi_version;
+          Status::SUCCESS
+        }
+        _ => Status::INCOMPATIBLE_VERSION
+      }
+    };
 
     // Free IO memory
     unsafe {
       let free_status = self.free_io_memory();
       if free_status.is_error() {
-        return Err(free_status);
+        return Err(self.error(UdiveError::io_allocation(free_status)));
       }
     }
 
-    return Ok(driver_status);
+    // A driver that never accepted the IO block may have misread it
+    if abi_status.is_error() {
+      return Err(self.error(UdiveError::abi_mismatch(abi_status)));
+    }
+
+    if driver_status.is_error() {
+      return Err(self.error(UdiveError::driver(driver_status)));
+    }
+
+    Ok(())
   }
 
   unsafe fn allocate_io_memory(&self, memtype: MemoryType) -> Status {
@@ -223,12 +574,12 @@ impl Driver {
 
   unsafe fn free_io_memory(&self) -> Status {
     // Cannot free memory that does not exist
-    if DRIVER_IO.is_none() {
+    let Some(pages) = DRIVER_IO.and_then(|dio| NonNull::new(dio as *mut u8)) else {
       return Status::ABORTED;
-    }
+    };
 
     match uefi::boot::free_pages(
-      NonNull::new(DRIVER_IO.unwrap() as *mut u8).unwrap(),
+      pages,
       DriverIO::page_count()
     ) {
       Ok(_) => {
@@ -0,0 +1,136 @@
+use core::ops::{Deref, DerefMut};
+
+use uefi::Status;
+
+use crate::{BootDriver, DriverState, FSDriver, UdiveError};
+
+/// A driver whose image can be loaded and unloaded.
+/// 
+/// Implemented by [`BootDriver`] and [`FSDriver`] so that either may be held
+/// by a [`LoadedDriver`].
+pub trait DriverLifecycle {
+  /// Loads the driver.
+  fn load(&mut self) -> Result<(), UdiveError>;
+  /// Unloads the driver.
+  fn unload(&mut self) -> Result<(), UdiveError>;
+  /// Unloads the driver if it is loaded, and loads it again.
+  fn reload(&mut self) -> Result<(), UdiveError>;
+  /// Returns the lifecycle state of the driver.
+  fn state(&self) -> DriverState;
+}
+
+impl DriverLifecycle for BootDriver {
+  fn load(&mut self) -> Result<(), UdiveError> {
+    BootDriver::load(self)
+  }
+
+  fn unload(&mut self) -> Result<(), UdiveError> {
+    BootDriver::unload(self)
+  }
+
+  fn reload(&mut self) -> Result<(), UdiveError> {
+    BootDriver::reload(self)
+  }
+
+  fn state(&self) -> DriverState {
+    BootDriver::state(self)
+  }
+}
+
+impl DriverLifecycle for FSDriver {
+  fn load(&mut self) -> Result<(), UdiveError> {
+    FSDriver::load(self)
+  }
+
+  fn unload(&mut self) -> Result<(), UdiveError> {
+    FSDriver::unload(self)
+  }
+
+  fn reload(&mut self) -> Result<(), UdiveError> {
+    FSDriver::reload(self)
+  }
+
+  fn state(&self) -> DriverState {
+    FSDriver::state(self)
+  }
+}
+
+/// A loaded driver, which is unloaded when dropped.
+/// 
+/// The driver is reached through [`Deref`], so it may be invoked as usual. A
+/// driver can only be invoked once per load; use [`LoadedDriver::reload`] to
+/// invoke it again.
+pub struct LoadedDriver<T: DriverLifecycle> {
+  /// The driver, or `None` once it has been released.
+  driver: Option<T>
+}
+
+impl<T: DriverLifecycle> LoadedDriver<T> {
+  /// Loads a driver.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(LoadedDriver)` on success.
+  /// - `Err((T, UdiveError))` if the driver could not be loaded, returning
+  ///   the driver.
+  pub fn load(mut driver: T) -> Result<LoadedDriver<T>, (T, UdiveError)> {
+    if let Err(err) = driver.load() {
+      return Err((driver, err));
+    }
+
+    Ok(LoadedDriver { driver: Some(driver) })
+  }
+
+  /// Reloads the driver, so that it may be invoked again after it has
+  /// exited.
+  pub fn reload(&mut self) -> Result<(), UdiveError> {
+    match &mut self.driver {
+      Some(driver) => driver.reload(),
+      None => Err(UdiveError::load(Status::NOT_READY))
+    }
+  }
+
+  /// Unloads the driver.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(T)` on success, containing the unloaded driver.
+  /// - `Err(UdiveError)` if the driver could not be unloaded. The driver is
+  ///   not unloaded again when dropped.
+  pub fn unload(mut self) -> Result<T, UdiveError> {
+    let mut driver = self.driver.take().ok_or(UdiveError::load(Status::NOT_READY))?;
+    driver.unload()?;
+
+    Ok(driver)
+  }
+
+  /// Releases the driver without unloading it, e.g. so that a file system
+  /// driver may keep serving a mounted session.
+  pub fn keep_loaded(mut self) -> T {
+    self.driver.take().unwrap()
+  }
+}
+
+impl<T: DriverLifecycle> Deref for LoadedDriver<T> {
+  type Target = T;
+
+  fn deref(&self) -> &T {
+    // The driver is only taken when the guard is consumed
+    self.driver.as_ref().unwrap()
+  }
+}
+
+impl<T: DriverLifecycle> DerefMut for LoadedDriver<T> {
+  fn deref_mut(&mut self) -> &mut T {
+    self.driver.as_mut().unwrap()
+  }
+}
+
+impl<T: DriverLifecycle> Drop for LoadedDriver<T> {
+  fn drop(&mut self) {
+    if let Some(driver) = &mut self.driver
+      && matches!(driver.state(), DriverState::Loaded | DriverState::Exited) {
+      let _ = driver.unload();
+    }
+  }
+}
@@ -0,0 +1,311 @@
+use core::fmt::Write;
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+use uefi::data_types::Align;
+use uefi::proto::media::file::{Directory, File, FileAttribute, FileInfo, FileMode, RegularFile};
+use uefi::{cstr16, CStr16, CString16, Status};
+
+use crate::UdiveError;
+
+#[cfg(test)]
+mod tests;
+
+/// The directory on the ESP boot logs are written to.
+pub const LOG_DIRECTORY: &CStr16 = cstr16!("\\EFI\\wakatiwai\\logs");
+
+/// The state of the installed [`BootLogger`].
+static mut BOOT_LOG: Option<BootLog> = None;
+/// The logger installed by [`install_boot_logger`].
+static BOOT_LOGGER: BootLogger = BootLogger;
+/// Whether a record is being recorded, so that records logged while doing so
+/// (e.g. by the console after boot services have exited) are discarded.
+static mut RECORDING: bool = false;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Configures the [`BootLogger`].
+pub struct BootLogConfig {
+  /// The most verbose level of records kept.
+  pub level: LevelFilter,
+  /// The most verbose level of records also printed to the console.
+  pub console_level: LevelFilter,
+  /// The number of bytes of records held in memory before the oldest are
+  /// discarded.
+  pub max_buffered: usize,
+  /// The size `boot.log` may grow to before it is rotated.
+  pub max_file_size: u64,
+  /// The number of log files kept, including `boot.log`. Rotated logs are
+  /// named `boot.1.log` (the most recent) to `boot.<max_files - 1>.log`.
+  pub max_files: usize,
+  /// Whether to write the log to the ESP whenever an error is recorded.
+  pub flush_on_error: bool
+}
+
+impl Default for BootLogConfig {
+  fn default() -> BootLogConfig {
+    BootLogConfig {
+      level: LevelFilter::Info,
+      console_level: LevelFilter::Warn,
+      max_buffered: 64 * 1024,
+      max_file_size: 256 * 1024,
+      max_files: 4,
+      flush_on_error: true
+    }
+  }
+}
+
+/// The records held by the [`BootLogger`].
+struct BootLog {
+  config: BootLogConfig,
+  /// Formatted records not yet written to the ESP.
+  buffer: Vec<u8>
+}
+
+impl BootLog {
+  /// Appends a formatted record, discarding the oldest records if the buffer
+  /// is full.
+  fn push(&mut self, line: &str) {
+    if line.len() > self.config.max_buffered {
+      return;
+    }
+
+    let excess = (self.buffer.len() + line.len()).saturating_sub(self.config.max_buffered);
+    if excess > 0 {
+      // Cut after the first newline at or beyond the excess, which may be
+      // the one just before it
+      let cut = self.buffer[excess - 1..].iter()
+        .position(|byte| *byte == b'\n')
+        .map_or(self.buffer.len(), |newline| excess + newline);
+      self.buffer.drain(..cut);
+    }
+
+    self.buffer.extend_from_slice(line.as_bytes());
+  }
+}
+
+/// A [`log::Log`] implementation which buffers loader and driver records in
+/// memory, and appends them to `boot.log` in [`LOG_DIRECTORY`] when flushed.
+/// 
+/// Records collected from drivers (see [`super::DriverLog`]) are passed to
+/// it with the name of the driver as their target. The log is written on
+/// demand with [`flush_boot_log`] (or `log::logger().flush()`), and whenever
+/// an error is recorded if [`BootLogConfig::flush_on_error`] is set, so that
+/// it survives a failed boot.
+pub struct BootLogger;
+
+impl Log for BootLogger {
+  fn enabled(&self, metadata: &Metadata) -> bool {
+    unsafe {
+      BOOT_LOG.as_ref().is_some_and(|boot_log| metadata.level() <= boot_log.config.level)
+    }
+  }
+
+  fn log(&self, record: &Record) {
+    if !self.enabled(record.metadata()) || unsafe { RECORDING } {
+      return;
+    }
+
+    let mut line = String::new();
+    let _ = match uefi::runtime::get_time() {
+      Ok(time) => write!(
+        line,
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} ",
+        time.year(), time.month(), time.day(), time.hour(), time.minute(), time.second()
+      ),
+      Err(_) => write!(line, "????-??-?? ??:??:?? ")
+    };
+    let _ = writeln!(line, "{:<5} {}: {}", record.level(), record.target(), record.args());
+
+    unsafe {
+      let Some(boot_log) = BOOT_LOG.as_mut() else {
+        return;
+      };
+      boot_log.push(&line);
+
+      RECORDING = true;
+      if record.level() <= boot_log.config.console_level {
+        uefi::print!("{}", line);
+      }
+      if boot_log.config.flush_on_error && record.level() == Level::Error {
+        let _ = flush_boot_log();
+      }
+      RECORDING = false;
+    }
+  }
+
+  fn flush(&self) {
+    let _ = flush_boot_log();
+  }
+}
+
+/// Installs the [`BootLogger`] as the logger.
+/// 
+/// # Returns
+/// 
+/// - `Ok(())` on success.
+/// - `Err(SetLoggerError)` if a logger is already installed.
+pub fn install_boot_logger(config: BootLogConfig) -> Result<(), SetLoggerError> {
+  log::set_logger(&BOOT_LOGGER)?;
+
+  unsafe {
+    BOOT_LOG = Some(
+      BootLog {
+        config,
+        buffer: Vec::new()
+      }
+    );
+  }
+  log::set_max_level(config.level);
+  Ok(())
+}
+
+/// Appends the records held by the [`BootLogger`] to `boot.log` in
+/// [`LOG_DIRECTORY`], on the volume wakatiwai was loaded from.
+/// 
+/// If this would grow `boot.log` beyond [`BootLogConfig::max_file_size`],
+/// the logs are rotated first. Should more records be held than fit in one
+/// file, only the most recent are written.
+/// 
+/// # Returns
+/// 
+/// - `Ok(())` on success, or if the [`BootLogger`] is not installed.
+/// - `Err(UdiveError::Disk)` if the log could not be written. The records
+///   are held, to be written by the next flush.
+pub fn flush_boot_log() -> Result<(), UdiveError> {
+  let Some(boot_log) = (unsafe { BOOT_LOG.as_mut() }) else {
+    return Ok(());
+  };
+  if boot_log.buffer.is_empty() {
+    return Ok(());
+  }
+
+  write_log(&boot_log.config, &boot_log.buffer)
+    .map_err(|err| UdiveError::disk(err).with_message("boot log could not be written"))?;
+  boot_log.buffer.clear();
+
+  Ok(())
+}
+
+/// Appends records to `boot.log`, rotating the logs if required.
+fn write_log(config: &BootLogConfig, records: &[u8]) -> Result<(), Status> {
+  let records = newest_records(records, usize::try_from(config.max_file_size).unwrap_or(usize::MAX));
+
+  let mut directory = open_log_directory()?;
+  let mut file = open_log_file(&mut directory, &log_file_name(0)?)?;
+  let info: Box<FileInfo> = file.get_boxed_info().map_err(|err| err.status())?;
+
+  if info.file_size() > 0 && info.file_size().saturating_add(records.len() as u64) > config.max_file_size {
+    file.close();
+    rotate(&mut directory, config.max_files)?;
+    file = open_log_file(&mut directory, &log_file_name(0)?)?;
+  }
+
+  file.set_position(RegularFile::END_OF_FILE).map_err(|err| err.status())?;
+  file.write(records).map_err(|err| err.status())?;
+  file.flush().map_err(|err| err.status())
+}
+
+/// Returns the most recent whole records which fit in `max_len` bytes.
+/// 
+/// As with [`BootLog::push`], the records are cut after a newline, so that
+/// no record is written in part.
+fn newest_records(records: &[u8], max_len: usize) -> &[u8] {
+  let excess = records.len().saturating_sub(max_len);
+  if excess == 0 {
+    return records;
+  }
+
+  let cut = records[excess - 1..].iter()
+    .position(|byte| *byte == b'\n')
+    .map_or(records.len(), |newline| excess + newline);
+  &records[cut..]
+}
+
+/// Opens [`LOG_DIRECTORY`] on the volume wakatiwai was loaded from, creating
+/// it if required.
+fn open_log_directory() -> Result<Directory, Status> {
+  let mut filesystem = uefi::boot::get_image_file_system(uefi::boot::image_handle()).map_err(|err| err.status())?;
+  let mut directory = filesystem.open_volume().map_err(|err| err.status())?;
+
+  let path = String::from_utf16_lossy(LOG_DIRECTORY.to_u16_slice());
+  for component in path.split('\\').filter(|component| !component.is_empty()) {
+    let component = CString16::try_from(component).map_err(|_| Status::INVALID_PARAMETER)?;
+    directory = directory.open(&component, FileMode::CreateReadWrite, FileAttribute::DIRECTORY)
+      .map_err(|err| err.status())?
+      .into_directory()
+      .ok_or(Status::NOT_FOUND)?;
+  }
+
+  Ok(directory)
+}
+
+/// Opens a log file, creating it if required.
+fn open_log_file(directory: &mut Directory, name: &CStr16) -> Result<RegularFile, Status> {
+  directory.open(name, FileMode::CreateReadWrite, FileAttribute::empty())
+    .map_err(|err| err.status())?
+    .into_regular_file()
+    .ok_or(Status::ACCESS_DENIED)
+}
+
+/// Returns the name of the log file `index` rotations old.
+fn log_file_name(index: usize) -> Result<CString16, Status> {
+  let name = match index {
+    0 => String::from("boot.log"),
+    _ => format!("boot.{}.log", index)
+  };
+
+  CString16::try_from(name.as_str()).map_err(|_| Status::INVALID_PARAMETER)
+}
+
+/// Rotates the log files, discarding the oldest, so that `boot.log` is free
+/// to be created anew.
+fn rotate(directory: &mut Directory, max_files: usize) -> Result<(), Status> {
+  let (oldest, renames) = rotation(max_files);
+
+  // Discard the oldest log, which would be rotated out
+  if let Ok(handle) = directory.open(&log_file_name(oldest)?, FileMode::ReadWrite, FileAttribute::empty()) {
+    handle.delete().map_err(|err| err.status())?;
+  }
+
+  for (from, to) in renames {
+    if let Ok(mut handle) = directory.open(&log_file_name(from)?, FileMode::ReadWrite, FileAttribute::empty()) {
+      rename(&mut handle, &log_file_name(to)?)?;
+    }
+  }
+
+  Ok(())
+}
+
+/// Returns the index of the log discarded when rotating `max_files` logs,
+/// and the logs then renamed as `(from, to)` indices, in the order they are
+/// renamed.
+/// 
+/// Keeping fewer than one file is treated as keeping only `boot.log`, which
+/// is discarded.
+fn rotation(max_files: usize) -> (usize, impl Iterator<Item = (usize, usize)>) {
+  let oldest = max_files.saturating_sub(1);
+  (oldest, (0..oldest).rev().map(|index| (index, index + 1)))
+}
+
+/// Renames a file within its directory.
+fn rename(file: &mut impl File, name: &CStr16) -> Result<(), Status> {
+  let info: Box<FileInfo> = file.get_boxed_info().map_err(|err| err.status())?;
+
+  let mut storage = alloc::vec![0u8; size_of_val(&*info) + (name.num_bytes() + FileInfo::alignment())];
+  let storage = FileInfo::align_buf(&mut storage).ok_or(Status::BUFFER_TOO_SMALL)?;
+  let renamed = FileInfo::new(
+    storage,
+    info.file_size(),
+    info.physical_size(),
+    *info.create_time(),
+    *info.last_access_time(),
+    *info.modification_time(),
+    info.attribute(),
+    name
+  ).map_err(|_| Status::BUFFER_TOO_SMALL)?;
+
+  file.set_info(renamed).map_err(|err| err.status())
+}
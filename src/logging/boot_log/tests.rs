@@ -0,0 +1,78 @@
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use super::*;
+
+fn boot_log(max_buffered: usize) -> BootLog {
+  BootLog {
+    config: BootLogConfig {
+      max_buffered,
+      ..BootLogConfig::default()
+    },
+    buffer: Vec::new()
+  }
+}
+
+#[test]
+fn push_discards_whole_records() {
+  let mut log = boot_log(16);
+  log.push("one\n");
+  log.push("two\n");
+  log.push("three\n");
+  assert_eq!(log.buffer, b"one\ntwo\nthree\n");
+
+  // Cut at the first newline after the excess, rather than mid-record
+  log.push("four\n");
+  assert_eq!(log.buffer, b"two\nthree\nfour\n");
+  log.push("fifteen chars!\n");
+  assert_eq!(log.buffer, b"fifteen chars!\n");
+
+  // A record which can never fit is discarded
+  log.push("seventeen chars!\n");
+  assert_eq!(log.buffer, b"fifteen chars!\n");
+
+  // A record which only fits alone discards everything else
+  log.push("sixteen chars!!\n");
+  assert_eq!(log.buffer, b"sixteen chars!!\n");
+
+  // No more is discarded than needed when the excess ends on a newline
+  let mut log = boot_log(10);
+  log.push("one\n");
+  log.push("two\n");
+  log.push("three\n");
+  assert_eq!(log.buffer, b"two\nthree\n");
+}
+
+#[test]
+fn newest_records_are_whole() {
+  let records = b"one\ntwo\nthree\n";
+  assert_eq!(newest_records(records, 64), records);
+  assert_eq!(newest_records(records, records.len()), records);
+  assert_eq!(newest_records(records, 13), b"two\nthree\n");
+  assert_eq!(newest_records(records, 10), b"two\nthree\n");
+  assert_eq!(newest_records(records, 9), b"three\n");
+  assert_eq!(newest_records(records, 5), b"");
+  assert_eq!(newest_records(records, 0), b"");
+}
+
+#[test]
+fn names_rotated_logs() {
+  assert_eq!(log_file_name(0).unwrap().to_string(), "boot.log");
+  assert_eq!(log_file_name(1).unwrap().to_string(), "boot.1.log");
+  assert_eq!(log_file_name(12).unwrap().to_string(), "boot.12.log");
+}
+
+#[test]
+fn rotates_within_max_files() {
+  let plan = |max_files| {
+    let (oldest, renames) = rotation(max_files);
+    (oldest, renames.collect::<Vec<_>>())
+  };
+
+  // Only boot.log is kept, so is discarded
+  assert_eq!(plan(0), (0, Vec::new()));
+  assert_eq!(plan(1), (0, Vec::new()));
+
+  assert_eq!(plan(2), (1, Vec::from([(0, 1)])));
+  assert_eq!(plan(4), (3, Vec::from([(2, 3), (1, 2), (0, 1)])));
+}
@@ -0,0 +1,318 @@
+use core::fmt::Display;
+use core::ptr::{null_mut, NonNull};
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use uefi::boot::{AllocateType, MemoryType, PAGE_SIZE};
+
+use crate::io::{DriverCapabilities, DriverIO};
+
+mod boot_log;
+#[cfg(test)]
+mod tests;
+pub use boot_log::{flush_boot_log, install_boot_logger, BootLogConfig, BootLogger, LOG_DIRECTORY};
+
+/// The number of pages allocated for each invocation's [`LogChannel`].
+pub const LOG_CHANNEL_PAGES: usize = 4;
+/// The size of the fixed part of an encoded [`LogRecord`] in bytes.
+const LOG_RECORD_HEADER_SIZE: usize = 16;
+
+/// The most verbose level of driver records collected.
+static mut DRIVER_LOG_LEVEL: LevelFilter = LevelFilter::Info;
+/// The channel of the running driver, while attached.
+static mut LOG_CHANNEL: Option<*mut LogChannel> = None;
+/// The logger installed by the driver preludes.
+static DRIVER_LOGGER: DriverLogger = DriverLogger;
+
+/// Sets the most verbose level of records collected from drivers.
+/// 
+/// The level is passed to each driver when it is invoked, so that records
+/// below it are never formatted.
+pub fn set_driver_log_level(level: LevelFilter) {
+  unsafe {
+    DRIVER_LOG_LEVEL = level;
+  }
+}
+
+/// Returns the most verbose level of records collected from drivers.
+pub fn driver_log_level() -> LevelFilter {
+  unsafe { DRIVER_LOG_LEVEL }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+/// A buffer in a [`DriverIO`] which a driver appends log records to.
+/// 
+/// Each record is encoded as its level (`u32`, `1` for
+/// [`log::Level::Error`] to `5` for [`log::Level::Trace`]), target length
+/// (`u32`), message length (`u32`) and a reserved `u32`, followed by its
+/// UTF-8 target and message, padded to a multiple of 8 bytes.
+pub struct LogChannel {
+  /// A pointer to the buffer, or null if records are not collected.
+  pub ptr: *mut u8,
+  /// The size of the buffer pointed to by `ptr`.
+  pub capacity: usize,
+  /// The number of bytes of records written to the buffer.
+  pub len: usize,
+  /// The most verbose level collected, as a [`LevelFilter`] (`0` for
+  /// [`LevelFilter::Off`] to `5` for [`LevelFilter::Trace`]).
+  pub max_level: u32,
+  /// The number of records dropped because the buffer was full.
+  pub dropped: u32
+}
+
+impl LogChannel {
+  /// Creates a channel which collects no records.
+  pub const fn empty() -> LogChannel {
+    LogChannel {
+      ptr: null_mut(),
+      capacity: 0,
+      len: 0,
+      max_level: 0,
+      dropped: 0
+    }
+  }
+
+  /// Returns the most verbose level collected.
+  pub fn max_level(&self) -> LevelFilter {
+    match self.max_level {
+      1 => LevelFilter::Error,
+      2 => LevelFilter::Warn,
+      3 => LevelFilter::Info,
+      4 => LevelFilter::Debug,
+      5 => LevelFilter::Trace,
+      _ => LevelFilter::Off
+    }
+  }
+
+  /// Appends a record to the buffer.
+  /// 
+  /// # Returns
+  /// 
+  /// - `true` if the record was written.
+  /// - `false` if there is no buffer, or it is full. Records which do not
+  ///   fit are counted in `dropped`.
+  /// 
+  /// # Safety
+  /// `ptr` must be valid for writes of `capacity` bytes.
+  pub unsafe fn push(&mut self, level: Level, target: &str, message: &str) -> bool {
+    if self.ptr.is_null() {
+      return false;
+    }
+
+    let size = (LOG_RECORD_HEADER_SIZE + target.len() + message.len()).next_multiple_of(8);
+    if self.capacity.saturating_sub(self.len) < size {
+      self.dropped = self.dropped.saturating_add(1);
+      return false;
+    }
+
+    let mut bytes = Vec::with_capacity(size);
+    bytes.extend_from_slice(&(level as u32).to_le_bytes());
+    bytes.extend_from_slice(&(target.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&(message.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    bytes.extend_from_slice(target.as_bytes());
+    bytes.extend_from_slice(message.as_bytes());
+    bytes.resize(size, 0);
+
+    core::ptr::copy(bytes.as_ptr(), self.ptr.add(self.len), size);
+    self.len += size;
+    true
+  }
+
+  /// Returns the records written to the buffer.
+  /// 
+  /// Decoding stops at the first malformed record.
+  /// 
+  /// # Safety
+  /// `ptr` must be valid for reads of `len` bytes.
+  pub unsafe fn read(&self) -> DriverLog {
+    let mut log = DriverLog {
+      records: Vec::new(),
+      dropped: self.dropped as usize
+    };
+    if self.ptr.is_null() {
+      return log;
+    }
+
+    let mut bytes = core::slice::from_raw_parts(self.ptr, self.len.min(self.capacity));
+    while bytes.len() >= LOG_RECORD_HEADER_SIZE {
+      let field = |index: usize| {
+        let mut raw = [0u8; 4];
+        raw.copy_from_slice(&bytes[index * 4..index * 4 + 4]);
+        u32::from_le_bytes(raw) as usize
+      };
+      let level = match field(0) {
+        1 => Level::Error,
+        2 => Level::Warn,
+        3 => Level::Info,
+        4 => Level::Debug,
+        5 => Level::Trace,
+        _ => {
+          break;
+        }
+      };
+      let target_end = LOG_RECORD_HEADER_SIZE.saturating_add(field(1));
+      let message_end = target_end.saturating_add(field(2));
+      if bytes.len() < message_end {
+        break;
+      }
+
+      log.records.push(
+        LogRecord {
+          level,
+          target: String::from_utf8_lossy(&bytes[LOG_RECORD_HEADER_SIZE..target_end]).into_owned(),
+          message: String::from_utf8_lossy(&bytes[target_end..message_end]).into_owned()
+        }
+      );
+      bytes = &bytes[message_end.next_multiple_of(8).min(bytes.len())..];
+    }
+
+    log
+  }
+
+  /// Allocates a buffer for a driver to log to.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Some(LogChannel)` on success, collecting records up to
+  ///   [`driver_log_level`].
+  /// - `None` if records are not collected, or the buffer could not be
+  ///   allocated.
+  pub(crate) fn allocate() -> Option<LogChannel> {
+    let max_level = driver_log_level();
+    if max_level == LevelFilter::Off {
+      return None;
+    }
+
+    let ptr = uefi::boot::allocate_pages(AllocateType::AnyPages, MemoryType::LOADER_DATA, LOG_CHANNEL_PAGES).ok()?;
+    Some(
+      LogChannel {
+        ptr: ptr.as_ptr(),
+        capacity: LOG_CHANNEL_PAGES * PAGE_SIZE,
+        len: 0,
+        max_level: max_level as u32,
+        dropped: 0
+      }
+    )
+  }
+
+  /// Frees a buffer allocated by [`LogChannel::allocate`].
+  pub(crate) unsafe fn free(&mut self) {
+    if let Some(ptr) = NonNull::new(self.ptr) {
+      let _ = uefi::boot::free_pages(ptr, LOG_CHANNEL_PAGES);
+    }
+    *self = LogChannel::empty();
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// A log record emitted by a driver.
+pub struct LogRecord {
+  /// The level of the record.
+  pub level: Level,
+  /// The target of the record, usually the module path it was emitted from.
+  pub target: String,
+  /// The formatted message.
+  pub message: String
+}
+
+impl Display for LogRecord {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "[{} {}] {}", self.level, self.target, self.message)
+  }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+/// The log records emitted by a driver during its last invocation.
+pub struct DriverLog {
+  /// The records, in the order they were emitted.
+  pub records: Vec<LogRecord>,
+  /// The number of records dropped because the channel was full.
+  pub dropped: usize
+}
+
+impl DriverLog {
+  /// Returns the records at or above a level.
+  pub fn filter(&self, level: LevelFilter) -> impl Iterator<Item = &LogRecord> {
+    self.records.iter().filter(move |record| record.level <= level)
+  }
+
+  /// Passes the records to the logger of the loader, if one is installed,
+  /// with `driver` as their target.
+  pub(crate) fn forward(&self, driver: &str) {
+    for record in self.filter(log::max_level()) {
+      log::logger().log(
+        &Record::builder()
+          .level(record.level)
+          .target(driver)
+          .module_path(Some(&record.target))
+          .args(format_args!("{}", record.message))
+          .build()
+      );
+    }
+  }
+}
+
+/// A [`log::Log`] implementation writing records to the [`LogChannel`] of the
+/// running driver.
+/// 
+/// The driver preludes install it, so that drivers may log through the `log`
+/// macros (re-exported as `wakatiwai_udive::log`). Records emitted outside of
+/// an invocation, e.g. while a [`crate::fs::FSSession`] is serving requests,
+/// are discarded.
+pub struct DriverLogger;
+
+impl Log for DriverLogger {
+  fn enabled(&self, metadata: &Metadata) -> bool {
+    unsafe {
+      LOG_CHANNEL.is_some_and(|channel| metadata.level() <= (*channel).max_level())
+    }
+  }
+
+  fn log(&self, record: &Record) {
+    if !self.enabled(record.metadata()) {
+      return;
+    }
+
+    let message = alloc::format!("{}", record.args());
+    unsafe {
+      if let Some(channel) = LOG_CHANNEL {
+        (*channel).push(record.level(), record.target(), &message);
+      }
+    }
+  }
+
+  fn flush(&self) {}
+}
+
+/// Detaches the [`DriverLogger`] from the channel of a driver when dropped.
+pub struct DriverLoggerGuard;
+
+impl Drop for DriverLoggerGuard {
+  fn drop(&mut self) {
+    unsafe {
+      LOG_CHANNEL = None;
+    }
+    log::set_max_level(LevelFilter::Off);
+  }
+}
+
+/// Attaches the [`DriverLogger`] to the channel of a [`DriverIO`], installing
+/// it as the logger if no other logger is installed.
+/// 
+/// This is called by the driver preludes, and the returned guard held until
+/// the driver exits, as the channel is freed once it has.
+/// 
+/// # Safety
+/// The channel of `dio` must be valid until the guard is dropped.
+pub unsafe fn attach_driver_logger(dio: &mut DriverIO) -> DriverLoggerGuard {
+  if dio.header.capabilities.contains(DriverCapabilities::LOG) && !dio.log.ptr.is_null() {
+    LOG_CHANNEL = Some(&mut dio.log);
+    let _ = log::set_logger(&DRIVER_LOGGER);
+    log::set_max_level(dio.log.max_level());
+  }
+
+  DriverLoggerGuard
+}
@@ -0,0 +1,133 @@
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use super::*;
+
+/// Creates a channel collecting every level into `buffer`.
+fn channel(buffer: &mut [u8]) -> LogChannel {
+  LogChannel {
+    ptr: buffer.as_mut_ptr(),
+    capacity: buffer.len(),
+    len: 0,
+    max_level: LevelFilter::Trace as u32,
+    dropped: 0
+  }
+}
+
+/// Decodes the records of a channel over `buffer`, as a driver would have
+/// left it with `len` bytes written.
+fn decode(buffer: &mut [u8], len: usize) -> Vec<LogRecord> {
+  unsafe { LogChannel { len, ..channel(buffer) }.read().records }
+}
+
+fn record(level: Level, target: &str, message: &str) -> LogRecord {
+  LogRecord {
+    level,
+    target: target.to_string(),
+    message: message.to_string()
+  }
+}
+
+#[test]
+fn records_round_trip() {
+  let mut buffer = alloc::vec![0xAA; 256];
+  let mut channel = channel(&mut buffer);
+
+  unsafe {
+    assert!(channel.push(Level::Error, "ext4::inode", "bad inode 12"));
+    assert!(channel.push(Level::Trace, "", ""));
+    assert!(channel.push(Level::Info, "fat", "volume «EFI» mounted"));
+    assert_eq!(channel.len % 8, 0);
+
+    let log = channel.read();
+    assert_eq!(log.dropped, 0);
+    assert_eq!(log.records, [
+      record(Level::Error, "ext4::inode", "bad inode 12"),
+      record(Level::Trace, "", ""),
+      record(Level::Info, "fat", "volume «EFI» mounted")
+    ]);
+    assert_eq!(log.filter(LevelFilter::Info).count(), 2);
+  }
+}
+
+#[test]
+fn full_channel_drops_records() {
+  let mut buffer = alloc::vec![0; 64];
+  let mut channel = channel(&mut buffer);
+
+  unsafe {
+    // 16 bytes of header and 20 of text, padded to 40
+    assert!(channel.push(Level::Warn, "fs", "eighteen character"));
+    assert_eq!(channel.len, 40);
+    assert!(!channel.push(Level::Warn, "fs", "eighteen character"));
+    // A smaller record still fits
+    assert!(channel.push(Level::Debug, "fs", "short"));
+    assert_eq!(channel.len, 64);
+    assert!(!channel.push(Level::Debug, "", ""));
+
+    let log = channel.read();
+    assert_eq!(log.dropped, 2);
+    assert_eq!(log.records.len(), 2);
+  }
+}
+
+#[test]
+fn empty_channel_collects_nothing() {
+  let mut channel = LogChannel::empty();
+  channel.dropped = 3;
+
+  unsafe {
+    assert!(!channel.push(Level::Error, "fs", "lost"));
+    assert_eq!(channel.read(), DriverLog { records: Vec::new(), dropped: 3 });
+  }
+  assert_eq!(channel.max_level(), LevelFilter::Off);
+}
+
+#[test]
+fn max_level_decodes_filters() {
+  let mut channel = LogChannel::empty();
+  for level in LevelFilter::iter() {
+    channel.max_level = level as u32;
+    assert_eq!(channel.max_level(), level);
+  }
+  channel.max_level = 6;
+  assert_eq!(channel.max_level(), LevelFilter::Off);
+}
+
+#[test]
+fn decoding_stops_at_malformed_records() {
+  let mut buffer = alloc::vec![0; 256];
+  let mut channel = channel(&mut buffer);
+  unsafe {
+    channel.push(Level::Info, "fs", "first");
+    channel.push(Level::Info, "fs", "second");
+  }
+  // Each record is 23 bytes, padded to 24
+  let first_len = 24;
+  let len = channel.len;
+  assert_eq!(len, 2 * first_len);
+
+  // A length cut short of the second record
+  assert_eq!(decode(&mut buffer, len - 1).len(), 1);
+  assert_eq!(decode(&mut buffer, first_len + LOG_RECORD_HEADER_SIZE - 1).len(), 1);
+
+  // A length beyond the buffer is clamped to its capacity
+  assert_eq!(decode(&mut buffer, usize::MAX).len(), 2);
+
+  // An unknown level
+  let mut corrupt = buffer.clone();
+  corrupt[first_len] = 0;
+  assert_eq!(decode(&mut corrupt, len).len(), 1);
+
+  // A message running past the end of the records
+  let mut corrupt = buffer.clone();
+  corrupt[first_len + 8..first_len + 12].copy_from_slice(&u32::MAX.to_le_bytes());
+  assert_eq!(decode(&mut corrupt, len).len(), 1);
+  corrupt[8..12].copy_from_slice(&1000u32.to_le_bytes());
+  assert!(decode(&mut corrupt, len).is_empty());
+
+  // Invalid UTF-8 is decoded lossily
+  let mut corrupt = buffer.clone();
+  corrupt[LOG_RECORD_HEADER_SIZE + 2] = 0xFF;
+  assert_eq!(decode(&mut corrupt, len)[0].message, "\u{FFFD}irst");
+}
@@ -0,0 +1,387 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use uefi::{Guid, Status};
+
+use crate::io::DRIVER_IO_ABI_VERSION;
+
+#[cfg(test)]
+mod tests;
+
+/// Returned when a driver manifest is not valid TOML, or a key has the wrong
+/// type.
+pub const MANIFEST_INVALID: Status = crate::oem_error(0x3A4F);
+
+/// The deepest arrays may be nested, so that a malformed manifest cannot
+/// exhaust the stack.
+const MAX_ARRAY_DEPTH: usize = 8;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+/// Metadata describing a driver, read from a manifest alongside it.
+/// 
+/// A driver `ext4.efi` may be accompanied by `ext4.toml`, e.g.
+/// 
+/// ```toml
+/// version = "1.2.0"
+/// abi_version = 4
+/// author = "Jane Doe"
+/// filesystems = ["ext2", "ext3", "ext4"]
+/// partition_types = ["0fc63daf-8483-4772-8e79-3d69d8477de4"]
+/// ```
+/// 
+/// Only a subset of TOML is understood: strings, integers, booleans and
+/// arrays thereof, with tables only used to namespace keys. Unknown keys are
+/// ignored.
+pub struct DriverManifest {
+  /// The version of the driver.
+  pub version: Option<String>,
+  /// The driver ABI version the driver was built with.
+  pub abi_version: Option<u32>,
+  /// The author of the driver.
+  pub author: Option<String>,
+  /// The file systems a file system driver can read.
+  pub filesystems: Vec<String>,
+  /// The boot protocols or formats a boot driver can boot.
+  pub boot_protocols: Vec<String>,
+  /// The GPT partition type GUIDs a file system driver expects to read.
+  pub partition_types: Vec<Guid>
+}
+
+impl DriverManifest {
+  /// Parses a manifest.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `source` (`&str`) - The contents of the manifest.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(DriverManifest)` on success.
+  /// - `Err(MANIFEST_INVALID)` if the manifest could not be parsed, a key is
+  ///   repeated, or a known key has the wrong type.
+  pub fn parse(source: &str) -> Result<DriverManifest, Status> {
+    let mut manifest = DriverManifest::default();
+    let mut seen: Vec<String> = Vec::new();
+
+    let mut parser = Parser { source, pos: 0, table: String::new(), depth: 0 };
+    while let Some((key, value)) = parser.next_pair()? {
+      if seen.contains(&key) {
+        return Err(MANIFEST_INVALID);
+      }
+
+      match key.as_str() {
+        "version" => manifest.version = Some(value.into_string()?),
+        "abi_version" => {
+          manifest.abi_version = Some(u32::try_from(value.into_integer()?).map_err(|_| MANIFEST_INVALID)?);
+        }
+        "author" => manifest.author = Some(value.into_string()?),
+        "filesystems" => manifest.filesystems = value.into_strings()?,
+        "boot_protocols" => manifest.boot_protocols = value.into_strings()?,
+        "partition_types" => {
+          manifest.partition_types = value.into_strings()?
+            .iter()
+            .map(|guid| Guid::try_parse(guid).map_err(|_| MANIFEST_INVALID))
+            .collect::<Result<Vec<Guid>, Status>>()?;
+        }
+        _ => {}
+      }
+      seen.push(key);
+    }
+
+    Ok(manifest)
+  }
+
+  /// Parses a manifest from raw bytes, as read from a file.
+  /// 
+  /// # Returns
+  /// 
+  /// - As with [`DriverManifest::parse`], failing with [`MANIFEST_INVALID`]
+  ///   if the bytes are not valid UTF-8.
+  pub fn from_bytes(bytes: &[u8]) -> Result<DriverManifest, Status> {
+    DriverManifest::parse(core::str::from_utf8(bytes).map_err(|_| MANIFEST_INVALID)?)
+  }
+
+  /// Returns whether the driver declares an ABI compatible with this crate.
+  /// 
+  /// A manifest without an `abi_version` is assumed to be compatible.
+  pub fn is_compatible(&self) -> bool {
+    self.abi_version.is_none_or(|version| version == DRIVER_IO_ABI_VERSION)
+  }
+
+  /// Returns whether the driver declares support for a file system, ignoring
+  /// case.
+  pub fn supports_filesystem(&self, filesystem: &str) -> bool {
+    self.filesystems.iter().any(|supported| supported.eq_ignore_ascii_case(filesystem))
+  }
+
+  /// Returns whether the driver declares support for a boot protocol,
+  /// ignoring case.
+  pub fn supports_boot_protocol(&self, protocol: &str) -> bool {
+    self.boot_protocols.iter().any(|supported| supported.eq_ignore_ascii_case(protocol))
+  }
+
+  /// Returns whether the driver declares support for a GPT partition type.
+  pub fn supports_partition_type(&self, type_guid: &Guid) -> bool {
+    self.partition_types.contains(type_guid)
+  }
+}
+
+/// A value in a manifest.
+enum Value {
+  String(String),
+  Integer(i64),
+  /// No known key is a boolean, but they are accepted for unknown keys.
+  #[allow(dead_code)]
+  Boolean(bool),
+  Array(Vec<Value>)
+}
+
+impl Value {
+  /// Returns the value as a string.
+  fn into_string(self) -> Result<String, Status> {
+    match self {
+      Value::String(string) => Ok(string),
+      _ => Err(MANIFEST_INVALID)
+    }
+  }
+
+  /// Returns the value as an integer.
+  fn into_integer(self) -> Result<i64, Status> {
+    match self {
+      Value::Integer(integer) => Ok(integer),
+      _ => Err(MANIFEST_INVALID)
+    }
+  }
+
+  /// Returns the value as an array of strings.
+  fn into_strings(self) -> Result<Vec<String>, Status> {
+    match self {
+      Value::Array(array) => array.into_iter().map(Value::into_string).collect(),
+      _ => Err(MANIFEST_INVALID)
+    }
+  }
+}
+
+/// A parser for the subset of TOML used by manifests.
+struct Parser<'a> {
+  /// The manifest being parsed.
+  source: &'a str,
+  /// The byte offset of the next character to be parsed.
+  pos: usize,
+  /// The name of the current table, or empty before any table header.
+  table: String,
+  /// The number of arrays the next value is nested in.
+  depth: usize
+}
+
+impl Parser<'_> {
+  /// Returns the next character without consuming it.
+  fn peek(&self) -> Option<char> {
+    self.source[self.pos..].chars().next()
+  }
+
+  /// Consumes the next character.
+  fn bump(&mut self) -> Option<char> {
+    let c = self.peek()?;
+    self.pos += c.len_utf8();
+    Some(c)
+  }
+
+  /// Consumes the next character if it is `expected`.
+  fn eat(&mut self, expected: char) -> bool {
+    if self.peek() == Some(expected) {
+      self.pos += expected.len_utf8();
+      return true;
+    }
+
+    false
+  }
+
+  /// Skips spaces and tabs, and a trailing comment.
+  fn skip_inline(&mut self) {
+    while let Some(' ' | '\t') = self.peek() {
+      self.pos += 1;
+    }
+    if self.peek() == Some('#') {
+      while self.peek().is_some_and(|c| c != '\n') {
+        self.bump();
+      }
+    }
+  }
+
+  /// Skips whitespace, newlines and comments.
+  fn skip_all(&mut self) {
+    loop {
+      self.skip_inline();
+      match self.peek() {
+        Some('\r' | '\n') => {
+          self.pos += 1;
+        }
+        _ => break
+      }
+    }
+  }
+
+  /// Expects the end of a line.
+  fn end_line(&mut self) -> Result<(), Status> {
+    self.skip_inline();
+    self.eat('\r');
+    match self.peek() {
+      None => Ok(()),
+      Some('\n') => {
+        self.pos += 1;
+        Ok(())
+      }
+      Some(_) => Err(MANIFEST_INVALID)
+    }
+  }
+
+  /// Parses a bare or quoted key, which may be dotted.
+  fn key(&mut self) -> Result<String, Status> {
+    let mut key = String::new();
+    loop {
+      if self.peek() == Some('"') {
+        key.push_str(&self.string()?);
+      } else {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+          self.pos += 1;
+        }
+        if start == self.pos {
+          return Err(MANIFEST_INVALID);
+        }
+        key.push_str(&self.source[start..self.pos]);
+      }
+
+      self.skip_inline();
+      if !self.eat('.') {
+        return Ok(key);
+      }
+      key.push('.');
+      self.skip_inline();
+    }
+  }
+
+  /// Parses a basic string.
+  fn string(&mut self) -> Result<String, Status> {
+    if !self.eat('"') {
+      return Err(MANIFEST_INVALID);
+    }
+
+    let mut string = String::new();
+    loop {
+      match self.bump() {
+        Some('"') => return Ok(string),
+        Some('\\') => {
+          let escaped = match self.bump() {
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            _ => {
+              return Err(MANIFEST_INVALID);
+            }
+          };
+          string.push(escaped);
+        }
+        Some('\n') | None => {
+          return Err(MANIFEST_INVALID);
+        }
+        Some(c) => string.push(c)
+      }
+    }
+  }
+
+  /// Parses a value.
+  fn value(&mut self) -> Result<Value, Status> {
+    match self.peek() {
+      Some('"') => Ok(Value::String(self.string()?)),
+      Some('[') => {
+        if self.depth >= MAX_ARRAY_DEPTH {
+          return Err(MANIFEST_INVALID);
+        }
+
+        self.pos += 1;
+        self.depth += 1;
+        let array = self.array_items();
+        self.depth -= 1;
+        array.map(Value::Array)
+      }
+      Some(_) => {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '+' || c == '-') {
+          self.pos += 1;
+        }
+
+        let token = &self.source[start..self.pos];
+        match token {
+          "true" => Ok(Value::Boolean(true)),
+          "false" => Ok(Value::Boolean(false)),
+          _ => {
+            let digits: String = token.chars().filter(|c| *c != '_').collect();
+            let integer = match digits.strip_prefix("0x") {
+              Some(hex) => i64::from_str_radix(hex, 16),
+              None => digits.parse::<i64>()
+            };
+            integer.map(Value::Integer).map_err(|_| MANIFEST_INVALID)
+          }
+        }
+      }
+      None => Err(MANIFEST_INVALID)
+    }
+  }
+
+  /// Parses the items of an array, after its opening bracket.
+  fn array_items(&mut self) -> Result<Vec<Value>, Status> {
+    let mut array = Vec::new();
+    loop {
+      self.skip_all();
+      if self.eat(']') {
+        return Ok(array);
+      }
+
+      array.push(self.value()?);
+      self.skip_all();
+      if !self.eat(',') {
+        self.skip_all();
+        return if self.eat(']') { Ok(array) } else { Err(MANIFEST_INVALID) };
+      }
+    }
+  }
+
+  /// Parses up to the next key/value pair, following any table headers.
+  /// 
+  /// Keys within a table are prefixed with the table's name.
+  fn next_pair(&mut self) -> Result<Option<(String, Value)>, Status> {
+    loop {
+      self.skip_all();
+      match self.peek() {
+        None => return Ok(None),
+        Some('[') => {
+          self.pos += 1;
+          self.skip_inline();
+          self.table = self.key()?;
+          if !self.eat(']') {
+            return Err(MANIFEST_INVALID);
+          }
+          self.end_line()?;
+        }
+        Some(_) => {
+          let key = self.key()?;
+          if !self.eat('=') {
+            return Err(MANIFEST_INVALID);
+          }
+          self.skip_inline();
+          let value = self.value()?;
+          self.end_line()?;
+
+          if self.table.is_empty() {
+            return Ok(Some((key, value)));
+          }
+          return Ok(Some((alloc::format!("{}.{}", self.table, key), value)));
+        }
+      }
+    }
+  }
+}
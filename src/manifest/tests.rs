@@ -0,0 +1,149 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use uefi::guid;
+
+use super::*;
+
+fn parse(source: &str) -> Result<DriverManifest, Status> {
+  DriverManifest::parse(source)
+}
+
+#[test]
+fn parses_known_keys() {
+  let manifest = parse(concat!(
+    "# An ext4 driver\r\n",
+    "version = \"1.2.0\"   # trailing comment\r\n",
+    "abi_version = 0x0_5\n",
+    "\n",
+    "author=\"Jane Doe\"\n",
+    "filesystems = [\n",
+    "  \"ext2\", # oldest\n",
+    "  \"ext3\",\n",
+    "  \"ext4\",\n",
+    "]\n",
+    "boot_protocols = []\n",
+    "partition_types = [\"0fc63daf-8483-4772-8e79-3d69d8477de4\"]\n",
+    "unknown = [true, false, 1_000, [\"nested\"]]"
+  )).unwrap();
+
+  assert_eq!(manifest.version.as_deref(), Some("1.2.0"));
+  assert_eq!(manifest.abi_version, Some(5));
+  assert_eq!(manifest.author.as_deref(), Some("Jane Doe"));
+  assert_eq!(manifest.filesystems, ["ext2", "ext3", "ext4"]);
+  assert!(manifest.boot_protocols.is_empty());
+  assert_eq!(manifest.partition_types, [guid!("0fc63daf-8483-4772-8e79-3d69d8477de4")]);
+}
+
+#[test]
+fn empty_manifest_has_defaults() {
+  assert_eq!(parse(""), Ok(DriverManifest::default()));
+  assert_eq!(parse("\n  # nothing here\n\n"), Ok(DriverManifest::default()));
+}
+
+#[test]
+fn parses_string_escapes() {
+  let manifest = parse(r#"author = "Jane \"JD\" Doe\\\t\n\r ü""#).unwrap();
+  assert_eq!(manifest.author.as_deref(), Some("Jane \"JD\" Doe\\\t\n\r ü"));
+
+  assert_eq!(parse(r#"author = "\x41""#), Err(MANIFEST_INVALID));
+  assert_eq!(parse(r#"author = "\"#), Err(MANIFEST_INVALID));
+  assert_eq!(parse("author = \"Jane"), Err(MANIFEST_INVALID));
+  assert_eq!(parse("author = \"Jane\nDoe\""), Err(MANIFEST_INVALID));
+}
+
+#[test]
+fn namespaces_keys_in_tables() {
+  // Keys in tables, or dotted keys, are not the known top-level keys
+  let manifest = parse("[driver]\nversion = \"1.0.0\"\n[ support . fs ]\nfilesystems = [\"fat32\"]\n").unwrap();
+  assert_eq!(manifest, DriverManifest::default());
+  assert_eq!(parse("driver.version = \"1.0.0\""), Ok(DriverManifest::default()));
+
+  let manifest = parse("\"version\" = \"1.0.0\"").unwrap();
+  assert_eq!(manifest.version.as_deref(), Some("1.0.0"));
+
+  assert_eq!(parse("[driver"), Err(MANIFEST_INVALID));
+  assert_eq!(parse("[]"), Err(MANIFEST_INVALID));
+  assert_eq!(parse("[driver] version = \"1.0.0\""), Err(MANIFEST_INVALID));
+}
+
+#[test]
+fn rejects_malformed_manifests() {
+  for source in [
+    "version",
+    "version \"1.0.0\"",
+    "= \"1.0.0\"",
+    "version = ",
+    "version = \"1.0.0\" \"2.0.0\"",
+    "version = \"1.0.0\"\nversion = \"2.0.0\"",
+    "filesystems = [\"fat32\"",
+    "filesystems = [\"fat32\" \"exfat\"]",
+    "filesystems = [,]",
+    "abi_version = 5.0",
+    "abi_version = 0xZZ",
+    "unknown = yes"
+  ] {
+    assert_eq!(parse(source), Err(MANIFEST_INVALID), "{:?}", source);
+  }
+}
+
+#[test]
+fn rejects_known_keys_of_wrong_type() {
+  for source in [
+    "version = 1",
+    "abi_version = \"5\"",
+    "abi_version = -1",
+    "abi_version = 0x1_0000_0000",
+    "author = [\"Jane Doe\"]",
+    "filesystems = \"fat32\"",
+    "filesystems = [[\"fat32\"]]",
+    "boot_protocols = [1]",
+    "partition_types = [\"not a guid\"]"
+  ] {
+    assert_eq!(parse(source), Err(MANIFEST_INVALID), "{:?}", source);
+  }
+}
+
+#[test]
+fn bounds_array_nesting() {
+  let nested = |depth: usize| {
+    let mut source = String::from("unknown = ");
+    source.extend(core::iter::repeat_n('[', depth));
+    source.extend(core::iter::repeat_n(']', depth));
+    source
+  };
+
+  assert!(parse(&nested(MAX_ARRAY_DEPTH)).is_ok());
+  assert_eq!(parse(&nested(MAX_ARRAY_DEPTH + 1)), Err(MANIFEST_INVALID));
+
+  // Far deeper than would fit on the stack if parsed recursively
+  let brackets: String = core::iter::repeat_n('[', 1_000_000).collect();
+  assert_eq!(parse(&alloc::format!("unknown = {}", brackets)), Err(MANIFEST_INVALID));
+}
+
+#[test]
+fn rejects_invalid_utf8() {
+  assert_eq!(DriverManifest::from_bytes(b"author = \"\xFF\""), Err(MANIFEST_INVALID));
+  assert!(DriverManifest::from_bytes(b"author = \"Jane\"").is_ok());
+}
+
+#[test]
+fn checks_support() {
+  let manifest = DriverManifest {
+    abi_version: Some(DRIVER_IO_ABI_VERSION),
+    filesystems: Vec::from([String::from("ext4")]),
+    boot_protocols: Vec::from([String::from("Linux")]),
+    partition_types: Vec::from([guid!("0fc63daf-8483-4772-8e79-3d69d8477de4")]),
+    ..DriverManifest::default()
+  };
+
+  assert!(manifest.is_compatible());
+  assert!(manifest.supports_filesystem("EXT4"));
+  assert!(!manifest.supports_filesystem("ext3"));
+  assert!(manifest.supports_boot_protocol("linux"));
+  assert!(manifest.supports_partition_type(&guid!("0fc63daf-8483-4772-8e79-3d69d8477de4")));
+
+  assert!(DriverManifest::default().is_compatible());
+  let outdated = DriverManifest { abi_version: Some(DRIVER_IO_ABI_VERSION - 1), ..DriverManifest::default() };
+  assert!(!outdated.is_compatible());
+}
@@ -0,0 +1,434 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use uefi::{Guid, Status};
+
+use crate::disk::{BlockDevice, DiskReader};
+use crate::UdiveError;
+
+#[cfg(test)]
+mod tests;
+
+/// The signature found at the end of a valid MBR or EBR.
+const MBR_SIGNATURE: [u8; 2]      = [0x55, 0xAA];
+/// The offset of the partition entry table within an MBR or EBR.
+const MBR_TABLE_OFFSET: usize     = 446;
+/// The size of a single MBR partition entry.
+const MBR_ENTRY_SIZE: usize       = 16;
+/// The OS type of a protective MBR partition.
+const MBR_TYPE_PROTECTIVE: u8     = 0xEE;
+/// OS types denoting an extended partition.
+const MBR_TYPES_EXTENDED: [u8; 3] = [0x05, 0x0F, 0x85];
+/// The maximum number of logical partitions followed in an extended partition.
+const MBR_MAX_LOGICAL: usize      = 128;
+
+/// The signature of a GPT header.
+const GPT_SIGNATURE: &[u8; 8]     = b"EFI PART";
+/// The smallest valid GPT header size.
+const GPT_MIN_HEADER_SIZE: usize  = 92;
+/// The smallest valid GPT partition entry size.
+const GPT_MIN_ENTRY_SIZE: usize   = 128;
+/// The largest partition entry array that will be read.
+const GPT_MAX_ENTRIES_SIZE: usize = 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// The partitioning schemes understood by [`PartitionTable::read`].
+pub enum PartitionScheme {
+  /// A GUID partition table, behind a protective MBR.
+  GPT,
+  /// A legacy MBR, possibly with logical partitions in extended partitions.
+  MBR
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Scheme-specific information about a [`Partition`].
+pub enum PartitionKind {
+  /// A partition described by a GPT partition entry.
+  GPT {
+    /// The partition type GUID.
+    type_guid: Guid,
+    /// The GUID unique to this partition.
+    unique_guid: Guid,
+    /// The partition name.
+    name: String,
+    /// The partition attribute flags.
+    attributes: u64
+  },
+  /// A partition described by an MBR or EBR partition entry.
+  MBR {
+    /// The OS type byte.
+    os_type: u8,
+    /// Whether the partition is marked active.
+    bootable: bool,
+    /// Whether this is a logical partition inside an extended partition.
+    logical: bool
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// A single partition on a disk.
+pub struct Partition {
+  /// The number of this partition, starting from 1.
+  /// 
+  /// For GPT, this is the index in the partition entry array. For MBR, primary
+  /// partitions are numbered 1-4 and logical partitions from 5.
+  pub number: u32,
+  /// The first LBA of the partition, relative to the disk.
+  pub first_lba: u64,
+  /// The last LBA of the partition (inclusive), relative to the disk.
+  pub last_lba: u64,
+  /// Scheme-specific information about the partition.
+  pub kind: PartitionKind
+}
+
+impl Partition {
+  /// Returns the number of blocks in this partition.
+  pub fn block_count(&self) -> u64 {
+    self.last_lba - self.first_lba + 1
+  }
+
+  /// Returns the partition type GUID, if this is a GPT partition.
+  pub fn type_guid(&self) -> Option<Guid> {
+    match &self.kind {
+      PartitionKind::GPT { type_guid, .. } => Some(*type_guid),
+      PartitionKind::MBR { .. } => None
+    }
+  }
+
+  /// Returns the unique partition GUID, if this is a GPT partition.
+  pub fn unique_guid(&self) -> Option<Guid> {
+    match &self.kind {
+      PartitionKind::GPT { unique_guid, .. } => Some(*unique_guid),
+      PartitionKind::MBR { .. } => None
+    }
+  }
+
+  /// Returns the partition name, if this is a GPT partition.
+  pub fn name(&self) -> Option<&str> {
+    match &self.kind {
+      PartitionKind::GPT { name, .. } => Some(name),
+      PartitionKind::MBR { .. } => None
+    }
+  }
+
+  /// Creates a [`DiskReader`] over this partition.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `disk` (`&DiskReader`) - The whole-disk reader the partition table was
+  ///   read from.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(DiskReader)` on success, with `abs_offset` set to the start of the
+  ///   partition and `last_block` to its last LBA relative to that offset.
+  /// - `Err(UdiveError::Disk)` with `VOLUME_CORRUPTED` if the partition does
+  ///   not lie within the disk.
+  /// - `Err(UdiveError::Disk)` if the reader could not be opened.
+  pub fn reader(&self, disk: &DiskReader) -> Result<DiskReader, UdiveError> {
+    if self.first_lba > self.last_lba || self.last_lba > disk.last_block {
+      return Err(UdiveError::disk(Status::VOLUME_CORRUPTED));
+    }
+    let abs_offset = self.first_lba.checked_mul(disk.block_size as u64)
+      .and_then(|offset| offset.checked_add(disk.abs_offset))
+      .ok_or(UdiveError::disk(Status::VOLUME_CORRUPTED))?;
+
+    let mut reader = DiskReader::open(&disk.handle(), abs_offset)?;
+    reader.last_block = self.last_lba - self.first_lba;
+
+    Ok(reader)
+  }
+}
+
+#[derive(Clone, Debug)]
+/// The partitions found on a disk.
+pub struct PartitionTable {
+  /// The scheme the disk is partitioned with.
+  pub scheme: PartitionScheme,
+  /// The disk GUID, if the scheme is GPT.
+  pub disk_guid: Option<Guid>,
+  /// Whether the primary GPT was corrupt and the backup was used instead.
+  pub used_backup: bool,
+  /// The partitions on the disk, ordered by number.
+  pub partitions: Vec<Partition>
+}
+
+impl PartitionTable {
+  /// Reads the partition table of a disk.
+  /// 
+  /// The MBR is read first. If it contains a protective partition, the GPT
+  /// header and partition entry array are read and checked, falling back to
+  /// the backup GPT if the primary one is corrupt. Otherwise, the primary and
+  /// logical partitions of the MBR are returned.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `disk` (`&D`) - A reader over the whole disk.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(PartitionTable)` on success.
+  /// - `Err(UdiveError::Disk)` with `NOT_FOUND` if the disk has no MBR.
+  /// - `Err(UdiveError::Disk)` with `VOLUME_CORRUPTED` if the partition table
+  ///   is corrupt.
+  /// - `Err(UdiveError::Disk)` if the disk could not be read.
+  pub fn read<D: BlockDevice>(disk: &D) -> Result<PartitionTable, UdiveError> {
+    let mbr = disk.read_block(0).map_err(UdiveError::disk)?;
+    if mbr.len() < 512 || mbr[510..512] != MBR_SIGNATURE {
+      return Err(UdiveError::disk(Status::NOT_FOUND));
+    }
+
+    let is_protective = (0..4).any(|i| mbr[MBR_TABLE_OFFSET + i * MBR_ENTRY_SIZE + 4] == MBR_TYPE_PROTECTIVE);
+    if is_protective {
+      return read_gpt(disk).map_err(UdiveError::disk);
+    }
+
+    read_mbr(disk, &mbr).map_err(UdiveError::disk)
+  }
+
+  /// Returns the partition with the given unique GUID, if any.
+  pub fn find_by_unique_guid(&self, guid: &Guid) -> Option<&Partition> {
+    self.partitions.iter().find(|p| p.unique_guid().as_ref() == Some(guid))
+  }
+
+  /// Returns all partitions with the given type GUID.
+  pub fn find_by_type_guid(&self, guid: &Guid) -> Vec<&Partition> {
+    self.partitions.iter().filter(|p| p.type_guid().as_ref() == Some(guid)).collect()
+  }
+}
+
+/// Reads a legacy MBR and any extended partitions it contains.
+fn read_mbr<D: BlockDevice>(disk: &D, mbr: &[u8]) -> Result<PartitionTable, Status> {
+  let mut partitions = Vec::new();
+  let mut extended_lba = None;
+
+  for i in 0..4 {
+    let entry = &mbr[MBR_TABLE_OFFSET + i * MBR_ENTRY_SIZE..][..MBR_ENTRY_SIZE];
+    let os_type = entry[4];
+    let start = le_u32(entry, 8) as u64;
+    let sectors = le_u32(entry, 12) as u64;
+
+    if os_type == 0 || sectors == 0 {
+      continue;
+    }
+    let last_lba = start + sectors - 1;
+    if last_lba > disk.last_block() {
+      return Err(Status::VOLUME_CORRUPTED);
+    }
+    if MBR_TYPES_EXTENDED.contains(&os_type) {
+      extended_lba = Some(start);
+      continue;
+    }
+
+    partitions.push(
+      Partition {
+        number: i as u32 + 1,
+        first_lba: start,
+        last_lba,
+        kind: PartitionKind::MBR {
+          os_type,
+          bootable: entry[0] & 0x80 != 0,
+          logical: false
+        }
+      }
+    );
+  }
+
+  // Follow the chain of EBRs
+  if let Some(extended_start) = extended_lba {
+    let mut ebr_lba = extended_start;
+    let mut number = 5;
+
+    for _ in 0..MBR_MAX_LOGICAL {
+      let ebr = disk.read_block(ebr_lba)?;
+      if ebr.len() < 512 || ebr[510..512] != MBR_SIGNATURE {
+        return Err(Status::VOLUME_CORRUPTED);
+      }
+
+      let entry = &ebr[MBR_TABLE_OFFSET..][..MBR_ENTRY_SIZE];
+      let sectors = le_u32(entry, 12) as u64;
+      if entry[4] != 0 && sectors != 0 {
+        let start = ebr_lba + le_u32(entry, 8) as u64;
+        let last_lba = start + sectors - 1;
+        if last_lba > disk.last_block() {
+          return Err(Status::VOLUME_CORRUPTED);
+        }
+
+        partitions.push(
+          Partition {
+            number,
+            first_lba: start,
+            last_lba,
+            kind: PartitionKind::MBR {
+              os_type: entry[4],
+              bootable: entry[0] & 0x80 != 0,
+              logical: true
+            }
+          }
+        );
+        number += 1;
+      }
+
+      // The second entry links to the next EBR, relative to the extended partition
+      let next = &ebr[MBR_TABLE_OFFSET + MBR_ENTRY_SIZE..][..MBR_ENTRY_SIZE];
+      if next[4] == 0 || le_u32(next, 8) == 0 {
+        break;
+      }
+      ebr_lba = extended_start + le_u32(next, 8) as u64;
+    }
+  }
+
+  partitions.sort_by_key(|p| p.number);
+  Ok(
+    PartitionTable {
+      scheme: PartitionScheme::MBR,
+      disk_guid: None,
+      used_backup: false,
+      partitions
+    }
+  )
+}
+
+/// Reads the primary GPT, or the backup GPT if the primary is corrupt.
+fn read_gpt<D: BlockDevice>(disk: &D) -> Result<PartitionTable, Status> {
+  let primary = read_gpt_at(disk, 1);
+  if primary.is_ok() {
+    return primary;
+  }
+
+  // The backup header is usually at the last LBA of the disk
+  let mut backup = read_gpt_at(disk, disk.last_block());
+  if let Ok(ok) = backup.as_mut() {
+    ok.used_backup = true;
+  }
+  backup
+}
+
+/// Reads and validates the GPT header at the given LBA, and its entries.
+fn read_gpt_at<D: BlockDevice>(disk: &D, lba: u64) -> Result<PartitionTable, Status> {
+  let mut header = disk.read_block(lba)?;
+  if header.len() < GPT_MIN_HEADER_SIZE || &header[0..8] != GPT_SIGNATURE {
+    return Err(Status::VOLUME_CORRUPTED);
+  }
+
+  let header_size = le_u32(&header, 12) as usize;
+  if header_size < GPT_MIN_HEADER_SIZE || header_size > header.len() {
+    return Err(Status::VOLUME_CORRUPTED);
+  }
+
+  // The header CRC is calculated with its own field zeroed
+  let header_crc = le_u32(&header, 16);
+  header[16..20].fill(0);
+  if crc32(&header[..header_size]) != header_crc || le_u64(&header, 24) != lba {
+    return Err(Status::VOLUME_CORRUPTED);
+  }
+
+  let first_usable = le_u64(&header, 40);
+  let last_usable = le_u64(&header, 48);
+  let disk_guid = guid_at(&header, 56);
+  let entries_lba = le_u64(&header, 72);
+  let entry_count = le_u32(&header, 80) as usize;
+  let entry_size = le_u32(&header, 84) as usize;
+  let entries_crc = le_u32(&header, 88);
+
+  if entry_size < GPT_MIN_ENTRY_SIZE || !entry_size.is_multiple_of(8) {
+    return Err(Status::VOLUME_CORRUPTED);
+  }
+  let entries_size = match entry_count.checked_mul(entry_size) {
+    Some(some) if some <= GPT_MAX_ENTRIES_SIZE => some,
+    _ => {
+      return Err(Status::VOLUME_CORRUPTED);
+    }
+  };
+
+  if last_usable > disk.last_block() {
+    return Err(Status::VOLUME_CORRUPTED);
+  }
+
+  let entries_offset = entries_lba.checked_mul(disk.block_size() as u64).ok_or(Status::VOLUME_CORRUPTED)?;
+  let entries = disk.read_bytes(entries_offset, entries_size)?;
+  if crc32(&entries) != entries_crc {
+    return Err(Status::VOLUME_CORRUPTED);
+  }
+
+  let mut partitions = Vec::new();
+  for (i, entry) in entries.chunks_exact(entry_size).enumerate() {
+    let type_guid = guid_at(entry, 0);
+    if type_guid.is_zero() {
+      continue;
+    }
+
+    let first_lba = le_u64(entry, 32);
+    let last_lba = le_u64(entry, 40);
+    if first_lba > last_lba || first_lba < first_usable || last_lba > last_usable {
+      return Err(Status::VOLUME_CORRUPTED);
+    }
+
+    let name_units = entry[56..128]
+      .chunks_exact(2)
+      .map(|c| u16::from_le_bytes([c[0], c[1]]))
+      .take_while(|c| *c != 0);
+
+    partitions.push(
+      Partition {
+        number: i as u32 + 1,
+        first_lba,
+        last_lba,
+        kind: PartitionKind::GPT {
+          type_guid,
+          unique_guid: guid_at(entry, 16),
+          name: char::decode_utf16(name_units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)).collect(),
+          attributes: le_u64(entry, 48)
+        }
+      }
+    );
+  }
+
+  Ok(
+    PartitionTable {
+      scheme: PartitionScheme::GPT,
+      disk_guid: Some(disk_guid),
+      used_backup: false,
+      partitions
+    }
+  )
+}
+
+/// Reads a little-endian `u32` from a buffer.
+fn le_u32(buffer: &[u8], offset: usize) -> u32 {
+  u32::from_le_bytes(buffer[offset..offset + 4].try_into().unwrap())
+}
+
+/// Reads a little-endian `u64` from a buffer.
+fn le_u64(buffer: &[u8], offset: usize) -> u64 {
+  u64::from_le_bytes(buffer[offset..offset + 8].try_into().unwrap())
+}
+
+/// Reads a mixed-endian GUID from a buffer.
+fn guid_at(buffer: &[u8], offset: usize) -> Guid {
+  Guid::from_bytes(buffer[offset..offset + 16].try_into().unwrap())
+}
+
+/// The lookup table for [`crc32`], using the reflected IEEE polynomial.
+const CRC32_TABLE: [u32; 256] = {
+  let mut table = [0u32; 256];
+  let mut i = 0;
+  while i < 256 {
+    let mut crc = i as u32;
+    let mut bit = 0;
+    while bit < 8 {
+      crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+      bit += 1;
+    }
+    table[i] = crc;
+    i += 1;
+  }
+  table
+};
+
+/// Calculates the CRC32 of a buffer, as used by GPT.
+pub fn crc32(buffer: &[u8]) -> u32 {
+  !buffer.iter().fold(!0u32, |crc, byte| {
+    CRC32_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+  })
+}
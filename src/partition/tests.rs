@@ -0,0 +1,191 @@
+use alloc::vec::Vec;
+
+use uefi::{guid, Guid, Status};
+
+use super::*;
+use crate::disk::MemoryDisk;
+
+/// The block size of the test disks.
+const BLOCK_SIZE: usize = 512;
+/// The number of blocks in the test disks.
+const BLOCK_COUNT: u64 = 64;
+
+const LINUX_TYPE: Guid = guid!("0fc63daf-8483-4772-8e79-3d69d8477de4");
+const ROOT_GUID: Guid = guid!("2c9d3a0e-59a4-4c4b-9d1e-0b3f0a6e7c11");
+const DISK_GUID: Guid = guid!("6a1f9c2b-3d44-4f0e-8a5b-77e0c1d2e3f4");
+
+fn block(disk: &mut [u8], lba: u64) -> &mut [u8] {
+  &mut disk[lba as usize * BLOCK_SIZE..][..BLOCK_SIZE]
+}
+
+/// Writes an MBR or EBR partition entry.
+fn set_mbr_entry(sector: &mut [u8], index: usize, os_type: u8, start: u32, sectors: u32) {
+  let entry = &mut sector[MBR_TABLE_OFFSET + index * MBR_ENTRY_SIZE..][..MBR_ENTRY_SIZE];
+  entry[4] = os_type;
+  entry[8..12].copy_from_slice(&start.to_le_bytes());
+  entry[12..16].copy_from_slice(&sectors.to_le_bytes());
+  sector[510..512].copy_from_slice(&MBR_SIGNATURE);
+}
+
+/// Writes a GPT header at `lba`, describing entries at `entries_lba`.
+fn set_gpt_header(disk: &mut [u8], lba: u64, entries_lba: u64, entries_crc: u32) {
+  let header = block(disk, lba);
+  header.fill(0);
+  header[0..8].copy_from_slice(GPT_SIGNATURE);
+  header[8..12].copy_from_slice(&0x0001_0000u32.to_le_bytes());
+  header[12..16].copy_from_slice(&(GPT_MIN_HEADER_SIZE as u32).to_le_bytes());
+  header[24..32].copy_from_slice(&lba.to_le_bytes());
+  header[40..48].copy_from_slice(&3u64.to_le_bytes());
+  header[48..56].copy_from_slice(&(BLOCK_COUNT - 3).to_le_bytes());
+  header[56..72].copy_from_slice(&DISK_GUID.to_bytes());
+  header[72..80].copy_from_slice(&entries_lba.to_le_bytes());
+  header[80..84].copy_from_slice(&4u32.to_le_bytes());
+  header[84..88].copy_from_slice(&(GPT_MIN_ENTRY_SIZE as u32).to_le_bytes());
+  header[88..92].copy_from_slice(&entries_crc.to_le_bytes());
+  reseal_gpt_header(disk, lba);
+}
+
+/// Recalculates the CRC of the GPT header at `lba`.
+fn reseal_gpt_header(disk: &mut [u8], lba: u64) {
+  let header = block(disk, lba);
+  header[16..20].fill(0);
+  let crc = crc32(&header[..GPT_MIN_HEADER_SIZE]);
+  header[16..20].copy_from_slice(&crc.to_le_bytes());
+}
+
+/// Builds a GPT disk with a single partition, and both headers intact.
+fn gpt_disk() -> Vec<u8> {
+  let mut disk = alloc::vec![0; BLOCK_COUNT as usize * BLOCK_SIZE];
+  set_mbr_entry(block(&mut disk, 0), 0, MBR_TYPE_PROTECTIVE, 1, BLOCK_COUNT as u32 - 1);
+
+  let mut entries = alloc::vec![0u8; BLOCK_SIZE];
+  entries[0..16].copy_from_slice(&LINUX_TYPE.to_bytes());
+  entries[16..32].copy_from_slice(&ROOT_GUID.to_bytes());
+  entries[32..40].copy_from_slice(&8u64.to_le_bytes());
+  entries[40..48].copy_from_slice(&40u64.to_le_bytes());
+  for (i, unit) in "root".encode_utf16().enumerate() {
+    entries[56 + i * 2..][..2].copy_from_slice(&unit.to_le_bytes());
+  }
+  let entries_crc = crc32(&entries);
+
+  block(&mut disk, 2).copy_from_slice(&entries);
+  set_gpt_header(&mut disk, 1, 2, entries_crc);
+  block(&mut disk, BLOCK_COUNT - 2).copy_from_slice(&entries);
+  set_gpt_header(&mut disk, BLOCK_COUNT - 1, BLOCK_COUNT - 2, entries_crc);
+
+  disk
+}
+
+fn read(disk: Vec<u8>) -> Result<PartitionTable, Status> {
+  PartitionTable::read(&MemoryDisk::new(disk, BLOCK_SIZE as u32).unwrap()).map_err(|err| err.status())
+}
+
+#[test]
+fn crc32_check_value() {
+  assert_eq!(crc32(b""), 0);
+  assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+}
+
+#[test]
+fn reads_gpt() {
+  let table = read(gpt_disk()).unwrap();
+
+  assert_eq!(table.scheme, PartitionScheme::GPT);
+  assert_eq!(table.disk_guid, Some(DISK_GUID));
+  assert!(!table.used_backup);
+  assert_eq!(table.partitions.len(), 1);
+
+  let root = &table.partitions[0];
+  assert_eq!((root.number, root.first_lba, root.last_lba), (1, 8, 40));
+  assert_eq!(root.block_count(), 33);
+  assert_eq!(root.type_guid(), Some(LINUX_TYPE));
+  assert_eq!(root.name(), Some("root"));
+  assert_eq!(table.find_by_unique_guid(&ROOT_GUID), Some(root));
+  assert_eq!(table.find_by_type_guid(&LINUX_TYPE), [root]);
+}
+
+#[test]
+fn falls_back_to_backup_gpt() {
+  let expected = read(gpt_disk()).unwrap().partitions;
+
+  // A corrupt header
+  let mut disk = gpt_disk();
+  block(&mut disk, 1)[60] ^= 0xFF;
+  let table = read(disk).unwrap();
+  assert!(table.used_backup);
+  assert_eq!(table.partitions, expected);
+
+  // A corrupt partition entry array
+  let mut disk = gpt_disk();
+  block(&mut disk, 2)[32] ^= 0xFF;
+  let table = read(disk).unwrap();
+  assert!(table.used_backup);
+  assert_eq!(table.partitions, expected);
+
+  // Both copies corrupt
+  let mut disk = gpt_disk();
+  block(&mut disk, 1)[60] ^= 0xFF;
+  block(&mut disk, BLOCK_COUNT - 1)[60] ^= 0xFF;
+  assert_eq!(read(disk).err(), Some(Status::VOLUME_CORRUPTED));
+}
+
+#[test]
+fn rejects_gpt_extents_beyond_disk() {
+  // An entry array offset which overflows
+  let mut disk = gpt_disk();
+  block(&mut disk, 1)[72..80].copy_from_slice(&u64::MAX.to_le_bytes());
+  reseal_gpt_header(&mut disk, 1);
+  assert!(read(disk).unwrap().used_backup);
+
+  // Usable blocks past the end of the disk, in both headers
+  let mut disk = gpt_disk();
+  for lba in [1, BLOCK_COUNT - 1] {
+    block(&mut disk, lba)[48..56].copy_from_slice(&BLOCK_COUNT.to_le_bytes());
+    reseal_gpt_header(&mut disk, lba);
+  }
+  assert_eq!(read(disk).err(), Some(Status::VOLUME_CORRUPTED));
+}
+
+#[test]
+fn reads_mbr_and_ebr_chain() {
+  let mut disk = alloc::vec![0; BLOCK_COUNT as usize * BLOCK_SIZE];
+  let mbr = block(&mut disk, 0);
+  set_mbr_entry(mbr, 0, 0x0C, 1, 8);
+  set_mbr_entry(mbr, 1, 0x05, 16, 40);
+  mbr[MBR_TABLE_OFFSET] = 0x80;
+
+  // Logical partitions start relative to their EBR, and the next EBR
+  // relative to the extended partition
+  let ebr = block(&mut disk, 16);
+  set_mbr_entry(ebr, 0, 0x83, 1, 7);
+  set_mbr_entry(ebr, 1, 0x05, 8, 8);
+  let ebr = block(&mut disk, 24);
+  set_mbr_entry(ebr, 0, 0x83, 1, 5);
+
+  let table = read(disk).unwrap();
+  assert_eq!(table.scheme, PartitionScheme::MBR);
+  assert_eq!(table.disk_guid, None);
+
+  let extents: Vec<_> = table.partitions.iter().map(|p| (p.number, p.first_lba, p.last_lba)).collect();
+  assert_eq!(extents, [(1, 1, 8), (5, 17, 23), (6, 25, 29)]);
+  assert_eq!(table.partitions[0].kind, PartitionKind::MBR { os_type: 0x0C, bootable: true, logical: false });
+  assert_eq!(table.partitions[2].kind, PartitionKind::MBR { os_type: 0x83, bootable: false, logical: true });
+}
+
+#[test]
+fn rejects_mbr_extents_beyond_disk() {
+  let mut disk = alloc::vec![0; BLOCK_COUNT as usize * BLOCK_SIZE];
+  set_mbr_entry(block(&mut disk, 0), 0, 0x83, 1, BLOCK_COUNT as u32);
+  assert_eq!(read(disk).err(), Some(Status::VOLUME_CORRUPTED));
+
+  let mut disk = alloc::vec![0; BLOCK_COUNT as usize * BLOCK_SIZE];
+  set_mbr_entry(block(&mut disk, 0), 0, 0x05, 16, 40);
+  set_mbr_entry(block(&mut disk, 16), 0, 0x83, 1, u32::MAX);
+  assert_eq!(read(disk).err(), Some(Status::VOLUME_CORRUPTED));
+}
+
+#[test]
+fn rejects_disks_without_mbr() {
+  let disk = MemoryDisk::new(alloc::vec![0; BLOCK_COUNT as usize * BLOCK_SIZE], BLOCK_SIZE as u32).unwrap();
+  assert_eq!(PartitionTable::read(&disk).err(), Some(UdiveError::disk(Status::NOT_FOUND)));
+}
@@ -0,0 +1,157 @@
+use alloc::vec::Vec;
+
+use ed25519_compact::{PublicKey, Signature};
+use uefi::runtime::{VariableAttributes, VariableVendor};
+use uefi::{cstr16, guid, CStr16, Status};
+
+use crate::UdiveError;
+
+#[cfg(test)]
+mod tests;
+
+/// Returned when a driver is loaded under [`SignaturePolicy::Enforce`] but
+/// has no signature.
+pub const SIGNATURE_MISSING: Status = crate::oem_error(0x5160);
+/// Returned when a driver is loaded under [`SignaturePolicy::Enforce`] but
+/// its signature is malformed, or was not made by any trusted key.
+pub const SIGNATURE_INVALID: Status = crate::oem_error(0x5161);
+
+/// The vendor of the UEFI variable trusted keys may be enrolled in.
+pub const KEY_VARIABLE_VENDOR: VariableVendor = VariableVendor(guid!("7ef11444-3c14-4eef-9c19-a7754256588e"));
+/// The name of the UEFI variable trusted keys may be enrolled in.
+pub const KEY_VARIABLE_NAME: &CStr16 = cstr16!("WakatiwaiDriverKeys");
+
+/// The length of an ed25519 public key, in bytes.
+pub const KEY_LENGTH: usize = PublicKey::BYTES;
+/// The length of an ed25519 signature, in bytes.
+pub const SIGNATURE_LENGTH: usize = Signature::BYTES;
+
+/// The policy applied when loading drivers.
+static mut SIGNATURE_POLICY: SignaturePolicy = SignaturePolicy::Off;
+/// The keys trusted to sign drivers.
+static mut TRUSTED_KEYS: Vec<[u8; KEY_LENGTH]> = Vec::new();
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+/// How drivers are verified before they are loaded.
+/// 
+/// A driver `ext4.efi` is signed by a detached signature `ext4.sig` alongside
+/// it, holding the raw 64-byte ed25519 signature of the entire EFI file.
+pub enum SignaturePolicy {
+  /// Drivers without a valid signature are not loaded.
+  Enforce,
+  /// Drivers without a valid signature are loaded, and a warning logged.
+  Warn,
+  /// Signatures are not checked.
+  #[default]
+  Off
+}
+
+/// Sets the policy applied when loading drivers.
+pub fn set_signature_policy(policy: SignaturePolicy) {
+  unsafe {
+    SIGNATURE_POLICY = policy;
+  }
+}
+
+/// Returns the policy applied when loading drivers.
+pub fn signature_policy() -> SignaturePolicy {
+  unsafe { SIGNATURE_POLICY }
+}
+
+/// Trusts a key to sign drivers, e.g. one compiled into the loader.
+pub fn add_trusted_key(key: [u8; KEY_LENGTH]) {
+  unsafe {
+    if !TRUSTED_KEYS.contains(&key) {
+      TRUSTED_KEYS.push(key);
+    }
+  }
+}
+
+/// Trusts the keys enrolled in the [`KEY_VARIABLE_NAME`] UEFI variable.
+/// 
+/// The variable holds any number of concatenated 32-byte ed25519 public keys.
+/// It must not have [`VariableAttributes::RUNTIME_ACCESS`], so that it
+/// cannot have been written from the operating system.
+/// 
+/// # Returns
+/// 
+/// - `Ok(usize)` on success, containing the number of keys enrolled.
+/// - `Err(UdiveError::Firmware)` carrying [`uefi_raw::Status::NOT_FOUND`] if
+///   the variable does not exist.
+/// - `Err(UdiveError::Load)` carrying
+///   [`uefi_raw::Status::SECURITY_VIOLATION`] if the variable is accessible
+///   at runtime, or [`SIGNATURE_INVALID`] if it is not a whole number of
+///   keys.
+pub fn enroll_variable_keys() -> Result<usize, UdiveError> {
+  let (keys, attributes) = uefi::runtime::get_variable_boxed(KEY_VARIABLE_NAME, &KEY_VARIABLE_VENDOR)?;
+
+  if attributes.contains(VariableAttributes::RUNTIME_ACCESS) {
+    return Err(UdiveError::load(Status::SECURITY_VIOLATION).with_message("key variable is accessible at runtime"));
+  }
+  if keys.len() % KEY_LENGTH != 0 {
+    return Err(UdiveError::load(SIGNATURE_INVALID).with_message("key variable is malformed"));
+  }
+
+  for key in keys.chunks_exact(KEY_LENGTH) {
+    add_trusted_key(key.try_into().map_err(|_| UdiveError::load(SIGNATURE_INVALID))?);
+  }
+
+  Ok(keys.len() / KEY_LENGTH)
+}
+
+/// Checks an image against its detached signature.
+/// 
+/// # Arguments
+/// 
+/// - `image` (`&[u8]`) - The contents of the EFI file.
+/// - `signature` (`Option<&[u8]>`) - The contents of the signature file, if
+///   there is one.
+/// 
+/// # Returns
+/// 
+/// - [`uefi_raw::Status::SUCCESS`] if a trusted key signed the image.
+/// - [`SIGNATURE_MISSING`] if there is no signature.
+/// - [`SIGNATURE_INVALID`] otherwise, including when no keys are trusted.
+pub fn verify_signature(image: &[u8], signature: Option<&[u8]>) -> Status {
+  let Some(signature) = signature else {
+    return SIGNATURE_MISSING;
+  };
+  let Ok(signature) = Signature::from_slice(signature) else {
+    return SIGNATURE_INVALID;
+  };
+
+  let trusted = unsafe {
+    TRUSTED_KEYS.iter().any(|key| PublicKey::new(*key).verify(image, &signature).is_ok())
+  };
+  if trusted {
+    Status::SUCCESS
+  } else {
+    SIGNATURE_INVALID
+  }
+}
+
+/// Applies the [`signature_policy`] to a driver about to be loaded.
+/// 
+/// # Returns
+/// 
+/// - [`uefi_raw::Status::SUCCESS`] if the driver may be loaded. Under
+///   [`SignaturePolicy::Warn`], a warning is logged if it is not signed.
+/// - The error of [`verify_signature`] if the driver must not be loaded.
+pub(crate) fn check_driver(name: &str, image: &[u8], signature: Option<&[u8]>) -> Status {
+  let policy = signature_policy();
+  if policy == SignaturePolicy::Off {
+    return Status::SUCCESS;
+  }
+
+  let status = verify_signature(image, signature);
+  if status.is_error() && policy == SignaturePolicy::Warn {
+    log::warn!(
+      "Driver {} has {} signature",
+      name,
+      if status == SIGNATURE_MISSING { "no" } else { "an invalid" }
+    );
+    return Status::SUCCESS;
+  }
+
+  status
+}
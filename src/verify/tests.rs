@@ -0,0 +1,58 @@
+use ed25519_compact::{KeyPair, Seed};
+
+use super::*;
+
+const IMAGE: &[u8] = b"MZ\x90\x00 not really an EFI image";
+
+fn key_pair(seed: u8) -> KeyPair {
+  KeyPair::from_seed(Seed::new([seed; Seed::BYTES]))
+}
+
+fn sign(key_pair: &KeyPair, image: &[u8]) -> [u8; SIGNATURE_LENGTH] {
+  *key_pair.sk.sign(image, None)
+}
+
+// The policy and trusted keys are global, so are only changed by this test
+#[test]
+fn verifies_signatures_under_each_policy() {
+  let trusted = key_pair(1);
+  let untrusted = key_pair(2);
+  let signature = sign(&trusted, IMAGE);
+
+  // Nothing is trusted until a key is added
+  assert_eq!(verify_signature(IMAGE, Some(&signature)), SIGNATURE_INVALID);
+  add_trusted_key(*trusted.pk);
+  add_trusted_key(*trusted.pk);
+  assert_eq!(unsafe { TRUSTED_KEYS.len() }, 1);
+
+  assert_eq!(verify_signature(IMAGE, Some(&signature)), Status::SUCCESS);
+  assert_eq!(verify_signature(IMAGE, None), SIGNATURE_MISSING);
+  assert_eq!(verify_signature(IMAGE, Some(&sign(&untrusted, IMAGE))), SIGNATURE_INVALID);
+  assert_eq!(verify_signature(b"MZ tampered", Some(&signature)), SIGNATURE_INVALID);
+  assert_eq!(verify_signature(IMAGE, Some(&signature[..SIGNATURE_LENGTH - 1])), SIGNATURE_INVALID);
+  assert_eq!(verify_signature(IMAGE, Some(&[])), SIGNATURE_INVALID);
+
+  let mut padded = signature.to_vec();
+  padded.push(0);
+  assert_eq!(verify_signature(IMAGE, Some(&padded)), SIGNATURE_INVALID);
+
+  // Anything loads when signatures are not checked
+  assert_eq!(signature_policy(), SignaturePolicy::Off);
+  assert_eq!(check_driver("fat", IMAGE, None), Status::SUCCESS);
+  assert_eq!(check_driver("fat", IMAGE, Some(&signature[..8])), Status::SUCCESS);
+
+  // Failures are only logged under Warn
+  set_signature_policy(SignaturePolicy::Warn);
+  assert_eq!(check_driver("fat", IMAGE, Some(&signature)), Status::SUCCESS);
+  assert_eq!(check_driver("fat", IMAGE, None), Status::SUCCESS);
+  assert_eq!(check_driver("fat", IMAGE, Some(&sign(&untrusted, IMAGE))), Status::SUCCESS);
+
+  // And stop the driver loading under Enforce
+  set_signature_policy(SignaturePolicy::Enforce);
+  assert_eq!(check_driver("fat", IMAGE, Some(&signature)), Status::SUCCESS);
+  assert_eq!(check_driver("fat", IMAGE, None), SIGNATURE_MISSING);
+  assert_eq!(check_driver("fat", IMAGE, Some(&signature[..32])), SIGNATURE_INVALID);
+  assert_eq!(check_driver("fat", IMAGE, Some(&sign(&untrusted, IMAGE))), SIGNATURE_INVALID);
+
+  set_signature_policy(SignaturePolicy::Off);
+}
@@ -0,0 +1,61 @@
+use alloc::vec::Vec;
+
+use uefi::proto::media::file::{Directory, File, FileAttribute, FileMode};
+use uefi::{CStr16, CString16, Status};
+
+use super::read_to_end;
+
+#[derive(Clone, Debug, PartialEq)]
+/// An entry of a [`DriverDirectory`].
+pub(crate) struct DirectoryEntry {
+  /// The name of the entry.
+  pub name: CString16,
+  /// Whether the entry is a regular file, rather than a directory.
+  pub is_regular_file: bool
+}
+
+/// A directory drivers are discovered in.
+/// 
+/// Implemented for UEFI [`Directory`]s, and by the mock directories used to
+/// test discovery on the host.
+pub(crate) trait DriverDirectory {
+  /// Reads the next entry of the directory.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(Some(DirectoryEntry))` on success.
+  /// - `Ok(None)` once every entry has been read.
+  /// - `Err(Status)` if the directory could not be read. Reading should not
+  ///   be continued, as the position of the directory is unknown.
+  fn next_entry(&mut self) -> Result<Option<DirectoryEntry>, Status>;
+
+  /// Reads a file in the directory in full.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(Some(Vec<u8>))` on success.
+  /// - `Ok(None)` if the file does not exist, or is not a regular file.
+  /// - `Err(Status)` if the file could not be read.
+  fn read_file(&mut self, name: &CStr16) -> Result<Option<Vec<u8>>, Status>;
+}
+
+impl DriverDirectory for Directory {
+  fn next_entry(&mut self) -> Result<Option<DirectoryEntry>, Status> {
+    let info = self.read_entry_boxed().map_err(|err| err.status())?;
+
+    Ok(
+      info.map(|info| DirectoryEntry {
+        name: CString16::from(info.file_name()),
+        is_regular_file: info.is_regular_file()
+      })
+    )
+  }
+
+  fn read_file(&mut self, name: &CStr16) -> Result<Option<Vec<u8>>, Status> {
+    match self.open(name, FileMode::Read, FileAttribute::READ_ONLY) {
+      Ok(ok) => read_to_end(ok),
+      Err(err) if err.status() == Status::NOT_FOUND => Ok(None),
+      Err(err) => Err(err.status())
+    }
+  }
+}
@@ -1,193 +1,291 @@
 use uefi::proto::media::file::{Directory, File, FileAttribute, FileHandle, FileInfo, FileMode};
-use uefi::{CString16, Error, Status};
+use uefi::proto::media::fs::SimpleFileSystem;
+use uefi::{CStr16, CString16, Handle, Status};
 
 use alloc::boxed::Box;
 use alloc::vec::Vec;
 use alloc::string::ToString;
 
+use crate::disk::DiskReader;
+use crate::fs::ProbeResult;
 use crate::*;
 
-/// Open the directory containing the `boot` and `fs` driver subdirectories.
-/// 
-/// The image file system is opened on the root directory, which in turn opens
-/// [`DRIVER_DIRECTORY`].
-/// 
-/// # Returns
-/// - `Ok(uefi::proto::media::file::FileHandle)` on success.
-/// - `Err(uefi::result::Error)` on failure.
-fn get_driver_dir() -> Result<FileHandle, Error> {
-  let mut efifs = uefi::boot::get_image_file_system(uefi::boot::image_handle())?;
-  let mut efivol = efifs.open_volume()?;
-  efivol.open(
-    DRIVER_DIRECTORY,
-    FileMode::Read,
-    FileAttribute::DIRECTORY
-  )
-}
+mod directory;
+mod registry;
+mod roots;
+#[cfg(test)]
+mod tests;
+use directory::{DirectoryEntry, DriverDirectory};
+pub use registry::DriverRegistry;
+pub use roots::{search_roots, set_search_roots, DriverVolume, SearchRoot};
 
-/// Open the directory containing boot drivers.
+/// Open the directory containing drivers of a type under each search root.
 /// 
-/// The driver directory is opened via [`get_driver_dir`], and
-/// [`BOOT_DRIVER_DIRECTORY`] is thne in turn opened.
+/// Each of the [`search_roots`] is opened in turn, and `subdirectory` (either
+/// [`BOOT_DRIVER_DIRECTORY`] or [`FSYS_DRIVER_DIRECTORY`]) is opened within
+/// it. Search roots which cannot be opened are skipped.
 /// 
 /// # Returns
-/// - `Ok(uefi::proto::media::file::FileHandle)` on success.
-/// - `Err(uefi::result::Error)` on failure.
-fn get_boot_driver_dir() -> Result<FileHandle, Error> {
-  get_driver_dir().unwrap().into_directory().unwrap().open(
-    BOOT_DRIVER_DIRECTORY,
-    FileMode::Read,
-    FileAttribute::DIRECTORY
-  )
-}
+/// - `Ok(Vec<(Handle, CString16, Directory)>)` on success, containing the
+///   volume handle, root directory and opened subdirectory of each search
+///   root, in order of precedence.
+/// - `Err(uefi::Status)` if no search root could be opened, containing the
+///   status of the last failure.
+fn get_driver_dirs(subdirectory: &CStr16) -> Result<Vec<(Handle, CString16, Directory)>, Status> {
+  let mut ret = Vec::new();
+  let mut last_err = Status::NOT_FOUND;
 
-/// Open the directory containing file system drivers.
-/// 
-/// The driver directory is opened via [`get_driver_dir`], and
-/// [`FSYS_DRIVER_DIRECTORY`] is thne in turn opened.
-/// 
-/// # Returns
-/// - `Ok(uefi::proto::media::file::FileHandle)` on success.
-/// - `Err(uefi::result::Error)` on failure.
-fn get_fs_driver_dir() -> Result<FileHandle, Error> {
-  get_driver_dir()?.into_directory().unwrap().open(
-    FSYS_DRIVER_DIRECTORY,
-    FileMode::Read,
-    FileAttribute::DIRECTORY
-  )
+  for root in search_roots() {
+    let opened = root.open().and_then(|(volume, mut directory)| {
+      match directory.open(subdirectory, FileMode::Read, FileAttribute::DIRECTORY) {
+        Ok(ok) => Ok((volume, ok.into_directory().ok_or(Status::NOT_FOUND)?)),
+        Err(err) => Err(err.status())
+      }
+    });
+
+    match opened {
+      Ok((volume, directory)) => ret.push((volume, root.directory, directory)),
+      Err(err) => {
+        last_err = err;
+      }
+    }
+  }
+
+  if ret.is_empty() {
+    return Err(last_err);
+  }
+
+  Ok(ret)
 }
 
-/// Validates a driver from its EFI file.
+/// Validates a driver from its directory entry.
 /// 
-/// A file handle is checked and deemed valid if it meets the following:
-/// - It points to a regular file.
-/// - The file has a `.efi` extension.
-fn is_valid_driver(handle: &mut FileHandle) -> bool {
+/// An entry is deemed valid if it meets the following:
+/// - It is a regular file.
+/// - The file has a `.efi` extension, preceded by a name.
+fn is_valid_driver(entry: &DirectoryEntry) -> bool {
   // TODO: Make checks more restrictive
-  let driver_info: Box<FileInfo> = handle.get_boxed_info().unwrap();
-  
   // Ensure that the driver is a file
-  if !driver_info.is_regular_file() {
+  if !entry.is_regular_file {
     return false;
   }
   // Ensure that the driver has the efi extension
-  if !driver_info.file_name().to_string().ends_with(".efi") {
+  let name = entry.name.to_string();
+  if !name.ends_with(".efi") || name.len() == ".efi".len() {
     return false;
   }
 
   true
 }
 
-/// Returns all drivers from a directory.
+/// Returns the path of a file accompanying a driver, e.g. its manifest.
 /// 
-/// A reference to a UEFI directory is given, and all valid drivers therein are
-/// returned.
+/// # Arguments
+/// - `driver_path` (`&CStr16`) - The name or path of the driver's EFI file.
+/// - `extension` (`&str`) - The extension of the accompanying file, which
+///   replaces `.efi`.
 /// 
 /// # Returns
-/// - `Ok(Vec<Driver>)` on success.
-/// - `Err(uefi::Status)` on failure.
-fn get_driver_files_from_dir(directory: &mut Directory) -> Result<Vec<Driver>, Status> {
-  let mut drivers: Vec<Driver> = Vec::new();
+/// - `Ok(CString16)` on success.
+/// - `Err(uefi::Status::INVALID_PARAMETER)` if the path does not end in
+///   `.efi`.
+pub(crate) fn sibling_path(driver_path: &CStr16, extension: &str) -> Result<CString16, Status> {
+  let driver_path = driver_path.to_string();
+  let stem = driver_path.strip_suffix(".efi").ok_or(Status::INVALID_PARAMETER)?;
 
-  loop {
-    match directory.read_entry_boxed() {
-      Ok(ok) => {
-        if ok.is_none() {
-          // No more files
-          break;
-        }
+  CString16::try_from(alloc::format!("{}.{}", stem, extension).as_str()).map_err(|_| Status::INVALID_PARAMETER)
+}
 
-        // Open a handle on the directory member
-        let mut file_handle = directory.open(
-          ok.unwrap().file_name(),
-          FileMode::Read,
-          FileAttribute::READ_ONLY
-        );
-        
-        // If unable to read, error
-        if file_handle.is_err() {
-          return Err(file_handle.err().unwrap().status());
-        }
+/// Reads a file in full.
+/// 
+/// # Returns
+/// - `Ok(Some(Vec<u8>))` on success.
+/// - `Ok(None)` if the handle is not a regular file.
+/// - `Err(uefi::Status)` if the file could not be read, or is too large to
+///   be held in memory.
+fn read_to_end(handle: FileHandle) -> Result<Option<Vec<u8>>, Status> {
+  let mut file = match handle.into_regular_file() {
+    Some(some) => some,
+    None => {
+      return Ok(None);
+    }
+  };
+  let info: Box<FileInfo> = file.get_boxed_info().map_err(|err| err.status())?;
 
-        // Skip if invalid
-        if !is_valid_driver(file_handle.as_mut().unwrap()) {
-          continue;
-        }
+  // Refuse sizes which cannot be allocated, rather than aborting
+  let size = usize::try_from(info.file_size()).map_err(|_| Status::OUT_OF_RESOURCES)?;
+  let mut bytes = Vec::new();
+  bytes.try_reserve_exact(size).map_err(|_| Status::OUT_OF_RESOURCES)?;
+  bytes.resize(size, 0);
 
-        let mut driver_name = CString16::new();
-        driver_name.push_str((file_handle.as_mut().unwrap().get_boxed_info().unwrap() as Box<FileInfo>).file_name());
+  let read = file.read(&mut bytes).map_err(|err| err.status())?;
+  bytes.truncate(read);
 
-        drivers.push(
-          Driver {
-            name: driver_name,
-            driver_type: None,
-            exec_handle: None
-          }
-        );
+  Ok(Some(bytes))
+}
+
+/// Reads a file in full from a volume.
+/// 
+/// # Arguments
+/// - `volume` (`Handle`) - A handle supporting `SimpleFileSystem`.
+/// - `path` (`&CStr16`) - The absolute path of the file on the volume.
+/// 
+/// # Returns
+/// - `Ok(Some(Vec<u8>))` on success.
+/// - `Ok(None)` if the file does not exist, or is not a regular file.
+/// - `Err(uefi::Status)` if the file could not be read.
+pub(crate) fn read_file(volume: Handle, path: &CStr16) -> Result<Option<Vec<u8>>, Status> {
+  let mut filesystem = roots::get_protocol::<SimpleFileSystem>(volume)?;
+  let mut root = filesystem.open_volume().map_err(|err| err.status())?;
+
+  match root.open(path, FileMode::Read, FileAttribute::READ_ONLY) {
+    Ok(ok) => read_to_end(ok),
+    Err(err) if err.status() == Status::NOT_FOUND => Ok(None),
+    Err(err) => Err(err.status())
+  }
+}
+
+/// Reads the manifest accompanying a driver, if any.
+/// 
+/// The manifest of `name.efi` is `name.toml`, in the same directory.
+/// 
+/// # Returns
+/// - `Ok(Some(DriverManifest))` if the manifest was read.
+/// - `Ok(None)` if the driver has no manifest.
+/// - `Err(uefi::Status)` if the manifest could not be read or parsed.
+fn read_manifest<D: DriverDirectory + ?Sized>(directory: &mut D, driver_name: &CStr16) -> Result<Option<DriverManifest>, Status> {
+  let manifest_name = sibling_path(driver_name, "toml")?;
+
+  match directory.read_file(&manifest_name)? {
+    Some(some) => DriverManifest::from_bytes(&some).map(Some),
+    None => Ok(None)
+  }
+}
+
+/// Returns all drivers from a directory.
+/// 
+/// A directory is given, and all valid drivers therein are returned, along
+/// with their manifests. Entries which are not drivers are ignored, and
+/// drivers with a manifest which cannot be read or parsed are skipped with a
+/// warning. Should the directory itself fail to be read, the drivers found
+/// up to that point are returned, also with a warning.
+fn get_driver_files_from_dir<D: DriverDirectory + ?Sized>(directory: &mut D) -> Vec<Driver> {
+  let mut drivers: Vec<Driver> = Vec::new();
+
+  loop {
+    let entry = match directory.next_entry() {
+      Ok(Some(some)) => some,
+      Ok(None) => {
+        break;
       }
-      Err(_) => {
+      Err(err) => {
+        // The position of the directory is unknown, so reading cannot go on
+        log::warn!(
+          "Skipping remaining drivers: {}",
+          UdiveError::discovery(err).with_message("driver directory could not be read")
+        );
         break;
       }
+    };
+
+    // Skip if invalid
+    if !is_valid_driver(&entry) {
+      continue;
     }
+
+    // Skip if the manifest is present but invalid
+    let manifest = match read_manifest(directory, &entry.name) {
+      Ok(ok) => ok,
+      Err(err) => {
+        log::warn!(
+          "Skipping driver: {}",
+          UdiveError::discovery(err).with_driver(entry.name.to_string()).with_message("manifest could not be read")
+        );
+        continue;
+      }
+    };
+
+    drivers.push(
+      Driver {
+        name: entry.name,
+        driver_type: None,
+        exec_handle: None,
+        state: DriverState::Discovered,
+        manifest,
+        volume: None,
+        root: CString16::from(DRIVER_DIRECTORY),
+        buffer: None,
+        signature: None,
+        log: DriverLog::default(),
+        timeout: None
+      }
+    );
   }
 
-  Ok(drivers)
+  drivers
 }
 
-/// Returns all boot drivers.
+/// Returns all drivers of a type, across all search roots.
 /// 
-/// The boot drivers directory is opened and all valid boot drivers are
-/// returned.
-fn get_boot_drivers() -> Result<Vec<BootDriver>, Status> {
-  let mut ret: Vec<BootDriver> = Vec::new();
-  match get_boot_driver_dir() {
-    Ok(ok) => {
-      for driver in get_driver_files_from_dir(&mut ok.into_directory().unwrap())?.iter() {
-        ret.push(
-          BootDriver(
-            Driver {
-              name: driver.name.clone(),
-              driver_type: Some(DriverType::BOOT),
-              exec_handle: driver.exec_handle
-            }
-          )
-        )
+/// Where drivers with the same name are found under several search roots,
+/// only the one under the root with the highest precedence is returned. A
+/// driver directory which cannot be read in full does not stop the other
+/// roots from being searched.
+fn get_drivers(driver_type: DriverType) -> Result<Vec<Driver>, UdiveError> {
+  let subdirectory = match driver_type {
+    DriverType::BOOT  => { BOOT_DRIVER_DIRECTORY }
+    DriverType::FS    => { FSYS_DRIVER_DIRECTORY }
+  };
+
+  let mut ret: Vec<Driver> = Vec::new();
+  let driver_dirs = get_driver_dirs(subdirectory)
+    .map_err(|err| UdiveError::discovery(err).with_message("no driver directory could be opened"))?;
+  for (volume, root, mut directory) in driver_dirs {
+    for driver in get_driver_files_from_dir(&mut directory) {
+      // Skip drivers overridden by a root with higher precedence
+      if ret.iter().any(|found| found.name().eq_ignore_ascii_case(&driver.name())) {
+        continue;
       }
 
-      return Ok(ret);
-    }
-    Err(err) => {
-      return Err(err.status());
+      ret.push(
+        Driver {
+          driver_type: Some(driver_type),
+          volume: Some(volume),
+          root: root.clone(),
+          ..driver
+        }
+      );
     }
   }
+
+  Ok(ret)
 }
 
-/// Returns all file system drivers.
+/// Returns all boot drivers.
 /// 
-/// The file system drivers directory is opened and all valid file system
-/// drivers are returned.
-fn get_fs_drivers() -> Result<Vec<FSDriver>, Status> {
-  let mut ret: Vec<FSDriver> = Vec::new();
-  match get_fs_driver_dir() {
-    Ok(ok) => {
-      for driver in get_driver_files_from_dir(&mut ok.into_directory().unwrap())?.iter() {
-        ret.push(
-          FSDriver(
-            Driver {
-              name: driver.name.clone(),
-              driver_type: Some(DriverType::FS),
-              exec_handle: driver.exec_handle
-            }
-          )
-        )
-      }
+/// The boot drivers directory under each search root is opened and all valid
+/// boot drivers are returned, unloaded. Use a [`DriverRegistry`] to avoid
+/// re-reading the directories on every call.
+/// 
+/// # Returns
+/// - `Ok(Vec<BootDriver>)` on success.
+/// - `Err(UdiveError::Discovery)` - No boot driver directory could be opened.
+pub fn get_boot_drivers() -> Result<Vec<BootDriver>, UdiveError> {
+  Ok(get_drivers(DriverType::BOOT)?.into_iter().map(BootDriver).collect())
+}
 
-      return Ok(ret)
-    }
-    Err(err) => {
-      return Err(err.status());
-    }
-  }
+/// Returns all file system drivers.
+/// 
+/// The file system drivers directory under each search root is opened and all
+/// valid file system drivers are returned, unloaded. Use a [`DriverRegistry`]
+/// to avoid re-reading the directories on every call.
+/// 
+/// # Returns
+/// - `Ok(Vec<FSDriver>)` on success.
+/// - `Err(UdiveError::Discovery)` - No file system driver directory could be
+///   opened.
+pub fn get_fs_drivers() -> Result<Vec<FSDriver>, UdiveError> {
+  Ok(get_drivers(DriverType::FS)?.into_iter().map(FSDriver).collect())
 }
 
 /// Attempts to obtain a specified boot driver.
@@ -198,14 +296,10 @@ fn get_fs_drivers() -> Result<Vec<FSDriver>, Status> {
 /// # Returns
 /// - `Ok(Some(BootDriver))` - The inner [`BootDriver`] is the requested driver.
 /// - `Ok(None)` - The boot driver could not be found.
-/// - `Err(Status)` - The boot driver directory could not be opened.
-pub fn get_boot_driver(driver_name: &str) -> Result<Option<BootDriver>, Status> {
-  let boot_drivers = get_boot_drivers();
-  if boot_drivers.is_err() {
-    return Err(boot_drivers.err().unwrap());
-  }
-
-  for boot_driver in boot_drivers.unwrap() {
+/// - `Err(UdiveError::Discovery)` - The boot driver directory could not be
+///   opened.
+pub fn get_boot_driver(driver_name: &str) -> Result<Option<BootDriver>, UdiveError> {
+  for boot_driver in get_boot_drivers()? {
     if boot_driver.name() == driver_name {
       return Ok(Some(boot_driver));
     }
@@ -222,18 +316,53 @@ pub fn get_boot_driver(driver_name: &str) -> Result<Option<BootDriver>, Status>
 /// # Returns
 /// - `Ok(Some(FSDriver))` - The inner [`FSDriver`] is the requested driver.
 /// - `Ok(None)` - The file system driver could not be found.
-/// - `Err(Status)` - The file system driver directory could not be opened.
-pub fn get_fs_driver(driver_name: &str) -> Result<Option<FSDriver>, Status> {
-  let fs_drivers = get_fs_drivers();
-  if fs_drivers.is_err() {
-    return Err(fs_drivers.err().unwrap());
-  }
-
-  for fs_driver in fs_drivers.unwrap() {
+/// - `Err(UdiveError::Discovery)` - The file system driver directory could
+///   not be opened.
+pub fn get_fs_driver(driver_name: &str) -> Result<Option<FSDriver>, UdiveError> {
+  for fs_driver in get_fs_drivers()? {
     if fs_driver.name() == driver_name {
       return Ok(Some(fs_driver));
     }
   }
 
   return Ok(None);
-}
\ No newline at end of file
+}
+
+/// Finds the file system driver best able to read a partition.
+/// 
+/// Each file system driver is loaded in turn and probed against the
+/// partition, and the driver reporting the highest confidence is chosen. Ties
+/// go to the driver found first. Drivers which fail to load, or cannot be
+/// probed, are skipped.
+/// 
+/// # Arguments
+/// 
+/// - `diskreader` (`&DiskReader`) - The partition to probe.
+/// 
+/// # Returns
+/// - `Ok(Some((FSDriver, ProbeResult)))` - The inner [`FSDriver`] is the best
+///   match, not yet loaded, and the [`ProbeResult`] describes the file system.
+/// - `Ok(None)` - No driver recognised the partition.
+/// - `Err(UdiveError::Discovery)` - The file system driver directory could
+///   not be opened.
+pub fn probe_partition(diskreader: &DiskReader) -> Result<Option<(FSDriver, ProbeResult)>, UdiveError> {
+  let mut best: Option<(FSDriver, ProbeResult)> = None;
+
+  for fs_driver in get_fs_drivers()? {
+    // Probe a copy, so that the driver is returned unloaded
+    let mut probed_driver = fs_driver.clone();
+    if probed_driver.load().is_err() {
+      continue;
+    }
+    let probe = probed_driver.probe(diskreader);
+    let _ = probed_driver.unload();
+
+    if let Ok(probe) = probe
+      && probe.confidence > 0
+      && best.as_ref().is_none_or(|(_, best_probe)| probe.confidence > best_probe.confidence) {
+      best = Some((fs_driver, probe));
+    }
+  }
+
+  Ok(best)
+}
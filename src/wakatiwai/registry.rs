@@ -0,0 +1,142 @@
+use alloc::vec::Vec;
+
+use crate::{BootDriver, FSDriver, UdiveError};
+use super::{get_boot_drivers, get_fs_drivers};
+
+#[derive(Clone, Debug, Default)]
+/// A cache of the drivers installed under the configured search roots (see
+/// [`super::set_search_roots`]), in the precedence order of the roots.
+/// 
+/// The driver directories of each type are read the first time drivers of
+/// that type are requested, and not again until [`DriverRegistry::refresh`]
+/// is called. Drivers are
+/// handed out as clones, so loading a driver obtained from the registry does
+/// not change the registry's copy.
+/// 
+/// Drivers created in memory (e.g. with [`crate::include_driver`]) may be
+/// added to the registry, and are listed after those found on disk. A driver
+/// on disk with the same name takes precedence, so that embedded drivers can
+/// be overridden by installing a newer version.
+pub struct DriverRegistry {
+  /// The cached boot drivers, if the boot driver directory has been read.
+  boot_drivers: Option<Vec<BootDriver>>,
+  /// The cached file system drivers, if the file system driver directory has
+  /// been read.
+  fs_drivers: Option<Vec<FSDriver>>,
+  /// Boot drivers added in memory.
+  embedded_boot_drivers: Vec<BootDriver>,
+  /// File system drivers added in memory.
+  embedded_fs_drivers: Vec<FSDriver>
+}
+
+impl DriverRegistry {
+  /// Creates an empty registry. No directories are read until drivers are
+  /// requested.
+  pub const fn new() -> DriverRegistry {
+    DriverRegistry {
+      boot_drivers: None,
+      fs_drivers: None,
+      embedded_boot_drivers: Vec::new(),
+      embedded_fs_drivers: Vec::new()
+    }
+  }
+
+  /// Adds a boot driver which is not installed on disk, e.g. one embedded in
+  /// the loader.
+  pub fn add_boot_driver(&mut self, boot_driver: BootDriver) {
+    self.embedded_boot_drivers.push(boot_driver);
+    self.boot_drivers = None;
+  }
+
+  /// Adds a file system driver which is not installed on disk, e.g. one
+  /// embedded in the loader.
+  pub fn add_fs_driver(&mut self, fs_driver: FSDriver) {
+    self.embedded_fs_drivers.push(fs_driver);
+    self.fs_drivers = None;
+  }
+
+  /// Discards the cached drivers, so that the driver directories are read
+  /// again on the next request. Drivers added in memory are kept.
+  pub fn refresh(&mut self) {
+    self.boot_drivers = None;
+    self.fs_drivers = None;
+  }
+
+  /// Returns all boot drivers, reading the boot driver directory if it has
+  /// not yet been read.
+  /// 
+  /// # Returns
+  /// - `Ok(&[BootDriver])` on success.
+  /// - `Err(UdiveError::Discovery)` - The boot driver directory could not be opened, and no
+  ///   boot drivers were added in memory.
+  pub fn boot_drivers(&mut self) -> Result<&[BootDriver], UdiveError> {
+    if self.boot_drivers.is_none() {
+      let mut drivers = match get_boot_drivers() {
+        Ok(ok) => ok,
+        // Without a driver directory, only the drivers added in memory exist
+        Err(_) if !self.embedded_boot_drivers.is_empty() => Vec::new(),
+        Err(err) => {
+          return Err(err);
+        }
+      };
+      for boot_driver in &self.embedded_boot_drivers {
+        if !drivers.iter().any(|found| found.name() == boot_driver.name()) {
+          drivers.push(boot_driver.clone());
+        }
+      }
+      self.boot_drivers = Some(drivers);
+    }
+
+    Ok(self.boot_drivers.as_deref().unwrap_or_default())
+  }
+
+  /// Returns all file system drivers, reading the file system driver
+  /// directory if it has not yet been read.
+  /// 
+  /// # Returns
+  /// - `Ok(&[FSDriver])` on success.
+  /// - `Err(UdiveError::Discovery)` - The file system driver directory could not be opened,
+  ///   and no file system drivers were added in memory.
+  pub fn fs_drivers(&mut self) -> Result<&[FSDriver], UdiveError> {
+    if self.fs_drivers.is_none() {
+      let mut drivers = match get_fs_drivers() {
+        Ok(ok) => ok,
+        // Without a driver directory, only the drivers added in memory exist
+        Err(_) if !self.embedded_fs_drivers.is_empty() => Vec::new(),
+        Err(err) => {
+          return Err(err);
+        }
+      };
+      for fs_driver in &self.embedded_fs_drivers {
+        if !drivers.iter().any(|found| found.name() == fs_driver.name()) {
+          drivers.push(fs_driver.clone());
+        }
+      }
+      self.fs_drivers = Some(drivers);
+    }
+
+    Ok(self.fs_drivers.as_deref().unwrap_or_default())
+  }
+
+  /// Attempts to obtain a specified boot driver.
+  /// 
+  /// # Returns
+  /// - `Ok(Some(BootDriver))` - The inner [`BootDriver`] is the requested
+  ///   driver, unloaded.
+  /// - `Ok(None)` - The boot driver could not be found.
+  /// - `Err(UdiveError::Discovery)` - The boot driver directory could not be opened.
+  pub fn get_boot_driver(&mut self, driver_name: &str) -> Result<Option<BootDriver>, UdiveError> {
+    Ok(self.boot_drivers()?.iter().find(|boot_driver| boot_driver.name() == driver_name).cloned())
+  }
+
+  /// Attempts to obtain a specified file system driver.
+  /// 
+  /// # Returns
+  /// - `Ok(Some(FSDriver))` - The inner [`FSDriver`] is the requested driver,
+  ///   unloaded.
+  /// - `Ok(None)` - The file system driver could not be found.
+  /// - `Err(UdiveError::Discovery)` - The file system driver directory could not be opened.
+  pub fn get_fs_driver(&mut self, driver_name: &str) -> Result<Option<FSDriver>, UdiveError> {
+    Ok(self.fs_drivers()?.iter().find(|fs_driver| fs_driver.name() == driver_name).cloned())
+  }
+}
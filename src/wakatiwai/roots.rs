@@ -0,0 +1,186 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use uefi::boot::{open_protocol, OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol};
+use uefi::proto::device_path::{DevicePath, DevicePathNodeEnum};
+use uefi::proto::device_path::media::PartitionSignature;
+use uefi::proto::loaded_image::LoadedImage;
+use uefi::proto::media::file::{Directory, File, FileAttribute, FileMode, FileSystemVolumeLabel};
+use uefi::proto::media::fs::SimpleFileSystem;
+use uefi::proto::ProtocolPointer;
+use uefi::{CStr16, CString16, Guid, Handle, Status};
+
+use crate::{UdiveError, DRIVER_DIRECTORY};
+
+/// The search roots in use, or `None` to search only the default root.
+static mut SEARCH_ROOTS: Option<Vec<SearchRoot>> = None;
+
+#[derive(Debug, PartialEq)]
+/// Identifies the volume a [`SearchRoot`] resides on.
+pub enum DriverVolume {
+  /// The volume wakatiwai itself was loaded from.
+  Image,
+  /// The volume with the given device path, which must match exactly.
+  DevicePath(Box<DevicePath>),
+  /// The GPT partition with the given unique partition GUID.
+  PartitionGuid(Guid),
+  /// The volume with the given label.
+  Label(CString16)
+}
+
+impl Clone for DriverVolume {
+  fn clone(&self) -> DriverVolume {
+    match self {
+      DriverVolume::Image => DriverVolume::Image,
+      DriverVolume::DevicePath(device_path) => DriverVolume::DevicePath(device_path.to_boxed()),
+      DriverVolume::PartitionGuid(guid) => DriverVolume::PartitionGuid(*guid),
+      DriverVolume::Label(label) => DriverVolume::Label(label.clone())
+    }
+  }
+}
+
+impl DriverVolume {
+  /// Finds the handle of the volume.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(Handle)` on success. The handle supports [`SimpleFileSystem`].
+  /// - `Err(UdiveError::Discovery)` carrying
+  ///   [`uefi_raw::Status::NOT_FOUND`] if no such volume exists.
+  pub fn locate(&self) -> Result<Handle, UdiveError> {
+    let not_found = || UdiveError::discovery(Status::NOT_FOUND).with_message("driver volume not found");
+
+    if let DriverVolume::Image = self {
+      let loaded_image = get_protocol::<LoadedImage>(uefi::boot::image_handle()).map_err(UdiveError::discovery)?;
+      return loaded_image.device().ok_or_else(not_found);
+    }
+
+    let volumes = uefi::boot::find_handles::<SimpleFileSystem>().map_err(|err| UdiveError::discovery(err.status()))?;
+    volumes.into_iter()
+      .find(|volume| self.matches(*volume))
+      .ok_or_else(not_found)
+  }
+
+  /// Returns whether a volume handle is the volume identified.
+  fn matches(&self, volume: Handle) -> bool {
+    match self {
+      DriverVolume::Image => self.locate().is_ok_and(|image_volume| image_volume == volume),
+      DriverVolume::DevicePath(device_path) => {
+        get_protocol::<DevicePath>(volume).is_ok_and(|volume_path| *volume_path == **device_path)
+      }
+      DriverVolume::PartitionGuid(guid) => {
+        let Ok(volume_path) = get_protocol::<DevicePath>(volume) else {
+          return false;
+        };
+        volume_path.node_iter().any(|node| {
+          matches!(
+            node.as_enum(),
+            Ok(DevicePathNodeEnum::MediaHardDrive(hard_drive))
+              if hard_drive.partition_signature() == PartitionSignature::Guid(*guid)
+          )
+        })
+      }
+      DriverVolume::Label(label) => {
+        let Ok(mut filesystem) = get_protocol::<SimpleFileSystem>(volume) else {
+          return false;
+        };
+        let Ok(mut root) = filesystem.open_volume() else {
+          return false;
+        };
+        root.get_boxed_info::<FileSystemVolumeLabel>()
+          .is_ok_and(|volume_label| volume_label.volume_label() == &**label)
+      }
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// A directory searched for drivers.
+/// 
+/// Boot drivers are found in its `boot` subdirectory, and file system drivers
+/// in its `fs` subdirectory.
+pub struct SearchRoot {
+  /// The volume the directory resides on.
+  pub volume: DriverVolume,
+  /// The absolute path of the directory on the volume (e.g.
+  /// `\EFI\wakatiwai\drivers`).
+  pub directory: CString16
+}
+
+impl Default for SearchRoot {
+  /// The root searched when none are configured: [`DRIVER_DIRECTORY`] on the
+  /// volume wakatiwai was loaded from.
+  fn default() -> SearchRoot {
+    SearchRoot::new(DriverVolume::Image, DRIVER_DIRECTORY)
+  }
+}
+
+impl SearchRoot {
+  /// Creates a search root.
+  pub fn new(volume: DriverVolume, directory: &CStr16) -> SearchRoot {
+    SearchRoot {
+      volume,
+      directory: CString16::from(directory)
+    }
+  }
+
+  /// Opens the directory of the search root.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok((Handle, Directory))` on success, containing the handle of the
+  ///   volume and the opened directory.
+  /// - `Err(Status)` if the volume could not be found or the directory could
+  ///   not be opened.
+  pub(crate) fn open(&self) -> Result<(Handle, Directory), Status> {
+    let volume = self.volume.locate()?;
+    let mut filesystem = get_protocol::<SimpleFileSystem>(volume)?;
+    let mut root = filesystem.open_volume().map_err(|err| err.status())?;
+    let directory = root.open(&self.directory, FileMode::Read, FileAttribute::DIRECTORY)
+      .map_err(|err| err.status())?
+      .into_directory()
+      .ok_or(Status::NOT_FOUND)?;
+
+    Ok((volume, directory))
+  }
+}
+
+/// Opens a protocol on a handle without taking exclusive access to it.
+pub(crate) fn get_protocol<P: ProtocolPointer + ?Sized>(handle: Handle) -> Result<ScopedProtocol<P>, Status> {
+  unsafe {
+    open_protocol::<P>(
+      OpenProtocolParams {
+        handle,
+        agent: uefi::boot::image_handle(),
+        controller: None
+      },
+      OpenProtocolAttributes::GetProtocol
+    ).map_err(|err| err.status())
+  }
+}
+
+/// Sets the directories searched for drivers.
+/// 
+/// Roots are searched in order. Where drivers of the same type and name are
+/// found under several roots, the one under the earliest root is used, so
+/// that e.g. a root on a removable disk listed first overrides drivers
+/// shipped on the ESP.
+/// 
+/// Passing an empty list restores the default root (see
+/// [`SearchRoot::default`]). A [`super::DriverRegistry`] which has already
+/// been populated must be refreshed to pick up the new roots.
+pub fn set_search_roots(roots: Vec<SearchRoot>) {
+  unsafe {
+    SEARCH_ROOTS = if roots.is_empty() { None } else { Some(roots) };
+  }
+}
+
+/// Returns the directories searched for drivers, in order of precedence.
+pub fn search_roots() -> Vec<SearchRoot> {
+  unsafe {
+    match &SEARCH_ROOTS {
+      Some(some) => some.clone(),
+      None => alloc::vec![SearchRoot::default()]
+    }
+  }
+}
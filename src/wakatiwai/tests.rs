@@ -0,0 +1,167 @@
+use alloc::collections::VecDeque;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use uefi::{cstr16, CStr16, CString16, Status};
+
+use super::*;
+
+/// A file in a [`MockDirectory`].
+enum MockFile {
+  /// A regular file with the given contents.
+  File(Vec<u8>),
+  /// A subdirectory.
+  Directory,
+  /// A file which cannot be read.
+  Unreadable(Status)
+}
+
+/// An in-memory directory, standing in for the UEFI file protocol.
+struct MockDirectory {
+  /// The files in the directory, in the order they are listed.
+  files: Vec<(CString16, MockFile)>,
+  /// The entries yet to be read, or the error returned in their place.
+  entries: VecDeque<Result<DirectoryEntry, Status>>
+}
+
+impl MockDirectory {
+  fn new() -> MockDirectory {
+    MockDirectory {
+      files: Vec::new(),
+      entries: VecDeque::new()
+    }
+  }
+
+  fn with(mut self, name: &str, file: MockFile) -> MockDirectory {
+    let name = CString16::try_from(name).unwrap();
+    self.entries.push_back(
+      Ok(
+        DirectoryEntry {
+          name: name.clone(),
+          is_regular_file: !matches!(file, MockFile::Directory)
+        }
+      )
+    );
+    self.files.push((name, file));
+    self
+  }
+
+  fn with_file(self, name: &str, contents: &[u8]) -> MockDirectory {
+    self.with(name, MockFile::File(contents.to_vec()))
+  }
+
+  /// Fails the listing at this point.
+  fn with_read_error(mut self, status: Status) -> MockDirectory {
+    self.entries.push_back(Err(status));
+    self
+  }
+}
+
+impl DriverDirectory for MockDirectory {
+  fn next_entry(&mut self) -> Result<Option<DirectoryEntry>, Status> {
+    self.entries.pop_front().transpose()
+  }
+
+  fn read_file(&mut self, name: &CStr16) -> Result<Option<Vec<u8>>, Status> {
+    match self.files.iter().find(|(file_name, _)| &**file_name == name) {
+      Some((_, MockFile::File(contents))) => Ok(Some(contents.clone())),
+      Some((_, MockFile::Directory)) | None => Ok(None),
+      Some((_, MockFile::Unreadable(status))) => Err(*status)
+    }
+  }
+}
+
+fn driver_names(drivers: &[Driver]) -> Vec<String> {
+  drivers.iter().map(|driver| driver.name.to_string()).collect()
+}
+
+#[test]
+fn empty_directory_has_no_drivers() {
+  let drivers = get_driver_files_from_dir(&mut MockDirectory::new());
+  assert!(drivers.is_empty());
+}
+
+#[test]
+fn finds_drivers_and_ignores_other_entries() {
+  let mut directory = MockDirectory::new()
+    .with(".", MockFile::Directory)
+    .with("..", MockFile::Directory)
+    .with_file("fat.efi", b"MZ")
+    .with_file("fat.sig", &[0; 64])
+    .with_file("README.txt", b"")
+    .with("nested.efi", MockFile::Directory)
+    .with_file(".efi", b"MZ")
+    .with_file("ext4.EFI", b"MZ")
+    .with_file("linux.efi", b"MZ");
+
+  let drivers = get_driver_files_from_dir(&mut directory);
+  assert_eq!(driver_names(&drivers), ["fat.efi", "linux.efi"]);
+  assert!(drivers.iter().all(|driver| driver.state == DriverState::Discovered && driver.exec_handle.is_none()));
+}
+
+#[test]
+fn reads_manifests() {
+  let mut directory = MockDirectory::new()
+    .with_file("fat.efi", b"MZ")
+    .with_file("fat.toml", b"version = \"1.0.0\"\nfilesystems = [\"fat32\"]\n");
+
+  let drivers = get_driver_files_from_dir(&mut directory);
+  let manifest = drivers[0].manifest.as_ref().unwrap();
+  assert_eq!(manifest.version.as_deref(), Some("1.0.0"));
+  assert!(manifest.supports_filesystem("fat32"));
+}
+
+#[test]
+fn skips_drivers_with_bad_manifests() {
+  let mut directory = MockDirectory::new()
+    .with_file("garbage.efi", b"MZ")
+    .with_file("garbage.toml", &[0xFF, 0xFE, 0x00, 0x80])
+    .with_file("malformed.efi", b"MZ")
+    .with_file("malformed.toml", b"[[[version = ")
+    .with_file("unreadable.efi", b"MZ")
+    .with("unreadable.toml", MockFile::Unreadable(Status::DEVICE_ERROR))
+    .with_file("linux.efi", b"MZ")
+    .with("linux.toml", MockFile::Directory);
+
+  let drivers = get_driver_files_from_dir(&mut directory);
+  assert_eq!(driver_names(&drivers), ["linux.efi"]);
+  assert!(drivers[0].manifest.is_none());
+}
+
+#[test]
+fn keeps_drivers_listed_before_errors() {
+  let mut directory = MockDirectory::new()
+    .with_file("fat.efi", b"MZ")
+    .with_read_error(Status::VOLUME_CORRUPTED)
+    .with_file("linux.efi", b"MZ");
+
+  // Nothing after the error is read, as the position is unknown
+  let drivers = get_driver_files_from_dir(&mut directory);
+  assert_eq!(driver_names(&drivers), ["fat.efi"]);
+
+  let mut directory = MockDirectory::new().with_read_error(Status::DEVICE_ERROR);
+  assert!(get_driver_files_from_dir(&mut directory).is_empty());
+}
+
+#[test]
+fn sibling_paths() {
+  assert_eq!(sibling_path(cstr16!("fat.efi"), "toml"), Ok(CString16::from(cstr16!("fat.toml"))));
+  assert_eq!(
+    sibling_path(cstr16!("\\EFI\\a.efi\\b.efi"), "sig"),
+    Ok(CString16::from(cstr16!("\\EFI\\a.efi\\b.sig")))
+  );
+  assert_eq!(sibling_path(cstr16!("fat"), "toml"), Err(Status::INVALID_PARAMETER));
+}
+
+#[test]
+fn truncated_manifests_do_not_panic() {
+  let manifest = "version = \"1.0.0\"\nabi_version = 1\n[support]\nfilesystems = [\"fat32\", \"exfat\"]\n";
+
+  for end in 0..=manifest.len() {
+    let mut directory = MockDirectory::new()
+      .with_file("fat.efi", b"MZ")
+      .with_file("fat.toml", &manifest.as_bytes()[..end]);
+
+    assert!(get_driver_files_from_dir(&mut directory).len() <= 1);
+  }
+}
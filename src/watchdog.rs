@@ -0,0 +1,200 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use uefi::boot::MemoryType;
+use uefi::mem::memory_map::MemoryMapOwned;
+use uefi::runtime::{VariableAttributes, VariableVendor};
+use uefi::{cstr16, CStr16, CString16, Status};
+
+use crate::UdiveError;
+
+/// The watchdog code reported by the firmware when a driver's timeout
+/// expires ("WAKA"). Codes up to `0xFFFF` are reserved for the firmware.
+pub const WATCHDOG_CODE: u64 = 0x5741_4B41;
+
+/// The vendor of the UEFI variable the driver being invoked is recorded in,
+/// shared with [`crate::verify::KEY_VARIABLE_VENDOR`].
+pub const INVOCATION_VARIABLE_VENDOR: VariableVendor = crate::verify::KEY_VARIABLE_VENDOR;
+/// The name of the UEFI variable the driver being invoked is recorded in.
+pub const INVOCATION_VARIABLE_NAME: &CStr16 = cstr16!("WakatiwaiInvokedDriver");
+
+/// The timeout applied to invocations of drivers without their own, in
+/// seconds.
+static mut INVOCATION_TIMEOUT: Option<usize> = None;
+
+/// The driver last recorded in [`INVOCATION_VARIABLE_NAME`] by this loader,
+/// if the record has not been cleared since.
+static mut RECORDED_DRIVER: Option<String> = None;
+
+/// Sets the timeout applied to invocations of drivers without their own
+/// (see e.g. [`crate::BootDriver::set_timeout`]), in seconds.
+/// 
+/// While a driver with a timeout is running, the UEFI watchdog is armed, so
+/// that a hung driver resets the machine rather than freezing it. The driver
+/// is recorded in the non-volatile [`INVOCATION_VARIABLE_NAME`] variable
+/// beforehand, so that after the reset [`timed_out_driver`] reports it and
+/// the loader may skip it. The watchdog is disarmed as soon as the driver
+/// returns.
+/// 
+/// The record of a boot driver is cleared as soon as it returns. File system
+/// drivers are invoked for every file read, so to spare the flash the
+/// variable is stored in, their record is kept between invocations and only
+/// rewritten when another driver is invoked. A loader should clear it with
+/// [`clear_timed_out_driver`] once it is done reading files, and drivers
+/// starting an OS should exit boot services through [`exit_boot_services`].
+/// 
+/// The watchdog is left disarmed once the driver returns. This includes the
+/// five minute watchdog the firmware arms before starting the loader, so a
+/// loader relying on it should re-arm it with `uefi::boot::set_watchdog_timer`
+/// after invoking a driver with a timeout.
+/// 
+/// Passing `None` or `Some(0)` disables the timeout.
+pub fn set_invocation_timeout(seconds: Option<usize>) {
+  unsafe {
+    INVOCATION_TIMEOUT = seconds;
+  }
+}
+
+/// Returns the timeout applied to invocations of drivers without their own,
+/// in seconds.
+pub fn invocation_timeout() -> Option<usize> {
+  unsafe { INVOCATION_TIMEOUT }
+}
+
+/// Returns the driver which was running with a timeout when the machine
+/// was last reset.
+/// 
+/// This is usually a driver whose timeout expired, but may also be one
+/// which crashed the machine, or was running when it lost power. As the
+/// record of a file system driver is kept between invocations, it may also
+/// be the last file system driver invoked, if the record was not cleared.
+/// 
+/// # Returns
+/// 
+/// - `Some(String)` containing the name of the driver, until cleared with
+///   [`clear_timed_out_driver`].
+/// - `None` if no driver invoked with a timeout is recorded.
+pub fn timed_out_driver() -> Option<String> {
+  let (name, _) = uefi::runtime::get_variable_boxed(INVOCATION_VARIABLE_NAME, &INVOCATION_VARIABLE_VENDOR).ok()?;
+
+  Some(String::from_utf8_lossy(&name).into_owned())
+}
+
+/// Clears the record of the driver reported by [`timed_out_driver`], once
+/// the loader has fallen back from it, or is done invoking file system
+/// drivers.
+/// 
+/// # Returns
+/// 
+/// - `Ok(())` on success, or if no driver is recorded.
+/// - `Err(UdiveError::Firmware)` if the record could not be cleared.
+pub fn clear_timed_out_driver() -> Result<(), UdiveError> {
+  match uefi::runtime::delete_variable(INVOCATION_VARIABLE_NAME, &INVOCATION_VARIABLE_VENDOR) {
+    Ok(_) => {},
+    Err(err) if err.status() == Status::NOT_FOUND => {},
+    Err(err) => return Err(UdiveError::firmware(err.status()).with_message("invoked driver record could not be cleared"))
+  }
+  unsafe {
+    RECORDED_DRIVER = None;
+  }
+
+  Ok(())
+}
+
+/// Clears the record of the running driver and exits boot services.
+/// 
+/// The record is only accessible while boot services are running, so a
+/// driver starting an OS (or a loader doing so itself) should exit boot
+/// services through this rather than `uefi::boot::exit_boot_services`, lest
+/// the next boot report the driver as timed out. The firmware disarms the
+/// watchdog itself.
+/// 
+/// # Arguments
+/// 
+/// - `memory_type` (`MemoryType`) - The memory type of the returned memory
+///   map.
+/// 
+/// # Returns
+/// 
+/// - `MemoryMapOwned` - The final memory map.
+/// 
+/// # Safety
+/// 
+/// The same as `uefi::boot::exit_boot_services`.
+pub unsafe fn exit_boot_services(memory_type: MemoryType) -> MemoryMapOwned {
+  let _ = clear_timed_out_driver();
+
+  unsafe { uefi::boot::exit_boot_services(memory_type) }
+}
+
+/// The watchdog armed while a driver is running, which is disarmed when
+/// dropped.
+/// 
+/// The firmware cannot report the timeout of a watchdog armed before this
+/// one, so none is restored when it is dropped.
+pub(crate) struct Watchdog {
+  /// The data passed to the firmware, which reports it if the watchdog
+  /// expires.
+  _data: Vec<u16>,
+  /// Whether the record is cleared when the driver returns.
+  clear_on_return: bool
+}
+
+impl Watchdog {
+  /// Records a driver as running and arms the watchdog.
+  /// 
+  /// The driver is only written to [`INVOCATION_VARIABLE_NAME`] if it is not
+  /// already recorded, so repeated invocations of a file system driver do
+  /// not write to the flash each time.
+  /// 
+  /// # Arguments
+  /// 
+  /// - `driver` (`&str`) - The name of the driver.
+  /// - `seconds` (`usize`) - The timeout of the watchdog.
+  /// - `clear_on_return` (`bool`) - Whether to clear the record when the
+  ///   driver returns, which is only done for boot drivers.
+  /// 
+  /// # Returns
+  /// 
+  /// - `Ok(Watchdog)` on success.
+  /// - `Err(UdiveError::Firmware)` if the driver could not be recorded, or
+  ///   the watchdog could not be armed.
+  pub(crate) fn arm(driver: &str, seconds: usize, clear_on_return: bool) -> Result<Watchdog, UdiveError> {
+    let recorded = unsafe { RECORDED_DRIVER.as_deref() == Some(driver) };
+    if !recorded {
+      uefi::runtime::set_variable(
+        INVOCATION_VARIABLE_NAME,
+        &INVOCATION_VARIABLE_VENDOR,
+        VariableAttributes::NON_VOLATILE | VariableAttributes::BOOTSERVICE_ACCESS,
+        driver.as_bytes()
+      ).map_err(|err| UdiveError::firmware(err.status()).with_message("invoked driver could not be recorded"))?;
+      unsafe {
+        RECORDED_DRIVER = Some(String::from(driver));
+      }
+    }
+
+    let mut data = CString16::try_from(driver)
+      .map(|name| name.to_u16_slice_with_nul().to_vec())
+      .unwrap_or_else(|_| alloc::vec![0]);
+    if let Err(err) = uefi::boot::set_watchdog_timer(seconds, WATCHDOG_CODE, Some(&mut data)) {
+      let _ = clear_timed_out_driver();
+      return Err(UdiveError::firmware(err.status()).with_message("watchdog could not be armed"));
+    }
+
+    Ok(
+      Watchdog {
+        _data: data,
+        clear_on_return
+      }
+    )
+  }
+}
+
+impl Drop for Watchdog {
+  fn drop(&mut self) {
+    let _ = uefi::boot::set_watchdog_timer(0, WATCHDOG_CODE, None);
+    if self.clear_on_return {
+      let _ = clear_timed_out_driver();
+    }
+  }
+}